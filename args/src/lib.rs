@@ -0,0 +1,114 @@
+//! Parses the values of the command line options shared by the examples.
+//!
+//! The value of an option is the argument following it, `None` when the
+//! command line ends first. The errors name the option and the value, they are
+//! printed along with the usage message.
+
+use std::str::FromStr;
+
+/// Parses the value of the option `name` with its `FromStr` implementation.
+pub fn parse_value<T: FromStr>(name: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for {}", name))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for {}", value, name))
+}
+
+/// Parses a `WIDTHxHEIGHT` size, neither of them can be 0.
+pub fn parse_size(name: &str, value: Option<String>) -> Result<(u32, u32), String> {
+    let value = value.ok_or_else(|| format!("missing value for {}", name))?;
+    let invalid = || format!("invalid size '{}' for {}, expected WIDTHxHEIGHT", value, name);
+
+    let mut parts = value.splitn(2, 'x');
+    let width = parts.next().and_then(|w| w.parse().ok()).ok_or_else(&invalid)?;
+    let height = parts.next().and_then(|h| h.parse().ok()).ok_or_else(&invalid)?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+
+    Ok((width, height))
+}
+
+/// Parses exactly `count` comma separated numbers.
+pub fn parse_floats(name: &str, value: Option<String>, count: usize) -> Result<Vec<f32>, String> {
+    let value = value.ok_or_else(|| format!("missing value for {}", name))?;
+
+    value
+        .split(',')
+        .map(|v| v.trim().parse())
+        .collect::<Result<Vec<f32>, _>>()
+        .ok()
+        .filter(|floats| floats.len() == count)
+        .ok_or_else(|| {
+            format!(
+                "invalid value '{}' for {}, expected {} comma separated numbers",
+                value, name, count
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(text: &str) -> Option<String> {
+        Some(text.to_owned())
+    }
+
+    #[test]
+    fn values_are_parsed() {
+        assert_eq!(parse_value::<u32>("--tile", value("256")), Ok(256));
+        assert_eq!(parse_value::<f32>("--zoom", value("2.5")), Ok(2.5));
+        assert_eq!(parse_value::<String>("--gif", value("zoom.gif")), Ok("zoom.gif".to_owned()));
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert_eq!(parse_value::<u32>("--tile", None), Err("missing value for --tile".to_owned()));
+        assert_eq!(
+            parse_value::<u32>("--tile", value("-1")),
+            Err("invalid value '-1' for --tile".to_owned())
+        );
+        assert_eq!(
+            parse_value::<f32>("--zoom", value("")),
+            Err("invalid value '' for --zoom".to_owned())
+        );
+    }
+
+    #[test]
+    fn sizes_are_parsed() {
+        assert_eq!(parse_size("--size", value("640x480")), Ok((640, 480)));
+        assert_eq!(parse_size("--size", value("1x1")), Ok((1, 1)));
+    }
+
+    #[test]
+    fn invalid_sizes_are_rejected() {
+        assert_eq!(parse_size("--size", None), Err("missing value for --size".to_owned()));
+        for size in &["640", "640x", "x480", "640x0", "0x480", "640x480x2", "640*480", "-640x480", "640 x 480"] {
+            assert_eq!(
+                parse_size("--size", value(size)),
+                Err(format!("invalid size '{}' for --size, expected WIDTHxHEIGHT", size))
+            );
+        }
+    }
+
+    #[test]
+    fn floats_are_parsed() {
+        assert_eq!(parse_floats("--center", value("-1,0.5"), 2), Ok(vec![-1.0, 0.5]));
+        assert_eq!(parse_floats("--camera", value(" 0, 1.2 ,-2.6"), 3), Ok(vec![0.0, 1.2, -2.6]));
+    }
+
+    #[test]
+    fn invalid_floats_are_rejected() {
+        assert_eq!(parse_floats("--center", None, 2), Err("missing value for --center".to_owned()));
+        for floats in &["1", "1,2,3", "1,", "1;2", "a,2", ""] {
+            assert_eq!(
+                parse_floats("--center", value(floats), 2),
+                Err(format!(
+                    "invalid value '{}' for --center, expected 2 comma separated numbers",
+                    floats
+                ))
+            );
+        }
+    }
+}
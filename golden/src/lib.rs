@@ -0,0 +1,347 @@
+//! Compares rendered images with reference ("golden") images, to catch
+//! rendering regressions.
+//!
+//! The examples write their output next to an `image.png` committed in the
+//! repository, `check` compares a rendered buffer with such a file and saves
+//! an image highlighting the differences when they are too far apart. It can
+//! be called from a test as well as from the command line of the examples:
+//!
+//! ```no_run
+//! # extern crate golden;
+//! # extern crate image;
+//! # let rendered = image::RgbaImage::new(1, 1);
+//! golden::check(&rendered, "image.png", &golden::Tolerance::default()).unwrap();
+//! ```
+
+extern crate image;
+
+use image::{Rgba, RgbaImage};
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// How far a rendered image may be from its reference.
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerance {
+    /// Lowest peak signal to noise ratio, in decibels.
+    pub min_psnr: f64,
+    /// Lowest structural similarity, 1 for identical images.
+    pub min_ssim: f64,
+}
+
+impl Default for Tolerance {
+    /// Allows the small rounding differences between devices, but not a
+    /// missing or moved shape.
+    fn default() -> Tolerance {
+        Tolerance {
+            min_psnr: 40.0,
+            min_ssim: 0.98,
+        }
+    }
+}
+
+/// Measures of the differences between two images of the same size.
+#[derive(Clone, Copy, Debug)]
+pub struct Comparison {
+    /// Largest difference of a channel.
+    pub max_error: u8,
+    /// Pixels with at least one channel differing.
+    pub differing_pixels: u64,
+    /// Peak signal to noise ratio of the colours, in decibels, infinite for
+    /// identical images.
+    pub psnr: f64,
+    /// Mean structural similarity of the luma, over 11 x 11 Gaussian windows.
+    pub ssim: f64,
+}
+
+impl Comparison {
+    pub fn passes(&self, tolerance: &Tolerance) -> bool {
+        self.psnr >= tolerance.min_psnr && self.ssim >= tolerance.min_ssim
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "max error {}, {} pixels differing, PSNR {:.2} dB, SSIM {:.4}",
+            self.max_error, self.differing_pixels, self.psnr, self.ssim
+        )
+    }
+}
+
+/// Compares `rendered` with the reference image at `reference`. When they are
+/// too far apart, an image of their differences is written next to the
+/// reference (`image.png` gives `image-diff.png`) and the error says so.
+pub fn check<P: AsRef<Path>>(rendered: &RgbaImage, reference: P, tolerance: &Tolerance) -> Result<Comparison, String> {
+    let reference = reference.as_ref();
+    let expected = image::open(reference)
+        .map_err(|err| format!("failed to load {}: {}", reference.display(), err))?
+        .to_rgba();
+
+    let comparison = compare(rendered, &expected)?;
+    if comparison.passes(tolerance) {
+        return Ok(comparison);
+    }
+
+    let diff_path = diff_path(reference);
+    diff_image(rendered, &expected)
+        .save(&diff_path)
+        .map_err(|err| format!("failed to write {}: {}", diff_path.display(), err))?;
+
+    Err(format!(
+        "the image differs from {}: {}, see {}",
+        reference.display(),
+        comparison,
+        diff_path.display()
+    ))
+}
+
+/// Computes the differences between `rendered` and `reference`.
+pub fn compare(rendered: &RgbaImage, reference: &RgbaImage) -> Result<Comparison, String> {
+    if rendered.dimensions() != reference.dimensions() {
+        return Err(format!(
+            "the image is {}x{} but the reference is {}x{}",
+            rendered.width(),
+            rendered.height(),
+            reference.width(),
+            reference.height()
+        ));
+    }
+
+    let mut max_error = 0;
+    let mut differing_pixels = 0;
+    let mut squared_error = 0.0;
+    for (a, b) in rendered.pixels().zip(reference.pixels()) {
+        let error = pixel_error(a, b);
+        max_error = max_error.max(error);
+        if error > 0 {
+            differing_pixels += 1;
+        }
+
+        // the alpha channel is left out of the PSNR, it is opaque everywhere
+        for channel in 0 .. 3 {
+            let difference = a[channel] as f64 - b[channel] as f64;
+            squared_error += difference * difference;
+        }
+    }
+
+    let samples = rendered.width() as f64 * rendered.height() as f64 * 3.0;
+    let mse = squared_error / samples;
+    let psnr = if mse == 0.0 {
+        ::std::f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    };
+
+    Ok(Comparison {
+        max_error,
+        differing_pixels,
+        psnr,
+        ssim: ssim(rendered, reference),
+    })
+}
+
+/// An image of the differences: the reference dimmed in grey, with the pixels
+/// that differ in red, brighter as they differ more.
+pub fn diff_image(rendered: &RgbaImage, reference: &RgbaImage) -> RgbaImage {
+    RgbaImage::from_fn(reference.width(), reference.height(), |x, y| {
+        let expected = reference.get_pixel(x, y);
+        let error = pixel_error(rendered.get_pixel(x, y), expected);
+
+        if error == 0 {
+            let grey = (luma(expected) / 4.0) as u8;
+            Rgba([grey, grey, grey, 255])
+        } else {
+            let red = (64 + error as u32 * 8).min(255) as u8;
+            Rgba([red, 0, 0, 255])
+        }
+    })
+}
+
+/// Where the diff image of `reference` is written.
+pub fn diff_path(reference: &Path) -> PathBuf {
+    let stem = reference
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "image".to_owned());
+    reference.with_file_name(format!("{}-diff.png", stem))
+}
+
+fn pixel_error(a: &Rgba<u8>, b: &Rgba<u8>) -> u8 {
+    (0 .. 4)
+        .map(|channel| if a[channel] > b[channel] { a[channel] - b[channel] } else { b[channel] - a[channel] })
+        .max()
+        .unwrap()
+}
+
+fn luma(pixel: &Rgba<u8>) -> f64 {
+    0.299 * pixel[0] as f64 + 0.587 * pixel[1] as f64 + 0.114 * pixel[2] as f64
+}
+
+/// Mean structural similarity of the luma of two images, with the usual
+/// constants of Wang et al.
+fn ssim(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    let (width, height) = (a.width() as usize, a.height() as usize);
+    let x = a.pixels().map(luma).collect::<Vec<_>>();
+    let y = b.pixels().map(luma).collect::<Vec<_>>();
+    let product = |u: &[f64], v: &[f64]| u.iter().zip(v).map(|(u, v)| u * v).collect::<Vec<_>>();
+
+    let mean_x = blur(&x, width, height);
+    let mean_y = blur(&y, width, height);
+    let mean_xx = blur(&product(&x, &x), width, height);
+    let mean_yy = blur(&product(&y, &y), width, height);
+    let mean_xy = blur(&product(&x, &y), width, height);
+
+    let total: f64 = (0 .. x.len())
+        .map(|i| {
+            let (mx, my) = (mean_x[i], mean_y[i]);
+            let variance_x = mean_xx[i] - mx * mx;
+            let variance_y = mean_yy[i] - my * my;
+            let covariance = mean_xy[i] - mx * my;
+
+            ((2.0 * mx * my + C1) * (2.0 * covariance + C2))
+                / ((mx * mx + my * my + C1) * (variance_x + variance_y + C2))
+        }).sum();
+
+    total / x.len() as f64
+}
+
+/// Gaussian blur of radius 5 and standard deviation 1.5, the pixels outside
+/// of the image repeat the ones on its edges.
+fn blur(values: &[f64], width: usize, height: usize) -> Vec<f64> {
+    const RADIUS: i64 = 5;
+    let weights = (-RADIUS ..= RADIUS)
+        .map(|i| (-(i * i) as f64 / (2.0 * 1.5 * 1.5)).exp())
+        .collect::<Vec<_>>();
+    let sum: f64 = weights.iter().sum();
+
+    let pass = |values: &[f64], horizontal: bool| {
+        let mut blurred = vec![0.0; values.len()];
+        for y in 0 .. height as i64 {
+            for x in 0 .. width as i64 {
+                let mut total = 0.0;
+                for (weight, offset) in weights.iter().zip(-RADIUS ..= RADIUS) {
+                    let (sx, sy) = if horizontal {
+                        ((x + offset).max(0).min(width as i64 - 1), y)
+                    } else {
+                        (x, (y + offset).max(0).min(height as i64 - 1))
+                    };
+                    total += weight * values[sy as usize * width + sx as usize];
+                }
+                blurred[y as usize * width + x as usize] = total / sum;
+            }
+        }
+        blurred
+    };
+
+    pass(&pass(values, true), false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    // Diagonal stripes with a gradient, so that moving them is noticed.
+    fn pattern(offset: u32) -> RgbaImage {
+        RgbaImage::from_fn(64, 48, |x, y| {
+            let stripe = if (x + offset + y) / 6 % 2 == 1 { 20 } else { 230 };
+            Rgba([stripe, (y * 5) as u8, (x * 3) as u8, 255])
+        })
+    }
+
+    #[test]
+    fn identical_images() {
+        let image = pattern(0);
+        let comparison = compare(&image, &image).unwrap();
+        assert_eq!(comparison.max_error, 0);
+        assert_eq!(comparison.differing_pixels, 0);
+        assert!(comparison.psnr.is_infinite());
+        assert!((comparison.ssim - 1.0).abs() < 1e-12);
+        assert!(comparison.passes(&Tolerance::default()));
+    }
+
+    #[test]
+    fn small_differences_pass() {
+        let image = pattern(0);
+        let mut rounded = image.clone();
+        for pixel in rounded.pixels_mut().step_by(3) {
+            pixel[1] = pixel[1].saturating_add(1);
+        }
+
+        let comparison = compare(&rounded, &image).unwrap();
+        assert_eq!(comparison.max_error, 1);
+        // every third pixel, starting from the first
+        assert_eq!(comparison.differing_pixels, 1024);
+        assert!(comparison.psnr.is_finite());
+        assert!(comparison.passes(&Tolerance::default()));
+    }
+
+    #[test]
+    fn shifted_image_fails() {
+        let comparison = compare(&pattern(3), &pattern(0)).unwrap();
+        assert!(comparison.max_error > 100);
+        assert!(comparison.ssim < Tolerance::default().min_ssim);
+        assert!(!comparison.passes(&Tolerance::default()));
+    }
+
+    #[test]
+    fn mismatched_sizes() {
+        let small = RgbaImage::new(4, 3);
+        let large = RgbaImage::new(3, 4);
+        assert!(compare(&small, &large).is_err());
+    }
+
+    #[test]
+    fn ssim_is_symmetric() {
+        let (a, b) = (pattern(1), pattern(0));
+        assert!((ssim(&a, &b) - ssim(&b, &a)).abs() < 1e-12);
+        assert!(ssim(&a, &b) < 1.0);
+    }
+
+    #[test]
+    fn diff_image_marks_the_differences() {
+        let reference = pattern(0);
+        let mut rendered = reference.clone();
+        rendered.put_pixel(5, 7, Rgba([0, 0, 0, 255]));
+
+        let diff = diff_image(&rendered, &reference);
+        assert_eq!(diff.dimensions(), reference.dimensions());
+        let marked = diff.get_pixel(5, 7);
+        assert!(marked[0] > 0 && marked[1] == 0 && marked[2] == 0);
+        let unchanged = diff.get_pixel(6, 7);
+        assert!(unchanged[0] == unchanged[1] && unchanged[1] == unchanged[2]);
+    }
+
+    #[test]
+    fn diff_path_is_next_to_the_reference() {
+        assert_eq!(diff_path(Path::new("image.png")), Path::new("image-diff.png"));
+        assert_eq!(
+            diff_path(Path::new("tests/golden/triangle.png")),
+            Path::new("tests/golden/triangle-diff.png")
+        );
+        assert_eq!(diff_path(Path::new("reference")), Path::new("reference-diff.png"));
+    }
+
+    #[test]
+    fn check_writes_the_diff_on_failure() {
+        let directory = env::temp_dir().join(format!("golden-check-{}", ::std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let reference = directory.join("image.png");
+        pattern(0).save(&reference).unwrap();
+
+        assert!(check(&pattern(0), &reference, &Tolerance::default()).is_ok());
+        assert!(!diff_path(&reference).exists());
+
+        assert!(check(&pattern(3), &reference, &Tolerance::default()).is_err());
+        assert!(diff_path(&reference).exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
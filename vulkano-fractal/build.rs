@@ -0,0 +1,83 @@
+//! Assembles the escape time shaders, the compute ones being compiled once
+//! per format of the images they write the colours to.
+//!
+//! vulkano-shader-derive only takes the source of a shader as a single string
+//! literal, so the sources in `glsl/` are turned into shader modules written
+//! to `$OUT_DIR/shaders.rs`, which `src/shaders.rs` includes. Their
+//! `#include "file"` lines are replaced by the file of `glsl/`, which is how
+//! they share the colours of the escape time.
+
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+// The storage image formats the colours are written to, with the suffix of
+// the module of each one, the 8 bit one keeping the plain name.
+const FORMATS: [(&str, &str); 3] = [("rgba8", ""), ("rgba16", "_rgba16"), ("rgba32f", "_rgba32f")];
+
+// Module, type and source of the shaders compiled for every format.
+const FORMAT_SHADERS: [(&str, &str, &str); 2] = [("cs", "compute", "cs.comp"), ("refine", "compute", "refine.comp")];
+
+// Module, type and source of the shaders compiled once.
+const SHADERS: [(&str, &str, &str); 1] = [("fs", "fragment", "fs.frag")];
+
+fn main() {
+    let mut modules = String::new();
+
+    for &(name, ty, file) in &FORMAT_SHADERS {
+        let source = load(file);
+
+        for &(format, suffix) in &FORMATS {
+            // the format is defined right after the version, which has to come first
+            let version_end = source.find('\n').expect("the shader has no #version line") + 1;
+            let source = format!(
+                "{}#define COLOR_FORMAT {}\n{}",
+                &source[.. version_end],
+                format,
+                &source[version_end ..]
+            );
+            modules.push_str(&module(&format!("{}{}", name, suffix), ty, &source));
+        }
+    }
+
+    for &(name, ty, file) in &SHADERS {
+        modules.push_str(&module(name, ty, &load(file)));
+    }
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    File::create(Path::new(&out_dir).join("shaders.rs"))
+        .and_then(|mut file| file.write_all(modules.as_bytes()))
+        .expect("failed to write the shaders");
+}
+
+/// Reads `file` from `glsl/` with its includes expanded.
+fn load(file: &str) -> String {
+    let path = Path::new("glsl").join(file);
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    let mut source = String::new();
+    File::open(&path)
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .unwrap_or_else(|err| panic!("failed to read {}: {}", path.display(), err));
+
+    let mut expanded = String::new();
+    for line in source.lines() {
+        let directive = line.trim();
+        if directive.starts_with("#include") {
+            let included = directive["#include".len() ..].trim().trim_matches('"');
+            expanded.push_str(&load(included));
+        } else {
+            expanded.push_str(line);
+            expanded.push('\n');
+        }
+    }
+    expanded
+}
+
+fn module(name: &str, ty: &str, source: &str) -> String {
+    format!(
+        "pub mod {} {{\n    #[derive(VulkanoShader)]\n    #[ty = \"{}\"]\n    #[src = {:?}]\n    struct Dummy;\n}}\n\n",
+        name, ty, source
+    )
+}
@@ -0,0 +1,39 @@
+// The view and the escape time colouring of the Mandelbrot set, the same for
+// every shader drawing it so that their images match. The push constants must
+// have image_size, center, scale, rotation, palette_offset and max_iterations.
+
+// Cheap integer hash, good enough to decorrelate the jitter of neighbouring samples.
+float hash(uvec3 v) {
+    uint h = v.x * 1597334677u ^ v.y * 3812015801u ^ v.z * 2912667907u;
+    h ^= h >> 16;
+    h *= 2246822519u;
+    h ^= h >> 13;
+    return float(h) / 4294967295.0;
+}
+
+// Maps a position in the final image (in pixels) to the complex plane.
+vec2 to_complex(vec2 position) {
+    vec2 p = (position - vec2(pc.image_size) * 0.5) / (float(pc.image_size.y) * 0.5);
+    float s = sin(pc.rotation);
+    float c = cos(pc.rotation);
+    return pc.center + mat2(c, s, -s, c) * p * pc.scale;
+}
+
+// Colour of a point that escaped after n iterations with |z| = l, or of a
+// point of the set when n is the maximum.
+vec4 escape_color(uint n, float l) {
+    float i = float(n) / float(pc.max_iterations);
+    if (i < 1.0) {
+        i = fract(i + pc.palette_offset);
+    }
+
+    vec4 to_write = vec4(0.0, 0.0, 0.0, 0.0);
+    if (i < 0.2) {
+        to_write = vec4(i, i / l, i, 1.0);
+    } else if (i < 0.7) {
+        to_write = vec4(vec2(i), i / l, 1.0);
+    } else {
+        to_write = vec4(i/ l, vec2(i), 1.0);
+    }
+    return to_write;
+}
@@ -0,0 +1,69 @@
+#version 450
+
+// Same escape time colouring of the Mandelbrot set as the compute shader,
+// evaluated for every fragment of a triangle covering the whole image.
+
+layout(location = 0) out vec4 f_color;
+
+layout(push_constant) uniform PushConstantData {
+    // size of the image
+    uvec2 image_size;
+    // number of samples per pixel along each axis (samples x samples in total)
+    uint samples;
+    // when not zero each sample is randomly moved inside its grid cell
+    uint jitter;
+    // point of the complex plane at the center of the image
+    vec2 center;
+    // half of the height of the visible region of the complex plane
+    float scale;
+    // rotation of the view around its center, in radians
+    float rotation;
+    // shifts the colours assigned to the escape time
+    float palette_offset;
+    // number of iterations after which a point is considered part of the set
+    uint max_iterations;
+} pc;
+
+#include "escape.glsl"
+
+vec4 mandelbrot(vec2 c) {
+    vec2 z = vec2(0.0, 0.0);
+    uint n;
+    float l;
+    for (n = 0; n < pc.max_iterations; n++) {
+        z = vec2(
+            z.x * z.x - z.y * z.y + c.x,
+            z.y * z.x + z.x * z.y + c.y
+        );
+
+        l = length(z);
+        if (l > 4.0) {
+            break;
+        }
+    }
+
+    return escape_color(n, l);
+}
+
+void main() {
+    // the fragments are at the center of the pixels
+    uvec2 pixel = uvec2(gl_FragCoord.xy);
+
+    vec4 color = vec4(0.0);
+    for (uint sy = 0; sy < pc.samples; sy++) {
+        for (uint sx = 0; sx < pc.samples; sx++) {
+            vec2 offset = vec2(0.5);
+            if (pc.jitter != 0) {
+                uint index = sy * pc.samples + sx;
+                offset = vec2(
+                    hash(uvec3(pixel, index * 2)),
+                    hash(uvec3(pixel, index * 2 + 1))
+                );
+            }
+            vec2 sample_pos = (vec2(sx, sy) + offset) / float(pc.samples);
+            color += mandelbrot(to_complex(pixel + sample_pos));
+        }
+    }
+
+    f_color = color / float(pc.samples * pc.samples);
+}
@@ -0,0 +1,287 @@
+use gif;
+use gif::SetParameter;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter};
+use std::sync::mpsc;
+use std::thread;
+
+use options::Options;
+use png;
+use render;
+use render::{Params, TileRenderer, View};
+
+/// The view at a given point in time, the views in between two keyframes are
+/// interpolated.
+pub struct Keyframe {
+    pub time: f32,
+    pub view: View,
+}
+
+/// Reads the keyframes from a text file.
+///
+/// Every line describes a keyframe as `time center_x center_y zoom rotation
+/// palette_offset`, empty lines and lines starting with `#` are ignored.
+pub fn load_keyframes(path: &str) -> Result<Vec<Keyframe>, String> {
+    let file = File::open(path).map_err(|err| format!("failed to open {}: {}", path, err))?;
+
+    let mut keyframes: Vec<Keyframe> = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|err| format!("failed to read {}: {}", path, err))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let values = line
+            .split_whitespace()
+            .map(|value| value.parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("{}:{}: invalid number", path, number + 1))?;
+        if values.len() != 6 {
+            return Err(format!("{}:{}: expected 6 values", path, number + 1));
+        }
+        if values[3] <= 0.0 {
+            return Err(format!("{}:{}: zoom must be positive", path, number + 1));
+        }
+        if keyframes.last().map_or(false, |last| last.time >= values[0]) {
+            return Err(format!("{}:{}: keyframes must be sorted by time", path, number + 1));
+        }
+
+        keyframes.push(Keyframe {
+            time: values[0],
+            view: View {
+                center: [values[1], values[2]],
+                zoom: values[3],
+                rotation: values[4],
+                palette_offset: values[5],
+            },
+        });
+    }
+
+    if keyframes.is_empty() {
+        return Err(format!("{}: no keyframes found", path));
+    }
+
+    Ok(keyframes)
+}
+
+/// Interpolates the view at `time`.
+pub fn view_at(keyframes: &[Keyframe], time: f32) -> View {
+    let next = match keyframes.iter().position(|k| k.time > time) {
+        Some(0) => return keyframes[0].view,
+        Some(next) => next,
+        None => return keyframes[keyframes.len() - 1].view,
+    };
+
+    let a = &keyframes[next - 1];
+    let b = &keyframes[next];
+    let t = (time - a.time) / (b.time - a.time);
+
+    // The zoom is interpolated geometrically, so that it proceeds at a
+    // constant speed.
+    let zoom = a.view.zoom * (b.view.zoom / a.view.zoom).powf(t);
+
+    // Moving the center linearly while zooming exponentially makes the target
+    // drift out of the view, so we move it proportionally to the change of scale.
+    let center_t = if (b.view.zoom - a.view.zoom).abs() > ::std::f32::EPSILON {
+        (1.0 / zoom - 1.0 / a.view.zoom) / (1.0 / b.view.zoom - 1.0 / a.view.zoom)
+    } else {
+        t
+    };
+
+    View {
+        center: [
+            lerp(a.view.center[0], b.view.center[0], center_t),
+            lerp(a.view.center[1], b.view.center[1], center_t),
+        ],
+        zoom,
+        rotation: lerp(a.view.rotation, b.view.rotation, t),
+        palette_offset: lerp(a.view.palette_offset, b.view.palette_offset, t),
+    }
+}
+
+/// Renders every frame of the animation described by `keyframes`.
+///
+/// The frames are encoded on a worker thread, so that the GPU can already
+/// work on the next frame in the meantime.
+pub fn render_animation(renderer: &TileRenderer, options: &Options, keyframes: &[Keyframe]) {
+    let first = keyframes[0].time;
+    let duration = keyframes[keyframes.len() - 1].time - first;
+    let frames = (duration * options.fps as f32).round() as usize + 1;
+
+    let (width, height) = (options.width, options.height);
+    let gif_path = options.gif.clone();
+    let fps = options.fps;
+
+    // Keep at most two frames in flight, so that a slow encoder doesn't make
+    // us accumulate frames in memory.
+    let (sender, receiver) = mpsc::sync_channel::<(String, Vec<u8>)>(2);
+
+    let worker = thread::spawn(move || {
+        let mut gif_encoder = gif_path.map(|path| {
+            let file = File::create(&path).expect("failed to create the gif file");
+            let mut encoder = gif::Encoder::new(BufWriter::new(file), width as u16, height as u16, &[])
+                .expect("failed to write the gif header");
+            encoder.set(gif::Repeat::Infinite).expect("failed to write the gif header");
+            encoder
+        });
+
+        for (index, (path, mut pixels)) in receiver.into_iter().enumerate() {
+            png::write_png(&path, width, height, &pixels).expect("failed to write the frame");
+
+            if let Some(ref mut encoder) = gif_encoder {
+                let mut frame = gif::Frame::from_rgba(width as u16, height as u16, &mut pixels);
+                frame.delay = frame_delay(index, fps);
+                encoder.write_frame(&frame).expect("failed to write the gif frame");
+            }
+
+            println!("Written {}", path);
+        }
+    });
+
+    for index in 0 .. frames {
+        let time = first + index as f32 / options.fps as f32;
+        let mut params = Params {
+            view: view_at(keyframes, time),
+            ..options.params()
+        };
+        if options.auto_iterations {
+            params.iterations = render::auto_iterations(params.view.zoom);
+        }
+
+        let pixels = renderer.render_image(&params, width, height);
+        if sender.send((options.numbered_output(index), pixels)).is_err() {
+            // the worker died, joining it below reports why
+            break;
+        }
+    }
+
+    drop(sender);
+    worker.join().expect("failed to encode the frames");
+}
+
+/// Delay of the `index`-th frame, in hundredths of a second. The delays of
+/// GIF frames are whole hundredths, the rounding error is carried to the next
+/// frames so that the animation keeps its speed.
+fn frame_delay(index: usize, fps: u32) -> u16 {
+    // start of a frame, rounded to the nearest hundredth
+    let start = |frame: usize| (frame as u64 * 200 + u64::from(fps)) / (2 * u64::from(fps));
+    (start(index + 1) - start(index)) as u16
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    // Writes `text` to a file of the temporary directory and loads it.
+    fn load_text(name: &str, text: &str) -> Result<Vec<Keyframe>, String> {
+        let path = env::temp_dir().join(format!("vulkano-fractal-keyframes-{}.txt", name));
+        fs::write(&path, text).unwrap();
+        let keyframes = load_keyframes(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        keyframes
+    }
+
+    fn keyframe(time: f32, center: [f32; 2], zoom: f32) -> Keyframe {
+        Keyframe {
+            time,
+            view: View {
+                center,
+                zoom,
+                rotation: 0.0,
+                palette_offset: 0.0,
+            },
+        }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn keyframes_are_read() {
+        let text = "# time x y zoom rotation palette\n\n0 -0.5 0 1 0 0\n  2.5 -1.25 0.1 40 90 0.5\n";
+        let keyframes = load_text("read", text).unwrap();
+
+        assert_eq!(keyframes.len(), 2);
+        assert_eq!(keyframes[0].time, 0.0);
+        assert_eq!(keyframes[1].time, 2.5);
+        assert_eq!(
+            keyframes[1].view,
+            View {
+                center: [-1.25, 0.1],
+                zoom: 40.0,
+                rotation: 90.0,
+                palette_offset: 0.5,
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_keyframes_are_rejected() {
+        let error = |name: &str, text: &str| load_text(name, text).err().unwrap();
+
+        assert!(error("number", "0 0 0 1 0 x\n").ends_with(":1: invalid number"));
+        assert!(error("count", "0 0 0 1 0\n").ends_with(":1: expected 6 values"));
+        assert!(error("zoom", "0 0 0 1 0 0\n1 0 0 0 0 0\n").ends_with(":2: zoom must be positive"));
+        assert!(error("order", "1 0 0 1 0 0\n1 0 0 2 0 0\n").ends_with(":2: keyframes must be sorted by time"));
+        assert!(error("empty", "# nothing\n").ends_with(": no keyframes found"));
+        assert!(load_keyframes("/nonexistent/keyframes.txt").is_err());
+    }
+
+    #[test]
+    fn views_are_held_outside_of_the_keyframes() {
+        let keyframes = [keyframe(1.0, [0.0, 0.0], 1.0), keyframe(2.0, [1.0, 1.0], 4.0)];
+        assert_eq!(view_at(&keyframes, 0.0), keyframes[0].view);
+        assert_eq!(view_at(&keyframes, 1.0), keyframes[0].view);
+        assert_eq!(view_at(&keyframes, 2.0), keyframes[1].view);
+        assert_eq!(view_at(&keyframes, 3.0), keyframes[1].view);
+        assert_eq!(view_at(&keyframes[.. 1], 5.0), keyframes[0].view);
+    }
+
+    #[test]
+    fn zoom_is_interpolated_geometrically() {
+        let keyframes = [keyframe(0.0, [0.0, 0.0], 1.0), keyframe(2.0, [3.0, -3.0], 4.0)];
+        let view = view_at(&keyframes, 1.0);
+        assert_close(view.zoom, 2.0);
+
+        // the scale, 1 / zoom, went 2/3 of the way from 1 to 1/4
+        assert_close(view.center[0], 2.0);
+        assert_close(view.center[1], -2.0);
+    }
+
+    #[test]
+    fn center_is_linear_at_a_constant_zoom() {
+        let mut keyframes = [keyframe(0.0, [0.0, 1.0], 2.0), keyframe(4.0, [2.0, -1.0], 2.0)];
+        keyframes[1].view.rotation = 180.0;
+        keyframes[1].view.palette_offset = 1.0;
+
+        let view = view_at(&keyframes, 1.0);
+        assert_close(view.zoom, 2.0);
+        assert_close(view.center[0], 0.5);
+        assert_close(view.center[1], 0.5);
+        assert_close(view.rotation, 45.0);
+        assert_close(view.palette_offset, 0.25);
+    }
+
+    #[test]
+    fn frame_delays_keep_the_frame_rate() {
+        for fps in 1 .. 51 {
+            let delays = (0 .. fps as usize).map(|index| frame_delay(index, fps)).collect::<Vec<_>>();
+            assert_eq!(delays.iter().map(|&delay| u32::from(delay)).sum::<u32>(), 100, "{} fps", fps);
+            assert!(delays.iter().all(|&delay| delay >= 2), "{} fps", fps);
+        }
+
+        assert_eq!((0 .. 6).map(|index| frame_delay(index, 30)).collect::<Vec<_>>(), vec![3, 4, 3, 3, 4, 3]);
+        assert_eq!((0 .. 4).map(|index| frame_delay(index, 40)).collect::<Vec<_>>(), vec![3, 2, 3, 2]);
+        assert_eq!(frame_delay(7, 50), 2);
+    }
+}
@@ -0,0 +1,103 @@
+use vulkano::device::Device;
+use vulkano::device::Queue;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use fragment::FragmentRenderer;
+use options::Options;
+use output::OutputFormat;
+use render::TileRenderer;
+
+/// Renders the view asked on the command line `options.bench` times with the
+/// compute shader and as many times with the fragment shader, then reports
+/// the throughput of both and how much their images differ.
+///
+/// Both include the copy of the image to a buffer the CPU can read, which is
+/// the same for both.
+pub fn run(device: Arc<Device>, queue: Arc<Queue>, options: &Options) {
+    let (width, height) = (options.width, options.height);
+
+    let max_size = device.physical_device().limits().max_image_dimension_2d();
+    if width > max_size || height > max_size {
+        eprintln!("the benchmark can't use images bigger than {}x{} on this device", max_size, max_size);
+        ::std::process::exit(1);
+    }
+
+    // a single tile of the size of the image, like the framebuffer, so that
+    // both shaders evaluate the same number of pixels
+    let compute = TileRenderer::with_tile(
+        device.clone(),
+        queue.clone(),
+        [width, height],
+        &options.polynomial,
+        OutputFormat::Png8,
+    );
+    let fragment = FragmentRenderer::new(device.clone(), queue.clone(), width, height);
+    let params = options.params();
+
+    // the first frames also pay for the driver compiling the pipelines
+    let compute_pixels = compute.render_image(&params, width, height);
+    let fragment_pixels = fragment.render_image(&params);
+
+    let compute_time = measure(options.bench, || {
+        compute.render_image(&params, width, height);
+    });
+    let fragment_time = measure(options.bench, || {
+        fragment.render_image(&params);
+    });
+
+    println!(
+        "{}x{} pixels, {} samples per pixel, {} iterations, {} frames",
+        width,
+        height,
+        params.samples * params.samples,
+        params.iterations,
+        options.bench
+    );
+    let samples = width as f64 * height as f64 * (params.samples * params.samples) as f64;
+    for &(name, time) in &[("compute", compute_time), ("fragment", fragment_time)] {
+        let seconds = as_seconds(time) / options.bench as f64;
+        println!(
+            "{:>8}: {:8.2} ms per frame, {:8.1} million samples per second",
+            name,
+            seconds * 1000.0,
+            samples / seconds / 1_000_000.0
+        );
+    }
+
+    // both evaluate the same function, only the rounding may differ
+    let (different, max_difference) = compare(&compute_pixels, &fragment_pixels);
+    println!(
+        "{} of {} pixels differ between the two, by at most {} per channel",
+        different,
+        width * height,
+        max_difference
+    );
+}
+
+fn measure<F: FnMut()>(frames: u32, mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0 .. frames {
+        f();
+    }
+    start.elapsed()
+}
+
+fn as_seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+/// Counts the pixels that differ and the biggest difference of a channel.
+fn compare(a: &[u8], b: &[u8]) -> (usize, u8) {
+    a.chunks(4).zip(b.chunks(4)).fold((0, 0), |(different, max), (a, b)| {
+        let difference = a
+            .iter()
+            .zip(b)
+            .map(|(&a, &b)| if a > b { a - b } else { b - a })
+            .max()
+            .unwrap_or(0);
+        let different = if difference > 0 { different + 1 } else { different };
+        (different, max.max(difference))
+    })
+}
@@ -0,0 +1,212 @@
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::CpuAccessibleBuffer;
+
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::command_buffer::CommandBuffer;
+
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+
+use vulkano::device::Device;
+use vulkano::device::Queue;
+
+use vulkano::format::ClearValue;
+use vulkano::format::Format;
+
+use vulkano::image::Dimensions;
+use vulkano::image::StorageImage;
+
+use vulkano::pipeline::ComputePipeline;
+
+use vulkano::sync::GpuFuture;
+
+use std::sync::Arc;
+
+use options::{Mode, Options};
+use png;
+use shaders;
+
+// Work done by a single dispatch, small enough to stay clear of the driver timeouts.
+const GROUPS: u32 = 1024;
+const SAMPLES_PER_INVOCATION: u32 = 16;
+const SAMPLES_PER_DISPATCH: u64 = GROUPS as u64 * 64 * SAMPLES_PER_INVOCATION as u64;
+
+/// Renders the Buddhabrot, or the Nebulabrot when `options.mode` asks for it.
+///
+/// Random points are sampled from the whole set, the orbits of those that
+/// escape are traced again and every pixel they cross is incremented. The
+/// counts of each channel are then normalized and tone mapped in a second pass.
+///
+/// Orbits can land anywhere in the image, so unlike the escape time renderer
+/// the whole image has to fit in the device.
+pub fn render(device: Arc<Device>, queue: Arc<Queue>, options: &Options) {
+    let (width, height) = (options.width, options.height);
+
+    let max_size = device.physical_device().limits().max_image_dimension_2d();
+    if width > max_size || height > max_size {
+        eprintln!("the {:?} can't be bigger than {}x{} on this device", options.mode, max_size, max_size);
+        ::std::process::exit(1);
+    }
+
+    // The Nebulabrot maps orbits of different lengths to the three channels,
+    // the Buddhabrot is a grey scale image of a single one.
+    let iterations = match options.mode {
+        Mode::Nebulabrot => vec![
+            options.iterations * 10,
+            options.iterations,
+            (options.iterations / 10).max(1),
+        ],
+        _ => vec![options.iterations],
+    };
+
+    let accumulate_shader =
+        shaders::buddhabrot::Shader::load(device.clone()).expect("failed to load shader module");
+    let accumulate_pipeline = Arc::new(
+        ComputePipeline::new(device.clone(), &accumulate_shader.main_entry_point(), &())
+            .expect("failed to create compute pipeline"),
+    );
+
+    let tonemap_shader =
+        shaders::tonemap::Shader::load(device.clone()).expect("failed to load shader module");
+    let tonemap_pipeline = Arc::new(
+        ComputePipeline::new(device.clone(), &tonemap_shader.main_entry_point(), &())
+            .expect("failed to create compute pipeline"),
+    );
+
+    // one image of counters per channel, the unused ones only need to exist
+    // to fill the descriptor set of the tone mapping pass
+    let counts = (0 .. 3)
+        .map(|channel| {
+            let (width, height) = if channel < iterations.len() {
+                (width, height)
+            } else {
+                (1, 1)
+            };
+            StorageImage::new(
+                device.clone(),
+                Dimensions::Dim2d { width, height },
+                Format::R32Uint,
+                Some(queue.family()),
+            ).unwrap()
+        }).collect::<Vec<_>>();
+
+    let stats = CpuAccessibleBuffer::from_iter(
+        device.clone(),
+        BufferUsage::all(),
+        (0 .. 3).map(|_| 0u32),
+    ).expect("failed to create the buffer");
+
+    let accumulate_sets = counts
+        .iter()
+        .map(|image| {
+            Arc::new(
+                PersistentDescriptorSet::start(accumulate_pipeline.clone(), 0)
+                    .add_image(image.clone())
+                    .unwrap()
+                    .add_buffer(stats.clone())
+                    .unwrap()
+                    .build()
+                    .unwrap(),
+            )
+        }).collect::<Vec<_>>();
+
+    // clear the counters
+    let mut builder = AutoCommandBufferBuilder::new(device.clone(), queue.family()).unwrap();
+    for image in &counts {
+        builder = builder
+            .clear_color_image(image.clone(), ClearValue::Uint([0, 0, 0, 0]))
+            .unwrap();
+    }
+    execute(builder, &queue);
+
+    let samples = options.orbits * 1_000_000;
+    let dispatches = (samples + SAMPLES_PER_DISPATCH - 1) / SAMPLES_PER_DISPATCH;
+    let params = options.params();
+
+    for dispatch in 0 .. dispatches {
+        let mut builder = AutoCommandBufferBuilder::new(device.clone(), queue.family()).unwrap();
+        for (channel, &max_iterations) in iterations.iter().enumerate() {
+            let push_constants = shaders::buddhabrot::ty::PushConstantData {
+                center: params.view.center,
+                scale: 1.0 / params.view.zoom,
+                rotation: params.view.rotation.to_radians(),
+                seed: (dispatch * 3 + channel as u64) as u32,
+                samples: SAMPLES_PER_INVOCATION,
+                max_iterations,
+                channel: channel as u32,
+            };
+
+            builder = builder
+                .dispatch(
+                    [GROUPS, 1, 1],
+                    accumulate_pipeline.clone(),
+                    accumulate_sets[channel].clone(),
+                    push_constants,
+                ).unwrap();
+        }
+        execute(builder, &queue);
+
+        println!(
+            "Traced {} of {} million points",
+            (dispatch + 1) * SAMPLES_PER_DISPATCH / 1_000_000,
+            options.orbits
+        );
+    }
+
+    // normalize and tone map the counts
+    let image = StorageImage::new(
+        device.clone(),
+        Dimensions::Dim2d { width, height },
+        Format::R8G8B8A8Unorm,
+        Some(queue.family()),
+    ).unwrap();
+
+    let tonemap_set = Arc::new(
+        PersistentDescriptorSet::start(tonemap_pipeline.clone(), 0)
+            .add_image(counts[0].clone())
+            .unwrap()
+            .add_image(counts[1].clone())
+            .unwrap()
+            .add_image(counts[2].clone())
+            .unwrap()
+            .add_buffer(stats.clone())
+            .unwrap()
+            .add_image(image.clone())
+            .unwrap()
+            .build()
+            .unwrap(),
+    );
+
+    let buffer = CpuAccessibleBuffer::from_iter(
+        device.clone(),
+        BufferUsage::all(),
+        (0 .. width * height * 4).map(|_| 0u8),
+    ).expect("failed to create the buffer");
+
+    let builder = AutoCommandBufferBuilder::new(device.clone(), queue.family())
+        .unwrap()
+        .dispatch(
+            [(width + 7) / 8, (height + 7) / 8, 1],
+            tonemap_pipeline.clone(),
+            tonemap_set.clone(),
+            shaders::tonemap::ty::PushConstantData {
+                channels: iterations.len() as u32,
+            },
+        ).unwrap()
+        .copy_image_to_buffer(image.clone(), buffer.clone())
+        .unwrap();
+    execute(builder, &queue);
+
+    let buffer_content = buffer.read().unwrap();
+    png::write_png(&options.output, width, height, &buffer_content[..])
+        .expect("failed to write the image");
+}
+
+fn execute(builder: AutoCommandBufferBuilder, queue: &Arc<Queue>) {
+    let command_buffer = builder.build().unwrap();
+    let finished = command_buffer.execute(queue.clone()).unwrap();
+    finished
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+}
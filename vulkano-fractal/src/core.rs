@@ -0,0 +1,56 @@
+use vulkano::device::Device;
+use vulkano::device::DeviceExtensions;
+use vulkano::device::QueuesIter;
+
+use vulkano::instance::Features;
+use vulkano::instance::Instance;
+use vulkano::instance::InstanceExtensions;
+use vulkano::instance::PhysicalDevice;
+
+use std::sync::Arc;
+
+/// Creates a device on the first physical device, with the optional
+/// `features` some of the outputs need.
+pub fn init(features: &Features) -> (Arc<Device>, QueuesIter) {
+    // Create an instance of the vulkan API
+    let instance =
+        Instance::new(None, &InstanceExtensions::none(), None).expect("failed to create instance");
+
+    // List all the physical devices that support vulkan
+    for physical_device in PhysicalDevice::enumerate(&instance) {
+        println!("Available device: {}", physical_device.name());
+    }
+
+    // now we just get the first
+    let physical = PhysicalDevice::from_index(&instance, 0).expect("no device available");
+
+    // list all the queue families available for the device
+    for family in physical.queue_families() {
+        println!(
+            "Found a queue family with {:?} queue(s)",
+            family.queues_count()
+        );
+    }
+
+    // select a queue that supports graphical operations
+    let queue_family = physical
+        .queue_families()
+        .find(|&q| q.supports_graphics())
+        .expect("couldn't find a graphical queue family");
+
+    if !physical.supported_features().superset_of(features) {
+        eprintln!("{} doesn't support the features needed by this output format", physical.name());
+        ::std::process::exit(1);
+    }
+
+    let (device, queues) = {
+        Device::new(
+            physical,
+            features,
+            &DeviceExtensions::none(),
+            [(queue_family, 0.5)].iter().cloned(),
+        ).expect("failed to create device")
+    };
+
+    (device, queues)
+}
@@ -0,0 +1,421 @@
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::device::{Device, DeviceExtensions, Queue};
+use vulkano::format::Format;
+use vulkano::image::swapchain::SwapchainImage;
+use vulkano::image::{Dimensions, ImageUsage, StorageImage};
+use vulkano::instance::{Instance, PhysicalDevice};
+use vulkano::sampler::Filter;
+use vulkano::swapchain;
+use vulkano::swapchain::{
+    AcquireError, PresentMode, Surface, SurfaceTransform, Swapchain, SwapchainCreationError,
+};
+use vulkano::sync::now;
+use vulkano::sync::{FlushError, GpuFuture};
+
+use vulkano_graphical_pipeline::color::{choose_format, is_srgb};
+
+use vulkano_win;
+use vulkano_win::VkSurfaceBuild;
+
+use winit::{
+    ElementState, Event, EventsLoop, KeyboardInput, MouseButton, MouseScrollDelta,
+    VirtualKeyCode, Window, WindowBuilder, WindowEvent,
+};
+
+use std::sync::Arc;
+
+use options::Options;
+use output::OutputFormat;
+use render;
+use render::{FractalPipeline, FractalSet, Params, TileRenderer, View};
+use shaders;
+
+// The window is drawn in float, so that the colours lose no precision when an
+// sRGB swapchain gets them as linear values.
+const DISPLAY_FORMAT: OutputFormat = OutputFormat::Exr;
+
+/// What the window loop has to do after an event has been handled.
+enum Action {
+    Nothing,
+    Quit,
+    Save,
+}
+
+/// The view shown in the window and the state of the mouse.
+struct Explorer {
+    params: Params,
+    initial_view: View,
+    // follow the zoom with the number of iterations, until they are changed by hand
+    auto_iterations: bool,
+    cursor: [f64; 2],
+    dragging: bool,
+}
+
+impl Explorer {
+    fn handle_event(&mut self, event: WindowEvent, dimensions: [u32; 2], hidpi_factor: f64) -> Action {
+        match event {
+            WindowEvent::CloseRequested => return Action::Quit,
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => self.dragging = state == ElementState::Pressed,
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = position.to_physical(hidpi_factor);
+                let cursor = [position.x, position.y];
+                if self.dragging {
+                    // keep the point under the cursor attached to it
+                    let before = to_complex(&self.params.view, self.cursor, dimensions);
+                    let after = to_complex(&self.params.view, cursor, dimensions);
+                    self.params.view.center[0] += before[0] - after[0];
+                    self.params.view.center[1] += before[1] - after[1];
+                }
+                self.cursor = cursor;
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let steps = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y as f64,
+                    MouseScrollDelta::PixelDelta(position) => position.y / 20.0,
+                };
+                self.zoom_at_cursor(1.2f64.powf(steps) as f32, dimensions);
+                if self.auto_iterations {
+                    self.params.iterations = render::auto_iterations(self.params.view.zoom);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => match key {
+                VirtualKeyCode::Escape => return Action::Quit,
+                VirtualKeyCode::S => return Action::Save,
+                VirtualKeyCode::R => {
+                    self.params.view = self.initial_view;
+                    if self.auto_iterations {
+                        self.params.iterations = render::auto_iterations(self.params.view.zoom);
+                    }
+                }
+                VirtualKeyCode::Up => {
+                    self.auto_iterations = false;
+                    self.params.iterations += (self.params.iterations / 4).max(1);
+                    println!("Iterations: {}", self.params.iterations);
+                }
+                VirtualKeyCode::Down => {
+                    self.auto_iterations = false;
+                    self.params.iterations = (self.params.iterations * 4 / 5).max(1);
+                    println!("Iterations: {}", self.params.iterations);
+                }
+                _ => (),
+            },
+            _ => (),
+        }
+
+        Action::Nothing
+    }
+
+    // Zooms in by `factor` leaving the point under the cursor where it is.
+    fn zoom_at_cursor(&mut self, factor: f32, dimensions: [u32; 2]) {
+        let before = to_complex(&self.params.view, self.cursor, dimensions);
+        self.params.view.zoom *= factor;
+        let after = to_complex(&self.params.view, self.cursor, dimensions);
+        self.params.view.center[0] += before[0] - after[0];
+        self.params.view.center[1] += before[1] - after[1];
+    }
+}
+
+/// Opens a window showing the fractal, which can be panned by dragging it with
+/// the mouse and zoomed with the wheel.
+///
+/// The up and down arrows change the number of iterations, `S` saves the
+/// current view at the size given on the command line and `R` resets the view.
+pub fn run(options: &Options) {
+    let instance = Instance::new(None, &vulkano_win::required_extensions(), None)
+        .expect("failed to create Vulkan instance");
+
+    // just get the first device, as everywhere else
+    let physical = PhysicalDevice::from_index(&instance, 0).expect("no device available");
+    println!("Using device: {}", physical.name());
+
+    let mut events_loop = EventsLoop::new();
+    let surface = WindowBuilder::new()
+        .with_title("Fractal explorer")
+        .build_vk_surface(&events_loop, instance.clone())
+        .unwrap();
+
+    let (device, queue) = create_device(physical, &surface);
+
+    let (mut swapchain, mut images) =
+        create_swapchain(surface.clone(), physical, device.clone(), queue.clone());
+
+    // The renderer saves the current view, the window is drawn with its
+    // polynomial by a pipeline of its own.
+    let mut renderer = TileRenderer::new(
+        device.clone(),
+        queue.clone(),
+        options.tile_size,
+        &options.polynomial,
+        options.format,
+    );
+    if options.progressive {
+        renderer.enable_refinement();
+    }
+    let pipeline = render::create_pipeline(&device, DISPLAY_FORMAT);
+    let (mut image, mut set) = create_target(&renderer, &pipeline, &device, &queue, swapchain.dimensions());
+
+    // The colours of the fractal are already encoded for display, they are
+    // decoded by the shader when the swapchain encodes them again.
+    let decode_srgb = is_srgb(swapchain.format());
+
+    // interactive frames use a single sample, the saved images use what was asked
+    let mut explorer = Explorer {
+        params: Params {
+            samples: 1,
+            jitter: false,
+            ..options.params()
+        },
+        initial_view: options.view,
+        auto_iterations: options.auto_iterations,
+        cursor: [0.0, 0.0],
+        dragging: false,
+    };
+
+    let mut saved = 0;
+    let mut recreate_swapchain = false;
+    let mut previous_frame_end = Box::new(now(device.clone())) as Box<GpuFuture>;
+
+    loop {
+        previous_frame_end.cleanup_finished();
+
+        if recreate_swapchain {
+            let dimensions = surface
+                .capabilities(physical)
+                .expect("failed to get surface capabilities")
+                .current_extent
+                .unwrap_or_else(|| swapchain.dimensions());
+
+            let (new_swapchain, new_images) = match swapchain.recreate_with_dimension(dimensions) {
+                Ok(r) => r,
+                // This error tends to happen when the user is manually resizing the window.
+                Err(SwapchainCreationError::UnsupportedDimensions) => {
+                    continue;
+                }
+                Err(err) => panic!("{:?}", err),
+            };
+
+            swapchain = new_swapchain;
+            images = new_images;
+
+            // the storage image always matches the size of the window
+            let target = create_target(&renderer, &pipeline, &device, &queue, dimensions);
+            image = target.0;
+            set = target.1;
+
+            recreate_swapchain = false;
+        }
+
+        let (image_num, acquire_future) =
+            match swapchain::acquire_next_image(swapchain.clone(), None) {
+                Ok(r) => r,
+                Err(AcquireError::OutOfDate) => {
+                    recreate_swapchain = true;
+                    continue;
+                }
+                Err(err) => panic!("{:?}", err),
+            };
+
+        // Render the fractal in the storage image and then blit it to the
+        // swapchain image, which handles the conversion to the window format.
+        let dimensions = swapchain.dimensions();
+        let corner = [dimensions[0] as i32, dimensions[1] as i32, 1];
+        let push_constants = shaders::cs::ty::PushConstantData {
+            decode_srgb: decode_srgb as u32,
+            ..render::push_constants(&explorer.params, [0, 0], dimensions, false)
+        };
+        let command_buffer =
+            AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), queue.family())
+                .unwrap()
+                .dispatch(
+                    [(dimensions[0] + 7) / 8, (dimensions[1] + 7) / 8, 1],
+                    pipeline.clone(),
+                    set.clone(),
+                    push_constants,
+                ).unwrap()
+                .blit_image(
+                    image.clone(),
+                    [0, 0, 0],
+                    corner,
+                    0,
+                    0,
+                    images[image_num].clone(),
+                    [0, 0, 0],
+                    corner,
+                    0,
+                    0,
+                    1,
+                    Filter::Nearest,
+                ).unwrap()
+                .build()
+                .unwrap();
+
+        let future = previous_frame_end
+            .join(acquire_future)
+            .then_execute(queue.clone(), command_buffer)
+            .unwrap()
+            .then_swapchain_present(queue.clone(), swapchain.clone(), image_num)
+            .then_signal_fence_and_flush();
+
+        match future {
+            Ok(future) => {
+                previous_frame_end = Box::new(future) as Box<_>;
+            }
+            Err(FlushError::OutOfDate) => {
+                recreate_swapchain = true;
+                previous_frame_end = Box::new(now(device.clone())) as Box<_>;
+            }
+            Err(e) => {
+                println!("{:?}", e);
+                previous_frame_end = Box::new(now(device.clone())) as Box<_>;
+            }
+        }
+
+        let hidpi_factor = surface.window().get_hidpi_factor();
+        let mut done = false;
+        let mut save = false;
+        events_loop.poll_events(|event| {
+            if let Event::WindowEvent { event, .. } = event {
+                if let WindowEvent::Resized(_) = event {
+                    recreate_swapchain = true;
+                }
+                match explorer.handle_event(event, dimensions, hidpi_factor) {
+                    Action::Nothing => (),
+                    Action::Quit => done = true,
+                    Action::Save => save = true,
+                }
+            }
+        });
+
+        if done {
+            return;
+        }
+
+        if save {
+            // keep the aspect ratio of the window at the requested height
+            let height = options.height;
+            let width = (height as u64 * dimensions[0] as u64 / dimensions[1] as u64).max(1) as u32;
+            let params = Params {
+                samples: options.samples,
+                jitter: options.jitter,
+                ..explorer.params
+            };
+
+            let path = options.numbered_output(saved);
+            match renderer.render_to_file(&params, width, height, &path, None) {
+                Ok(()) => println!("Saved {} ({:?})", path, params.view),
+                Err(err) => println!("failed to save {}: {}", path, err),
+            }
+            saved += 1;
+        }
+    }
+}
+
+/// Maps a position in the window (in pixels) to the complex plane, exactly as
+/// the shader does.
+fn to_complex(view: &View, position: [f64; 2], dimensions: [u32; 2]) -> [f32; 2] {
+    let half_height = dimensions[1] as f64 * 0.5;
+    let x = (position[0] - dimensions[0] as f64 * 0.5) / half_height;
+    let y = (position[1] - half_height) / half_height;
+
+    let (s, c) = (view.rotation as f64).to_radians().sin_cos();
+    let scale = 1.0 / view.zoom as f64;
+    [
+        view.center[0] + ((c * x - s * y) * scale) as f32,
+        view.center[1] + ((s * x + c * y) * scale) as f32,
+    ]
+}
+
+fn create_device(physical: PhysicalDevice, surface: &Arc<Surface<Window>>) -> (Arc<Device>, Arc<Queue>) {
+    // the queue must be able to render and to present to the window
+    let queue_family = physical
+        .queue_families()
+        .find(|&q| q.supports_graphics() && surface.is_supported(q).unwrap_or(false))
+        .expect("couldn't find a graphical queue family");
+
+    let device_ext = DeviceExtensions {
+        khr_swapchain: true,
+        ..DeviceExtensions::none()
+    };
+
+    let (device, mut queues) = Device::new(
+        physical,
+        physical.supported_features(),
+        &device_ext,
+        [(queue_family, 0.5)].iter().cloned(),
+    ).expect("failed to create device");
+
+    let queue = queues.next().expect("Couldn't get the first queue");
+    (device, queue)
+}
+
+fn create_swapchain(
+    surface: Arc<Surface<Window>>,
+    physical: PhysicalDevice,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+) -> (Arc<Swapchain<Window>>, Vec<Arc<SwapchainImage<Window>>>) {
+    let caps = surface
+        .capabilities(physical)
+        .expect("failed to get surface capabilities");
+
+    let dimensions = caps.current_extent.unwrap_or([1024, 768]);
+    let alpha = caps.supported_composite_alpha.iter().next().unwrap();
+    let format = choose_format(&caps.supported_formats);
+
+    // we never render to the swapchain images directly, we blit into them
+    let usage = ImageUsage {
+        transfer_destination: true,
+        ..ImageUsage::none()
+    };
+
+    Swapchain::new(
+        device.clone(),
+        surface.clone(),
+        caps.min_image_count,
+        format,
+        dimensions,
+        1,
+        usage,
+        &queue,
+        SurfaceTransform::Identity,
+        alpha,
+        PresentMode::Fifo,
+        true,
+        None,
+    ).expect("failed to create swapchain")
+}
+
+/// Creates the storage image the fractal is rendered into and its descriptor set.
+fn create_target(
+    renderer: &TileRenderer,
+    pipeline: &Arc<FractalPipeline>,
+    device: &Arc<Device>,
+    queue: &Arc<Queue>,
+    dimensions: [u32; 2],
+) -> (Arc<StorageImage<Format>>, Arc<FractalSet>) {
+    let image = StorageImage::new(
+        device.clone(),
+        Dimensions::Dim2d {
+            width: dimensions[0],
+            height: dimensions[1],
+        },
+        DISPLAY_FORMAT.image_format(),
+        Some(queue.family()),
+    ).unwrap();
+
+    let set = renderer.descriptor_set(pipeline, image.clone());
+
+    (image, set)
+}
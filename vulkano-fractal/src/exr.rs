@@ -0,0 +1,244 @@
+use std::io;
+use std::io::Write;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+
+/// Writes an uncompressed scanline OpenEXR file with 32 bit float RGBA
+/// channels, row by row.
+///
+/// Without compression every line has the same size, so the offset table that
+/// precedes the pixels can be written before rendering anything.
+pub struct ExrStreamWriter<W: Write> {
+    inner: W,
+    width: u32,
+    next_line: u32,
+    height: u32,
+    line: Vec<u8>,
+}
+
+impl<W: Write> ExrStreamWriter<W> {
+    pub fn new(mut inner: W, width: u32, height: u32) -> io::Result<ExrStreamWriter<W>> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&MAGIC);
+        // version 2, single part scanline image
+        header.extend_from_slice(&le_u32(2));
+
+        // channels are stored in alphabetical order
+        let mut channels = Vec::new();
+        for name in &[b"A", b"B", b"G", b"R"] {
+            channels.extend_from_slice(&name[..]);
+            channels.push(0);
+            // FLOAT pixels, not linear, reserved, x and y sampling
+            channels.extend_from_slice(&le_u32(2));
+            channels.extend_from_slice(&[0, 0, 0, 0]);
+            channels.extend_from_slice(&le_u32(1));
+            channels.extend_from_slice(&le_u32(1));
+        }
+        channels.push(0);
+        attribute(&mut header, "channels", "chlist", &channels);
+
+        attribute(&mut header, "compression", "compression", &[0]);
+
+        let mut window = Vec::new();
+        for value in &[0, 0, width - 1, height - 1] {
+            window.extend_from_slice(&le_u32(*value));
+        }
+        attribute(&mut header, "dataWindow", "box2i", &window);
+        attribute(&mut header, "displayWindow", "box2i", &window);
+
+        attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        attribute(&mut header, "pixelAspectRatio", "float", &le_u32(1.0f32.to_bits()));
+        let mut center = Vec::new();
+        center.extend_from_slice(&le_u32(0.0f32.to_bits()));
+        center.extend_from_slice(&le_u32(0.0f32.to_bits()));
+        attribute(&mut header, "screenWindowCenter", "v2f", &center);
+        attribute(&mut header, "screenWindowWidth", "float", &le_u32(1.0f32.to_bits()));
+        header.push(0);
+
+        inner.write_all(&header)?;
+
+        // each line is made of its y coordinate, the size of the pixel data and the pixels
+        let line_size = 8 + width as u64 * 16;
+        let first_line = header.len() as u64 + height as u64 * 8;
+        for y in 0 .. height as u64 {
+            inner.write_all(&le_u64(first_line + y * line_size))?;
+        }
+
+        Ok(ExrStreamWriter {
+            inner,
+            width,
+            next_line: 0,
+            height,
+            line: Vec::with_capacity(line_size as usize),
+        })
+    }
+
+    /// Appends the next row, `row` must contain `width` RGBA pixels made of
+    /// native endian 32 bit floats.
+    pub fn write_row(&mut self, row: &[u8]) -> io::Result<()> {
+        assert_eq!(row.len(), self.width as usize * 16, "row has the wrong size");
+        assert!(self.next_line < self.height, "too many rows written");
+
+        self.line.clear();
+        self.line.extend_from_slice(&le_u32(self.next_line));
+        self.line.extend_from_slice(&le_u32(self.width * 16));
+        // the pixels are stored one channel after the other: A, B, G and R
+        for &channel in &[3, 2, 1, 0] {
+            for pixel in row.chunks(16) {
+                let mut value = [0u8; 4];
+                value.copy_from_slice(&pixel[channel * 4 .. channel * 4 + 4]);
+                if cfg!(target_endian = "big") {
+                    value.reverse();
+                }
+                self.line.extend_from_slice(&value);
+            }
+        }
+
+        self.next_line += 1;
+        self.inner.write_all(&self.line)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        assert_eq!(self.next_line, self.height, "not all the rows have been written");
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&le_u32(value.len() as u32));
+    header.extend_from_slice(value);
+}
+
+fn le_u32(value: u32) -> [u8; 4] {
+    [
+        value as u8,
+        (value >> 8) as u8,
+        (value >> 16) as u8,
+        (value >> 24) as u8,
+    ]
+}
+
+fn le_u64(value: u64) -> [u8; 8] {
+    let mut bytes = [0; 8];
+    bytes[.. 4].copy_from_slice(&le_u32(value as u32));
+    bytes[4 ..].copy_from_slice(&le_u32((value >> 32) as u32));
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        (0 .. 4).fold(0, |value, i| value | u32::from(bytes[offset + i]) << (8 * i))
+    }
+
+    fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+        u64::from(read_u32(bytes, offset)) | u64::from(read_u32(bytes, offset + 4)) << 32
+    }
+
+    fn read_name(bytes: &[u8], offset: &mut usize) -> String {
+        let end = *offset + bytes[*offset ..].iter().position(|&byte| byte == 0).unwrap();
+        let name = String::from_utf8(bytes[*offset .. end].to_vec()).unwrap();
+        *offset = end + 1;
+        name
+    }
+
+    // The attributes of the header by name, with their type and value, and
+    // the offset of the table that follows them.
+    fn attributes(file: &[u8]) -> (HashMap<String, (String, Vec<u8>)>, usize) {
+        let mut attributes = HashMap::new();
+        let mut offset = 8;
+        while file[offset] != 0 {
+            let name = read_name(file, &mut offset);
+            let kind = read_name(file, &mut offset);
+            let size = read_u32(file, offset) as usize;
+            attributes.insert(name, (kind, file[offset + 4 .. offset + 4 + size].to_vec()));
+            offset += 4 + size;
+        }
+        (attributes, offset + 1)
+    }
+
+    // A 3x2 image whose channels are 10 times the pixel index plus the channel.
+    fn test_image() -> Vec<u8> {
+        let mut writer = ExrStreamWriter::new(Vec::new(), 3, 2).unwrap();
+        for y in 0 .. 2 {
+            let row = (0 .. 3 * 4)
+                .flat_map(|i| {
+                    let value = (y * 3 + i / 4) as f32 * 10.0 + (i % 4) as f32;
+                    let bits = value.to_bits();
+                    let bytes = [bits as u8, (bits >> 8) as u8, (bits >> 16) as u8, (bits >> 24) as u8];
+                    if cfg!(target_endian = "big") {
+                        vec![bytes[3], bytes[2], bytes[1], bytes[0]]
+                    } else {
+                        bytes.to_vec()
+                    }
+                }).collect::<Vec<_>>();
+            writer.write_row(&row).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn header_describes_the_image() {
+        let file = test_image();
+        assert_eq!(&file[.. 4], &MAGIC);
+        assert_eq!(read_u32(&file, 4), 2);
+
+        let (attributes, _) = attributes(&file);
+        let attribute = |name: &str, kind: &str| {
+            let (actual_kind, value) = &attributes[name];
+            assert_eq!(actual_kind, kind, "{}", name);
+            value.clone()
+        };
+
+        let channels = attribute("channels", "chlist");
+        assert_eq!(channels.len(), 4 * 18 + 1);
+        for (channel, name) in channels.chunks(18).zip(b"ABGR".iter()) {
+            assert_eq!(&channel[.. 2], &[*name, 0]);
+            // FLOAT pixels sampled once per pixel
+            assert_eq!(read_u32(channel, 2), 2);
+            assert_eq!((read_u32(channel, 10), read_u32(channel, 14)), (1, 1));
+        }
+
+        assert_eq!(attribute("compression", "compression"), vec![0]);
+        let window = attribute("dataWindow", "box2i");
+        assert_eq!((0 .. 4).map(|i| read_u32(&window, i * 4)).collect::<Vec<_>>(), vec![0, 0, 2, 1]);
+        assert_eq!(attribute("displayWindow", "box2i"), window);
+        assert_eq!(attribute("lineOrder", "lineOrder"), vec![0]);
+        assert_eq!(read_u32(&attribute("pixelAspectRatio", "float"), 0), 1.0f32.to_bits());
+        assert_eq!(attribute("screenWindowCenter", "v2f"), vec![0; 8]);
+        assert_eq!(read_u32(&attribute("screenWindowWidth", "float"), 0), 1.0f32.to_bits());
+        assert_eq!(attributes.len(), 8);
+    }
+
+    #[test]
+    fn offsets_point_at_the_lines() {
+        let file = test_image();
+        let (_, table) = attributes(&file);
+
+        let line_size = 8 + 3 * 16;
+        for y in 0 .. 2 {
+            let offset = read_u64(&file, table + y * 8) as usize;
+            assert_eq!(offset, table + 2 * 8 + y * line_size);
+            assert_eq!(read_u32(&file, offset), y as u32);
+            assert_eq!(read_u32(&file, offset + 4), 3 * 16);
+
+            // the channels one after the other, in the order of the list
+            for (channel, rgba) in [3, 2, 1, 0].iter().enumerate() {
+                for x in 0 .. 3 {
+                    let value = f32::from_bits(read_u32(&file, offset + 8 + (channel * 3 + x) * 4));
+                    assert_eq!(value, (y * 3 + x) as f32 * 10.0 + *rgba as f32);
+                }
+            }
+        }
+        assert_eq!(file.len(), table + 2 * 8 + 2 * line_size);
+    }
+}
@@ -0,0 +1,123 @@
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::CpuAccessibleBuffer;
+
+use vulkano::device::Device;
+use vulkano::device::Queue;
+
+use vulkano::format::Format;
+
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::pipeline::GraphicsPipelineAbstract;
+
+use vulkano_graphical_pipeline::OffscreenRenderer;
+
+use std::sync::Arc;
+
+use options::Options;
+use png;
+use render::Params;
+use shaders;
+
+#[derive(Copy, Clone)]
+struct Vertex {
+    position: [f32; 2],
+}
+
+impl_vertex!(Vertex, position);
+
+/// Renders the escape time of the Mandelbrot set with a graphics pipeline.
+///
+/// A single triangle, big enough to cover the whole viewport, is drawn and
+/// the fractal is evaluated by the fragment shader. The whole image is drawn
+/// at once, so it can't be bigger than `maxImageDimension2D`.
+pub struct FragmentRenderer {
+    renderer: OffscreenRenderer,
+    pipeline: Arc<GraphicsPipelineAbstract + Send + Sync>,
+    vertex_buffer: Arc<CpuAccessibleBuffer<[Vertex]>>,
+}
+
+impl FragmentRenderer {
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>, width: u32, height: u32) -> FragmentRenderer {
+        // the colours of the shader are the bytes of the image, like those of
+        // the compute shader, they must not be encoded to sRGB
+        let renderer = OffscreenRenderer::with_format(device.clone(), queue, width, height, Format::R8G8B8A8Unorm);
+
+        // the corners outside of the viewport are clipped away
+        let vertex_buffer = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::all(),
+            vec![
+                Vertex {
+                    position: [-1.0, -1.0],
+                },
+                Vertex {
+                    position: [3.0, -1.0],
+                },
+                Vertex {
+                    position: [-1.0, 3.0],
+                },
+            ].into_iter(),
+        ).expect("failed to create buffer");
+
+        // create the vertex and fragment shader
+        let vs = shaders::vs::Shader::load(device.clone()).expect("failed to create shader module");
+        let fs = shaders::fs::Shader::load(device.clone()).expect("failed to create shader module");
+
+        let pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input_single_buffer::<Vertex>()
+                .vertex_shader(vs.main_entry_point(), ())
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(fs.main_entry_point(), ())
+                .render_pass(renderer.subpass())
+                .build(device.clone())
+                .unwrap(),
+        );
+
+        FragmentRenderer {
+            renderer,
+            pipeline,
+            vertex_buffer,
+        }
+    }
+
+    /// Draws the fractal and returns the RGBA pixels of the image.
+    pub fn render_image(&self, params: &Params) -> Vec<u8> {
+        let (width, height) = self.renderer.dimensions();
+        let push_constants = shaders::fs::ty::PushConstantData {
+            image_size: [width, height],
+            samples: params.samples,
+            jitter: params.jitter as u32,
+            center: params.view.center,
+            scale: 1.0 / params.view.zoom,
+            rotation: params.view.rotation.to_radians(),
+            palette_offset: params.view.palette_offset,
+            max_iterations: params.iterations,
+        };
+
+        // every pixel is written by the triangle, the clear colour never shows
+        self.renderer
+            .draw(
+                [0.0, 0.0, 0.0, 1.0],
+                self.pipeline.clone(),
+                vec![self.vertex_buffer.clone()],
+                (),
+                push_constants,
+            ).into_raw()
+    }
+}
+
+/// Renders the image asked on the command line with the fragment shader.
+pub fn render(device: Arc<Device>, queue: Arc<Queue>, options: &Options) {
+    let (width, height) = (options.width, options.height);
+
+    let max_size = device.physical_device().limits().max_image_dimension_2d();
+    if width > max_size || height > max_size {
+        eprintln!("the fragment renderer can't draw images bigger than {}x{} on this device", max_size, max_size);
+        ::std::process::exit(1);
+    }
+
+    let renderer = FragmentRenderer::new(device, queue, width, height);
+    let pixels = renderer.render_image(&options.params());
+    png::write_png(&options.output, width, height, &pixels).expect("failed to write the image");
+}
@@ -0,0 +1,215 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+
+use png::PngStreamWriter;
+
+// Colours of the heat map, from no iteration at all to the maximum.
+const RAMP: [[f32; 3]; 5] = [
+    [0.0, 0.0, 0.0],
+    [0.15, 0.1, 0.55],
+    [0.85, 0.15, 0.15],
+    [1.0, 0.85, 0.1],
+    [1.0, 1.0, 1.0],
+];
+
+/// Writes the number of iterations spent on each pixel as a heat map, and
+/// counts them for the statistics.
+pub struct HeatMap {
+    writer: PngStreamWriter<BufWriter<File>>,
+    counts: IterationCounts,
+    row: Vec<u8>,
+}
+
+/// A histogram of the iterations spent on the pixels.
+struct IterationCounts {
+    max_iterations: u32,
+    // pixels by number of iterations, rounded
+    histogram: Vec<u64>,
+    sum: f64,
+    at_max: u64,
+}
+
+/// Summary of the iterations spent on the pixels of an image.
+pub struct HeatMapStats {
+    pub max_iterations: u32,
+    pub mean: f64,
+    /// 99% of the pixels needed at most this many iterations.
+    pub p99: u32,
+    /// Fraction of the pixels that reached the maximum number of iterations.
+    pub at_max: f64,
+}
+
+impl HeatMap {
+    pub fn create(path: &str, width: u32, height: u32, max_iterations: u32) -> io::Result<HeatMap> {
+        let file = File::create(path)?;
+        Ok(HeatMap {
+            writer: PngStreamWriter::new(BufWriter::new(file), width, height)?,
+            counts: IterationCounts::new(max_iterations),
+            row: Vec::with_capacity(width as usize * 4),
+        })
+    }
+
+    /// Appends the next row, `data` contains the raw data of its pixels as
+    /// written by the fractal shader: four native endian floats per pixel,
+    /// the first one being the number of iterations.
+    pub fn write_row(&mut self, data: &[u8]) -> io::Result<()> {
+        let max_iterations = self.counts.max_iterations as f32;
+
+        self.row.clear();
+        for pixel in data.chunks(16) {
+            let iterations = read_f32(&pixel[.. 4]).max(0.0).min(max_iterations);
+            self.counts.add(iterations);
+
+            let color = ramp(iterations / max_iterations);
+            self.row.extend(color.iter().map(|c| (c * 255.0).round() as u8));
+            self.row.push(255);
+        }
+
+        self.writer.write_row(&self.row)
+    }
+
+    pub fn finish(self) -> io::Result<HeatMapStats> {
+        self.writer.finish()?;
+        Ok(self.counts.stats())
+    }
+}
+
+impl IterationCounts {
+    fn new(max_iterations: u32) -> IterationCounts {
+        IterationCounts {
+            max_iterations,
+            histogram: vec![0; max_iterations as usize + 1],
+            sum: 0.0,
+            at_max: 0,
+        }
+    }
+
+    /// Counts a pixel, `iterations` is between 0 and the maximum.
+    fn add(&mut self, iterations: f32) {
+        self.histogram[iterations.round() as usize] += 1;
+        self.sum += iterations as f64;
+        if iterations >= self.max_iterations as f32 {
+            self.at_max += 1;
+        }
+    }
+
+    fn stats(&self) -> HeatMapStats {
+        let pixels = self.histogram.iter().sum::<u64>().max(1);
+        let mut seen = 0;
+        let p99 = self
+            .histogram
+            .iter()
+            .position(|&count| {
+                seen += count;
+                seen * 100 >= pixels * 99
+            }).unwrap_or(0) as u32;
+
+        HeatMapStats {
+            max_iterations: self.max_iterations,
+            mean: self.sum / pixels as f64,
+            p99,
+            at_max: self.at_max as f64 / pixels as f64,
+        }
+    }
+}
+
+impl fmt::Display for HeatMapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "iterations per pixel: mean {:.1}, p99 {}, {:.2}% reached the maximum of {}",
+            self.mean,
+            self.p99,
+            self.at_max * 100.0,
+            self.max_iterations
+        )
+    }
+}
+
+fn ramp(t: f32) -> [f32; 3] {
+    let position = t * (RAMP.len() - 1) as f32;
+    let index = (position as usize).min(RAMP.len() - 2);
+    let t = position - index as f32;
+
+    let (a, b) = (RAMP[index], RAMP[index + 1]);
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+fn read_f32(bytes: &[u8]) -> f32 {
+    let mut value = [0u8; 4];
+    value.copy_from_slice(bytes);
+    if cfg!(target_endian = "big") {
+        value.reverse();
+    }
+    let bits = u32::from(value[0])
+        | u32::from(value[1]) << 8
+        | u32::from(value[2]) << 16
+        | u32::from(value[3]) << 24;
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_at_the_maximum() {
+        let mut counts = IterationCounts::new(500);
+        for _ in 0 .. 10 {
+            counts.add(500.0);
+        }
+
+        let stats = counts.stats();
+        assert_eq!(stats.max_iterations, 500);
+        assert_eq!(stats.mean, 500.0);
+        assert_eq!(stats.p99, 500);
+        assert_eq!(stats.at_max, 1.0);
+    }
+
+    #[test]
+    fn pixels_spread_uniformly() {
+        // a pixel per number of iterations, the 99th of the 100 pixels needed 98
+        let mut counts = IterationCounts::new(99);
+        for iterations in 0 .. 100 {
+            counts.add(iterations as f32);
+        }
+
+        let stats = counts.stats();
+        assert_eq!(stats.mean, 49.5);
+        assert_eq!(stats.p99, 98);
+        assert_eq!(stats.at_max, 0.01);
+        assert_eq!(
+            stats.to_string(),
+            "iterations per pixel: mean 49.5, p99 98, 1.00% reached the maximum of 99"
+        );
+    }
+
+    #[test]
+    fn fractional_iterations_are_rounded_in_the_histogram_only() {
+        let mut counts = IterationCounts::new(10);
+        for _ in 0 .. 3 {
+            counts.add(2.75);
+        }
+        counts.add(9.5);
+
+        let stats = counts.stats();
+        assert_eq!(stats.mean, (3.0 * 2.75 + 9.5) / 4.0);
+        assert_eq!(stats.p99, 10);
+        assert_eq!(stats.at_max, 0.0);
+        assert_eq!(counts.histogram[3], 3);
+    }
+
+    #[test]
+    fn empty_images_have_no_iterations() {
+        let stats = IterationCounts::new(100).stats();
+        assert_eq!(stats.mean, 0.0);
+        assert_eq!(stats.p99, 0);
+        assert_eq!(stats.at_max, 0.0);
+    }
+}
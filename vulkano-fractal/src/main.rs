@@ -1,3 +1,7 @@
+extern crate args;
+extern crate flate2;
+extern crate gif;
+extern crate golden;
 extern crate image;
 
 #[macro_use]
@@ -6,181 +10,163 @@ extern crate vulkano;
 #[macro_use]
 extern crate vulkano_shader_derive;
 
-use image::{ImageBuffer, Rgba};
+extern crate vulkano_graphical_pipeline;
+extern crate vulkano_win;
+extern crate winit;
+
+mod animation;
+mod bench;
+mod buddhabrot;
+mod core;
+mod explorer;
+mod exr;
+mod fragment;
+mod heatmap;
+mod mandelbulb;
+mod npy;
+mod options;
+mod output;
+mod png;
+mod polynomial;
+mod refine;
+mod render;
+mod server;
+mod shaders;
+
+use heatmap::HeatMap;
+use options::{Mode, Options};
+use render::TileRenderer;
 
-use vulkano::buffer::BufferUsage;
-use vulkano::buffer::CpuAccessibleBuffer;
-
-use vulkano::command_buffer::AutoCommandBufferBuilder;
-use vulkano::command_buffer::CommandBuffer;
-
-use vulkano::instance::Features;
-use vulkano::instance::Instance;
-use vulkano::instance::InstanceExtensions;
-use vulkano::instance::PhysicalDevice;
-
-use vulkano::device::Device;
-use vulkano::device::DeviceExtensions;
-use vulkano::device::QueuesIter;
-
-use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
-
-use vulkano::format::ClearValue;
-use vulkano::format::Format;
-
-use vulkano::image::Dimensions;
-use vulkano::image::StorageImage;
-
-use vulkano::sync::GpuFuture;
-
-use vulkano::pipeline::ComputePipeline;
-
-use std::sync::Arc;
-
-mod cs {
-    #[derive(VulkanoShader)]
-    #[ty = "compute"]
-    #[src = "
+fn main() {
+    let options = Options::from_args();
 
-#version 450
+    if options.explore {
+        explorer::run(&options);
+        return;
+    }
 
-layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+    if let Some(ref address) = options.serve {
+        server::run(&options, address);
+        return;
+    }
 
-layout(set = 0, binding = 0, rgba8) uniform writeonly image2D img;
+    render(&options);
 
-void main() {
-    vec2 norm_coordinates = (gl_GlobalInvocationID.xy + vec2(0.5)) / vec2(imageSize(img));
-    vec2 c = (norm_coordinates - vec2(0.5)) * 2.0 - vec2(1.0, 0.0);
+    if let Some(ref reference) = options.check {
+        check(&options.output, reference);
+    }
+}
 
-    vec2 z = vec2(0.0, 0.0);
-    float i;
-    float l;
-    for (i = 0.0; i < 1.0; i += 0.005) {
-        z = vec2(
-            z.x * z.x - z.y * z.y + c.x,
-            z.y * z.x + z.x * z.y + c.y
-        );
+/// Renders the image, or the animation, described by `options`.
+fn render(options: &Options) {
+    let (device, mut queues) = core::init(&options.format.required_features());
 
+    let queue = queues.next().expect("Couldn't get the first queue");
 
-        l = length(z);
-        if (l > 4.0) {
-            break;
+    match options.mode {
+        Mode::Mandelbrot | Mode::Newton => (),
+        Mode::Buddhabrot | Mode::Nebulabrot => {
+            buddhabrot::render(device.clone(), queue.clone(), options);
+            return;
+        }
+        Mode::Mandelbulb | Mode::Mandelbox => {
+            mandelbulb::render(device.clone(), queue.clone(), options);
+            return;
         }
     }
 
-    vec4 to_write = vec4(0.0, 0.0, 0.0, 0.0);
-    if (i < 0.2) {
-        to_write = vec4(i, i / l, i, 1.0);
-    } else if (i < 0.7) {
-        to_write = vec4(vec2(i), i / l, 1.0);
-    } else {
-        to_write = vec4(i/ l, vec2(i), 1.0);
+    if options.bench > 0 {
+        bench::run(device.clone(), queue.clone(), options);
+        return;
     }
-    imageStore(img, ivec2(gl_GlobalInvocationID.xy), to_write);
-}"]
-    struct Dummy;
-}
-
-fn main() {
-    let size_x = 1024;
-    let size_y = 1024;
-
-    let (device, mut queues) = init();
 
-    let queue = queues.next().expect("Couldn't get the first queue");
-
-    // create the compute pipeline
-    let shader = cs::Shader::load(device.clone()).expect("failed to load shader module");
-    let compute_pipeline = Arc::new(
-        ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
-            .expect("failed to create compute pipeline"),
-    );
+    if options.fragment {
+        fragment::render(device.clone(), queue.clone(), options);
+        return;
+    }
 
-    // allocate an image
-    let image = StorageImage::new(
+    let mut renderer = TileRenderer::new(
         device.clone(),
-        Dimensions::Dim2d {
-            width: size_x,
-            height: size_y,
-        },
-        Format::R8G8B8A8Unorm,
-        Some(queue.family()),
-    ).unwrap();
-
-    // bind the image to the shade with a descriptor set
-    let set = Arc::new(
-        PersistentDescriptorSet::start(compute_pipeline.clone(), 0)
-            .add_image(image.clone())
-            .unwrap()
-            .build()
-            .unwrap(),
+        queue.clone(),
+        options.tile_size,
+        &options.polynomial,
+        options.format,
     );
+    if options.progressive {
+        renderer.enable_refinement();
+    }
+    if options.heatmap.is_some() {
+        renderer.enable_heatmap();
+    }
 
-    // Create a buffer to read the resulting image
-    let buffer = CpuAccessibleBuffer::from_iter(
-        device.clone(),
-        BufferUsage::all(),
-        (0 .. size_x * size_y * 4).map(|_| 0u8),
-    ).expect("failed to create the buffer");
-
-    let command_buffer = AutoCommandBufferBuilder::new(device.clone(), queue.family())
-        .unwrap()
-        .dispatch([size_x / 8, size_y / 8, 1], compute_pipeline.clone(), set.clone(), ())
-        .unwrap()
-        .copy_image_to_buffer(image.clone(), buffer.clone())
-        .unwrap()
-        .build()
-        .unwrap();
-
-    // execute the commands
-    let finished = command_buffer.execute(queue.clone()).unwrap();
-    finished
-        .then_signal_fence_and_flush()
-        .unwrap()
-        .wait(None)
-        .unwrap();
-
-    // save buffer to an image file
-    let buffer_content = buffer.read().unwrap();
-    let image = ImageBuffer::<Rgba<u8>, _>::from_raw(size_x, size_y, &buffer_content[..]).unwrap();
-    image.save("image.png").unwrap();
-}
+    if let Some(ref path) = options.animate {
+        let keyframes = animation::load_keyframes(path).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            ::std::process::exit(1);
+        });
+        animation::render_animation(&renderer, options, &keyframes);
+        return;
+    }
 
-fn init() -> (Arc<Device>, QueuesIter) {
-    // Create an instance of the vulkan API
-    let instance =
-        Instance::new(None, &InstanceExtensions::none(), None).expect("failed to create instance");
+    let mut heatmap = options.heatmap.as_ref().map(|path| {
+        HeatMap::create(path, options.width, options.height, options.iterations)
+            .expect("failed to create the heat map")
+    });
+
+    renderer
+        .render_to_file(
+            &options.params(),
+            options.width,
+            options.height,
+            &options.output,
+            heatmap.as_mut(),
+        ).expect("failed to write the image");
+
+    if let Some(heatmap) = heatmap {
+        let stats = heatmap.finish().expect("failed to write the heat map");
+        println!("{}", stats);
+    }
+}
 
-    // List all the physical devices that support vulkan
-    for physical_device in PhysicalDevice::enumerate(&instance) {
-        println!("Available device: {}", physical_device.name());
+/// Compares the image written to `output` with `reference`, exiting with an
+/// error when they are too far apart.
+fn check(output: &str, reference: &str) {
+    let rendered = image::open(output)
+        .unwrap_or_else(|err| {
+            eprintln!("failed to load {}: {}", output, err);
+            ::std::process::exit(1);
+        }).to_rgba();
+
+    match golden::check(&rendered, reference, &golden::Tolerance::default()) {
+        Ok(comparison) => println!("matches {}: {}", reference, comparison),
+        Err(err) => {
+            eprintln!("{}", err);
+            ::std::process::exit(1);
+        }
     }
+}
 
-    // now we just get the first
-    let physical = PhysicalDevice::from_index(&instance, 0).expect("no device available");
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // list all the queue families available for the device
-    for family in physical.queue_families() {
-        println!(
-            "Found a queue family with {:?} queue(s)",
-            family.queues_count()
-        );
-    }
+    use image::RgbaImage;
+
+    use std::path::Path;
 
-    // select a queue that supports graphical operations
-    let queue_family = physical
-        .queue_families()
-        .find(|&q| q.supports_graphics())
-        .expect("couldn't find a graphical queue family");
-
-    let (device, queues) = {
-        Device::new(
-            physical,
-            &Features::none(),
-            &DeviceExtensions::none(),
-            [(queue_family, 0.5)].iter().cloned(),
-        ).expect("failed to create device")
-    };
-
-    (device, queues)
+    // The render needs a Vulkan device, it only runs with
+    // `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn default_image_matches_the_reference() {
+        let options = Options::default();
+        let (device, mut queues) = core::init(&options.format.required_features());
+        let queue = queues.next().expect("Couldn't get the first queue");
+        let renderer = TileRenderer::new(device, queue, options.tile_size, &options.polynomial, options.format);
+
+        let pixels = renderer.render_image(&options.params(), options.width, options.height);
+        let image = RgbaImage::from_raw(options.width, options.height, pixels).unwrap();
+        let reference = Path::new(env!("CARGO_MANIFEST_DIR")).join("image.png");
+        golden::check(&image, reference, &golden::Tolerance::default()).unwrap();
+    }
 }
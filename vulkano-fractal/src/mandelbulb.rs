@@ -0,0 +1,156 @@
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::CpuAccessibleBuffer;
+
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::command_buffer::CommandBuffer;
+
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+
+use vulkano::device::Device;
+use vulkano::device::Queue;
+
+use vulkano::format::Format;
+
+use vulkano::image::Dimensions;
+use vulkano::image::StorageImage;
+
+use vulkano::pipeline::ComputePipeline;
+
+use vulkano::sync::GpuFuture;
+
+use std::sync::Arc;
+
+use options::{Mode, Options};
+use png;
+use shaders;
+
+// Samples raymarched by a single dispatch, small enough to stay clear of the
+// driver timeouts: each one takes up to 300 steps, then a shadow ray and
+// ambient occlusion.
+const SAMPLES_PER_DISPATCH: u32 = 256 * 1024;
+
+/// Where the camera is and what it looks at.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    /// Vertical field of view, in degrees.
+    pub fov: f32,
+}
+
+impl Camera {
+    /// A camera framing the whole fractal of the given mode.
+    pub fn default_for(mode: Mode) -> Camera {
+        let position = match mode {
+            Mode::Mandelbox => [0.0, 5.0, -14.0],
+            _ => [0.0, 1.2, -2.6],
+        };
+
+        Camera {
+            position,
+            target: [0.0, 0.0, 0.0],
+            fov: 45.0,
+        }
+    }
+}
+
+/// Raymarches the Mandelbulb, or the Mandelbox, using their distance
+/// estimators, with soft shadows and ambient occlusion.
+pub fn render(device: Arc<Device>, queue: Arc<Queue>, options: &Options) {
+    let (width, height) = (options.width, options.height);
+
+    let max_size = device.physical_device().limits().max_image_dimension_2d();
+    if width > max_size || height > max_size {
+        eprintln!("the {:?} can't be bigger than {}x{} on this device", options.mode, max_size, max_size);
+        ::std::process::exit(1);
+    }
+
+    // create the compute pipeline
+    let shader = shaders::raymarch::Shader::load(device.clone()).expect("failed to load shader module");
+    let compute_pipeline = Arc::new(
+        ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+            .expect("failed to create compute pipeline"),
+    );
+
+    // allocate an image
+    let image = StorageImage::new(
+        device.clone(),
+        Dimensions::Dim2d { width, height },
+        Format::R8G8B8A8Unorm,
+        Some(queue.family()),
+    ).unwrap();
+
+    // bind the image to the shade with a descriptor set
+    let set = Arc::new(
+        PersistentDescriptorSet::start(compute_pipeline.clone(), 0)
+            .add_image(image.clone())
+            .unwrap()
+            .build()
+            .unwrap(),
+    );
+
+    // Create a buffer to read the resulting image
+    let buffer = CpuAccessibleBuffer::from_iter(
+        device.clone(),
+        BufferUsage::all(),
+        (0 .. width * height * 4).map(|_| 0u8),
+    ).expect("failed to create the buffer");
+
+    // the image is rendered in bands of rows, a multiple of the work group
+    // height, one dispatch at a time
+    let samples = width * options.samples * options.samples;
+    let band = (SAMPLES_PER_DISPATCH / samples.max(1) / 8).max(1) * 8;
+
+    let camera = &options.camera;
+    let push_constants = shaders::raymarch::ty::PushConstantData {
+        camera: [camera.position[0], camera.position[1], camera.position[2], 1.0],
+        target: [camera.target[0], camera.target[1], camera.target[2], 1.0],
+        fov: camera.fov.to_radians(),
+        power: options.power,
+        fractal: match options.mode {
+            Mode::Mandelbox => 1,
+            _ => 0,
+        },
+        samples: options.samples,
+        first_row: 0,
+    };
+
+    for first_row in (0 .. height).step_by(band as usize) {
+        let rows = band.min(height - first_row);
+        let builder = AutoCommandBufferBuilder::new(device.clone(), queue.family())
+            .unwrap()
+            .dispatch(
+                [(width + 7) / 8, (rows + 7) / 8, 1],
+                compute_pipeline.clone(),
+                set.clone(),
+                shaders::raymarch::ty::PushConstantData {
+                    first_row,
+                    ..push_constants
+                },
+            ).unwrap();
+        execute(builder, &queue);
+
+        println!("Rendered {} of {} rows", first_row + rows, height);
+    }
+
+    let builder = AutoCommandBufferBuilder::new(device.clone(), queue.family())
+        .unwrap()
+        .copy_image_to_buffer(image.clone(), buffer.clone())
+        .unwrap();
+    execute(builder, &queue);
+
+    // save buffer to an image file
+    let buffer_content = buffer.read().unwrap();
+    png::write_png(&options.output, width, height, &buffer_content[..])
+        .expect("failed to write the image");
+}
+
+fn execute(builder: AutoCommandBufferBuilder, queue: &Arc<Queue>) {
+    let command_buffer = builder.build().unwrap();
+    let finished = command_buffer.execute(queue.clone()).unwrap();
+    finished
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+}
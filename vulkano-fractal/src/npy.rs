@@ -0,0 +1,111 @@
+use std::io;
+use std::io::Write;
+
+/// Writes a NumPy `.npy` file holding a `height x width x channels` array of
+/// 32 bit floats, row by row.
+pub struct NpyStreamWriter<W: Write> {
+    inner: W,
+    row_len: usize,
+    rows_left: u32,
+}
+
+impl<W: Write> NpyStreamWriter<W> {
+    pub fn new(mut inner: W, width: u32, height: u32, channels: u32) -> io::Result<NpyStreamWriter<W>> {
+        // the values are written as they come from the device, in native endianness
+        let descr = if cfg!(target_endian = "little") {
+            "<f4"
+        } else {
+            ">f4"
+        };
+        let mut header = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}, {}), }}",
+            descr, height, width, channels
+        );
+
+        // magic, version and header length take 10 bytes, the whole header
+        // is padded with spaces to a multiple of 64 and ends with a newline
+        let len = 10 + header.len() + 1;
+        let padded = (len + 63) / 64 * 64;
+        header.extend((len .. padded).map(|_| ' '));
+        header.push('\n');
+
+        inner.write_all(b"\x93NUMPY\x01\x00")?;
+        let header_len = header.len() as u16;
+        inner.write_all(&[header_len as u8, (header_len >> 8) as u8])?;
+        inner.write_all(header.as_bytes())?;
+
+        Ok(NpyStreamWriter {
+            inner,
+            row_len: width as usize * channels as usize * 4,
+            rows_left: height,
+        })
+    }
+
+    /// Appends the next row, `row` must contain `width x channels` floats.
+    pub fn write_row(&mut self, row: &[u8]) -> io::Result<()> {
+        assert_eq!(row.len(), self.row_len, "row has the wrong size");
+        assert!(self.rows_left > 0, "too many rows written");
+        self.rows_left -= 1;
+
+        self.inner.write_all(row)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        assert_eq!(self.rows_left, 0, "not all the rows have been written");
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_len(file: &[u8]) -> usize {
+        file[8] as usize | (file[9] as usize) << 8
+    }
+
+    #[test]
+    fn headers_are_padded_to_64_bytes() {
+        for &(width, height, channels) in &[(1, 1, 1), (640, 480, 3), (100_000, 3, 4), (12_345_678, 9_876_543, 3)] {
+            let file = NpyStreamWriter::new(Vec::new(), width, height, channels)
+                .unwrap()
+                .inner;
+            let len = header_len(&file);
+
+            assert_eq!(&file[.. 8], b"\x93NUMPY\x01\x00");
+            assert_eq!(file.len(), 10 + len);
+            assert_eq!(file.len() % 64, 0);
+            assert_eq!(file[file.len() - 1], b'\n');
+
+            let header = String::from_utf8(file[10 ..].to_vec()).unwrap();
+            let descr = if cfg!(target_endian = "little") { "<f4" } else { ">f4" };
+            let dictionary = format!(
+                "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}, {}), }}",
+                descr, height, width, channels
+            );
+            assert_eq!(header.trim(), dictionary);
+        }
+    }
+
+    #[test]
+    fn rows_follow_the_header() {
+        let mut writer = NpyStreamWriter::new(Vec::new(), 2, 3, 1).unwrap();
+        for row in 0 .. 3u8 {
+            writer.write_row(&[row; 8]).unwrap();
+        }
+        let file = writer.finish().unwrap();
+
+        let data = &file[10 + header_len(&file) ..];
+        assert_eq!(data.len(), 3 * 8);
+        assert!(data.chunks(8).enumerate().all(|(row, values)| values.iter().all(|&value| value == row as u8)));
+    }
+
+    #[test]
+    #[should_panic(expected = "not all the rows have been written")]
+    fn missing_rows_are_caught() {
+        let mut writer = NpyStreamWriter::new(Vec::new(), 1, 2, 1).unwrap();
+        writer.write_row(&[0; 4]).unwrap();
+        writer.finish().unwrap();
+    }
+}
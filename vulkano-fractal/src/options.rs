@@ -0,0 +1,623 @@
+use args::{parse_floats, parse_size, parse_value};
+
+use std::env;
+use std::path::Path;
+use std::str::FromStr;
+
+use mandelbulb::Camera;
+use output::OutputFormat;
+use polynomial::Polynomial;
+use render;
+use render::{Fractal, Params, Shading, Trap, View};
+
+const USAGE: &str = "usage: vulkan-fractal [OPTIONS] [OUTPUT]
+
+Renders the fractal to OUTPUT (default image.png). The mandelbrot and newton
+fractals can also be written as float OpenEXR files (.exr) or as NumPy arrays
+(.npy) holding the iteration count, the final |z| (or the index of the root
+reached) and whether each pixel escaped (or converged).
+
+options:
+    --mode MODE    fractal to render: mandelbrot, newton, buddhabrot,
+                   nebulabrot, mandelbulb or mandelbox (default mandelbrot)
+    --size WxH     size of the rendered image (default 1024x1024)
+    --tile N       render tiles of N x N pixels at a time (default 1024)
+    --iterations N maximum number of iterations per point, or auto to derive
+                   it from --zoom for the mandelbrot set (default 200)
+    --progressive  iterate the mandelbrot set in passes of growing iteration
+                   budgets, only the pixels still undecided after a pass are
+                   iterated again; the image is the same but deep views with
+                   many iterations render much faster
+    --samples N    render N x N samples per pixel and average them (default 1)
+                   .npy files only take a single sample
+    --jitter       randomly offset each sample inside its cell
+    --center X,Y   point of the complex plane at the center of the image
+                   (default -1,0, or 0,0 for the newton fractal)
+    --zoom Z       magnification, at 1 the image is 2 units tall (default 1)
+    --rotation D   rotation of the view in degrees (default 0)
+    --palette-offset P
+                   shift the colour palette by P (default 0)
+    --bit-depth N  bits per channel of PNG files, 8 or 16 (default 8)
+    --fragment     draw the mandelbrot set with a fragment shader on a
+                   fullscreen triangle instead of the compute shader
+    --bench N      render the mandelbrot set N times with both the compute
+                   and the fragment shader and compare their throughput
+    --heatmap PATH also write a PNG heat map of the iterations spent on each
+                   pixel and print their mean, 99th percentile and the
+                   fraction of pixels reaching --iterations
+    --check REF    compare the rendered 8 bit PNG with the reference image
+                   REF and fail when they differ, writing the differences
+                   next to REF as REF-diff.png
+
+mandelbrot shading:
+    --shading S    what the colours are derived from: escape (the escape
+                   time), distance (the estimated distance to the set),
+                   point-trap, line-trap or cross-trap (how close the orbits
+                   come to the trap) (default escape)
+    --trap X,Y     point of the complex plane the traps go through (default 0,0)
+    --trap-angle D angle of the line and cross traps in degrees (default 0)
+
+animation:
+    --animate FILE render the keyframed path described in FILE, frames are
+                   written next to OUTPUT as numbered PNG files
+    --fps N        frames per second of the animation (default 30)
+    --gif PATH     also assemble the frames into an animated GIF, at most
+                   65535x65535 and 50 frames per second
+
+newton:
+    --polynomial C comma separated coefficients of the polynomial, from the
+                   highest degree down, either real or complex like 1-2i
+                   (default 1,0,0,-1 that is z^3 - 1)
+
+buddhabrot and nebulabrot:
+    --orbits N     millions of random points whose orbits are traced (default 20),
+                   the nebulabrot uses 10x, 1x and 0.1x --iterations for the
+                   red, green and blue channels
+
+mandelbulb and mandelbox:
+    --camera X,Y,Z position of the camera (default 0,1.2,-2.6 for the
+                   mandelbulb and 0,5,-14 for the mandelbox)
+    --target X,Y,Z point the camera looks at (default 0,0,0)
+    --fov D        vertical field of view in degrees (default 45)
+    --power P      exponent of the mandelbulb formula (default 8)
+
+explorer:
+    --explore      open a window to explore the fractal: drag to pan, scroll
+                   to zoom, up/down to change the iterations, R to reset the
+                   view and S to save it at --size height next to OUTPUT
+
+tile server:
+    --serve ADDR   serve the mandelbrot or newton fractal as /z/x/y.png tiles
+                   on ADDR (like 127.0.0.1:8080), with a viewer at /; level 0
+                   is a single tile twice as tall as the --center/--zoom view
+    --cache N      number of tiles kept in memory (default 1024)";
+
+/// The kind of fractal being rendered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Mandelbrot,
+    Newton,
+    Buddhabrot,
+    Nebulabrot,
+    Mandelbulb,
+    Mandelbox,
+}
+
+impl FromStr for Mode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Mode, ()> {
+        match s {
+            "mandelbrot" => Ok(Mode::Mandelbrot),
+            "newton" => Ok(Mode::Newton),
+            "buddhabrot" => Ok(Mode::Buddhabrot),
+            "nebulabrot" => Ok(Mode::Nebulabrot),
+            "mandelbulb" => Ok(Mode::Mandelbulb),
+            "mandelbox" => Ok(Mode::Mandelbox),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Command line options of the fractal renderer.
+pub struct Options {
+    pub mode: Mode,
+    pub output: String,
+    pub bit_depth: u32,
+    pub format: OutputFormat,
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: u32,
+    pub iterations: u32,
+    pub auto_iterations: bool,
+    pub progressive: bool,
+    pub fragment: bool,
+    pub bench: u32,
+    pub heatmap: Option<String>,
+    pub check: Option<String>,
+    pub samples: u32,
+    pub jitter: bool,
+    pub view: View,
+    pub shading: Shading,
+    pub trap: Trap,
+    pub animate: Option<String>,
+    pub fps: u32,
+    pub gif: Option<String>,
+    pub explore: bool,
+    pub serve: Option<String>,
+    pub cache_size: usize,
+    pub orbits: u64,
+    pub polynomial: Polynomial,
+    pub camera: Camera,
+    pub power: f32,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            mode: Mode::Mandelbrot,
+            output: "image.png".to_owned(),
+            bit_depth: 8,
+            format: OutputFormat::Png8,
+            width: 1024,
+            height: 1024,
+            tile_size: 1024,
+            iterations: 200,
+            auto_iterations: false,
+            progressive: false,
+            fragment: false,
+            bench: 0,
+            heatmap: None,
+            check: None,
+            samples: 1,
+            jitter: false,
+            view: View::default(),
+            shading: Shading::EscapeTime,
+            trap: Trap::default(),
+            animate: None,
+            fps: 30,
+            gif: None,
+            explore: false,
+            serve: None,
+            cache_size: 1024,
+            orbits: 20,
+            polynomial: Polynomial::default(),
+            camera: Camera::default_for(Mode::Mandelbulb),
+            power: 8.0,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the options from the process arguments, exiting with the usage
+    /// message when they are not valid.
+    pub fn from_args() -> Options {
+        match Options::parse(env::args().skip(1)) {
+            Ok(options) => options,
+            Err(err) => {
+                eprintln!("{}\n\n{}", err, USAGE);
+                ::std::process::exit(1);
+            }
+        }
+    }
+
+    pub fn parse<I>(args: I) -> Result<Options, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        let mut output = None;
+        let mut center = None;
+        let mut camera = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--mode" => options.mode = parse_value(&arg, args.next())?,
+                "--size" => {
+                    let size = args.next();
+                    let (width, height) = parse_size(&arg, size)?;
+                    options.width = width;
+                    options.height = height;
+                }
+                "--tile" => {
+                    options.tile_size = parse_value(&arg, args.next())?;
+                    if options.tile_size == 0 {
+                        return Err("--tile must be at least 1".to_owned());
+                    }
+                }
+                "--iterations" => {
+                    let value = args.next();
+                    if value.as_ref().map(|v| &v[..]) == Some("auto") {
+                        options.auto_iterations = true;
+                    } else {
+                        options.iterations = parse_value(&arg, value)?;
+                        options.auto_iterations = false;
+                        if options.iterations == 0 {
+                            return Err("--iterations must be at least 1".to_owned());
+                        }
+                    }
+                }
+                "--progressive" => options.progressive = true,
+                "--fragment" => options.fragment = true,
+                "--heatmap" => options.heatmap = Some(parse_value(&arg, args.next())?),
+                "--check" => options.check = Some(parse_value(&arg, args.next())?),
+                "--bench" => {
+                    options.bench = parse_value(&arg, args.next())?;
+                    if options.bench == 0 {
+                        return Err("--bench must be at least 1".to_owned());
+                    }
+                }
+                "--samples" => {
+                    options.samples = parse_value(&arg, args.next())?;
+                    if options.samples == 0 {
+                        return Err("--samples must be at least 1".to_owned());
+                    }
+                }
+                "--jitter" => options.jitter = true,
+                "--bit-depth" => options.bit_depth = parse_value(&arg, args.next())?,
+                "--center" => {
+                    let value = parse_floats(&arg, args.next(), 2)?;
+                    center = Some([value[0], value[1]]);
+                }
+                "--zoom" => {
+                    options.view.zoom = parse_value(&arg, args.next())?;
+                    if options.view.zoom <= 0.0 {
+                        return Err("--zoom must be positive".to_owned());
+                    }
+                }
+                "--rotation" => options.view.rotation = parse_value(&arg, args.next())?,
+                "--palette-offset" => {
+                    options.view.palette_offset = parse_value(&arg, args.next())?
+                }
+                "--shading" => options.shading = parse_value(&arg, args.next())?,
+                "--trap" => {
+                    let value = parse_floats(&arg, args.next(), 2)?;
+                    options.trap.point = [value[0], value[1]];
+                }
+                "--trap-angle" => options.trap.angle = parse_value(&arg, args.next())?,
+                "--animate" => options.animate = Some(parse_value(&arg, args.next())?),
+                "--fps" => {
+                    options.fps = parse_value(&arg, args.next())?;
+                    if options.fps == 0 {
+                        return Err("--fps must be at least 1".to_owned());
+                    }
+                }
+                "--gif" => options.gif = Some(parse_value(&arg, args.next())?),
+                "--explore" => options.explore = true,
+                "--serve" => options.serve = Some(parse_value(&arg, args.next())?),
+                "--cache" => options.cache_size = parse_value(&arg, args.next())?,
+                "--polynomial" => {
+                    let value = args.next().ok_or_else(|| format!("missing value for {}", arg))?;
+                    options.polynomial = Polynomial::parse(&value)?;
+                }
+                "--camera" => {
+                    let value = parse_floats(&arg, args.next(), 3)?;
+                    camera = Some([value[0], value[1], value[2]]);
+                }
+                "--target" => {
+                    let value = parse_floats(&arg, args.next(), 3)?;
+                    options.camera.target = [value[0], value[1], value[2]];
+                }
+                "--fov" => {
+                    options.camera.fov = parse_value(&arg, args.next())?;
+                    if options.camera.fov <= 0.0 || options.camera.fov >= 180.0 {
+                        return Err("--fov must be between 0 and 180 degrees".to_owned());
+                    }
+                }
+                "--power" => options.power = parse_value(&arg, args.next())?,
+                "--orbits" => {
+                    options.orbits = parse_value(&arg, args.next())?;
+                    if options.orbits == 0 {
+                        return Err("--orbits must be at least 1".to_owned());
+                    }
+                }
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    ::std::process::exit(0);
+                }
+                _ if !arg.starts_with('-') && output.is_none() => output = Some(arg.clone()),
+                _ => return Err(format!("unknown argument '{}'", arg)),
+            }
+        }
+
+        if let Some(output) = output {
+            options.output = output;
+        }
+
+        // only the escape time renderer writes something else than 8 bit PNG files
+        options.format = OutputFormat::from_path(&options.output, options.bit_depth)?;
+        let escape_time = options.mode == Mode::Mandelbrot || options.mode == Mode::Newton;
+        let single_image = options.animate.is_none() && !options.explore && options.serve.is_none();
+        if options.format != OutputFormat::Png8 && !(escape_time && single_image) {
+            return Err(format!(
+                "only single mandelbrot and newton images can be written as {:?}",
+                options.format
+            ));
+        }
+
+        // the raw data of several samples can't be averaged, the root reached
+        // and whether the point escaped are per sample
+        if options.format.is_raw() && options.samples != 1 {
+            return Err("--samples must be 1 when writing .npy files".to_owned());
+        }
+
+        if options.heatmap.is_some()
+            && !(escape_time && single_image && !options.progressive && !options.fragment && options.bench == 0)
+        {
+            return Err("--heatmap only applies to single mandelbrot and newton images".to_owned());
+        }
+
+        if options.check.is_some() && !(single_image && options.format == OutputFormat::Png8 && options.bench == 0) {
+            return Err("--check only applies to single images written as 8 bit PNG".to_owned());
+        }
+
+        // GIF frames are at most 65535 pixels wide and last a whole number of
+        // hundredths of a second, the browsers slow down the ones of a single
+        // hundredth
+        if options.gif.is_some() {
+            if options.fps > 50 {
+                return Err("--fps can be at most 50 with --gif".to_owned());
+            }
+            if options.width > u32::from(u16::max_value()) || options.height > u32::from(u16::max_value()) {
+                return Err(format!("--gif frames can be at most {0}x{0}", u16::max_value()));
+            }
+        }
+
+        if options.serve.is_some() && !escape_time {
+            return Err("--serve only renders the mandelbrot and newton fractals".to_owned());
+        }
+
+        if options.explore && !escape_time {
+            return Err("--explore only renders the mandelbrot and newton fractals".to_owned());
+        }
+
+        if options.shading != Shading::EscapeTime && options.mode != Mode::Mandelbrot {
+            return Err("--shading only applies to the mandelbrot set".to_owned());
+        }
+
+        // the fragment shader only knows the escape time of the mandelbrot set
+        if (options.fragment || options.bench > 0)
+            && (options.mode != Mode::Mandelbrot
+                || options.shading != Shading::EscapeTime
+                || options.format != OutputFormat::Png8
+                || options.progressive)
+        {
+            return Err(
+                "--fragment and --bench only render the escape time of the mandelbrot set to 8 bit PNG"
+                    .to_owned(),
+            );
+        }
+
+        if options.auto_iterations {
+            if options.mode != Mode::Mandelbrot {
+                return Err("--iterations auto only applies to the mandelbrot set".to_owned());
+            }
+            options.iterations = render::auto_iterations(options.view.zoom);
+        }
+
+        if options.progressive
+            && (options.mode != Mode::Mandelbrot
+                || options.shading != Shading::EscapeTime
+                || options.samples != 1
+                || options.format.is_raw())
+        {
+            return Err(
+                "--progressive only renders the escape time of the mandelbrot set with a single sample"
+                    .to_owned(),
+            );
+        }
+
+        // the interesting part of the newton fractal is around the origin
+        options.view.center = match (center, options.mode) {
+            (Some(center), _) => center,
+            (None, Mode::Newton) => [0.0, 0.0],
+            (None, _) => options.view.center,
+        };
+
+        // the mandelbox is much bigger than the mandelbulb
+        options.camera.position =
+            camera.unwrap_or_else(|| Camera::default_for(options.mode).position);
+        if options.camera.position == options.camera.target {
+            return Err("--camera and --target must be different points".to_owned());
+        }
+
+        Ok(options)
+    }
+
+    /// Derives the name of the `index`-th file from the output path, so that
+    /// `image.png` becomes `image-00000.png` and so on.
+    pub fn numbered_output(&self, index: usize) -> String {
+        let path = Path::new(&self.output);
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "image".to_owned());
+
+        path.with_file_name(format!("{}-{:05}.png", stem, index))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// The fractal parameters selected on the command line.
+    pub fn params(&self) -> Params {
+        Params {
+            fractal: match self.mode {
+                Mode::Newton => Fractal::Newton,
+                _ => Fractal::Mandelbrot,
+            },
+            view: self.view,
+            iterations: self.iterations,
+            samples: self.samples,
+            jitter: self.jitter,
+            shading: self.shading,
+            trap: self.trap,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        Options::parse(args.split_whitespace().map(|arg| arg.to_owned()))
+    }
+
+    fn error(args: &str) -> String {
+        match parse(args) {
+            Ok(_) => panic!("'{}' should be rejected", args),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn defaults_render_the_mandelbrot_set() {
+        let options = parse("").unwrap();
+        assert_eq!(options.mode, Mode::Mandelbrot);
+        assert_eq!(options.output, "image.png");
+        assert_eq!(options.format, OutputFormat::Png8);
+        assert_eq!((options.width, options.height, options.tile_size), (1024, 1024, 1024));
+        assert_eq!(options.iterations, 200);
+        assert!(!options.auto_iterations);
+        assert_eq!(options.samples, 1);
+        assert_eq!(options.view, View::default());
+        assert_eq!(options.view.center, [-1.0, 0.0]);
+        assert_eq!(options.shading, Shading::EscapeTime);
+        assert_eq!(options.fps, 30);
+        assert_eq!(options.cache_size, 1024);
+        assert_eq!(options.orbits, 20);
+        assert_eq!(options.camera.position, [0.0, 1.2, -2.6]);
+        assert_eq!(options.params().fractal, Fractal::Mandelbrot);
+    }
+
+    #[test]
+    fn defaults_follow_the_mode() {
+        let newton = parse("--mode newton").unwrap();
+        assert_eq!(newton.view.center, [0.0, 0.0]);
+        assert_eq!(newton.params().fractal, Fractal::Newton);
+        assert_eq!(parse("--mode newton --center 1,2").unwrap().view.center, [1.0, 2.0]);
+
+        assert_eq!(parse("--mode mandelbox").unwrap().camera.position, [0.0, 5.0, -14.0]);
+        assert_eq!(parse("--mode mandelbox --camera 1,2,3").unwrap().camera.position, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn options_are_read() {
+        let options = parse("--size 640x480 --tile 128 --samples 3 --jitter --zoom 4 --rotation 30 zoom.png").unwrap();
+        assert_eq!(options.output, "zoom.png");
+        assert_eq!((options.width, options.height, options.tile_size), (640, 480, 128));
+        assert_eq!(options.samples, 3);
+        assert!(options.jitter);
+        assert_eq!((options.view.zoom, options.view.rotation), (4.0, 30.0));
+
+        assert_eq!(parse("--bit-depth 16 deep.png").unwrap().format, OutputFormat::Png16);
+        assert_eq!(parse("data.npy").unwrap().format, OutputFormat::Npy);
+        assert_eq!(parse("--gif zoom.gif --fps 50").unwrap().fps, 50);
+    }
+
+    #[test]
+    fn iterations_follow_the_zoom_when_automatic() {
+        let options = parse("--iterations auto --zoom 1000").unwrap();
+        assert!(options.auto_iterations);
+        assert_eq!(options.iterations, render::auto_iterations(1000.0));
+        assert_eq!(options.iterations, 1499);
+
+        // the zoom may come first, and a zoom below 1 keeps the default
+        assert_eq!(parse("--zoom 1000 --iterations auto").unwrap().iterations, 1499);
+        assert_eq!(parse("--zoom 0.5 --iterations auto").unwrap().iterations, 200);
+
+        // the last --iterations wins
+        let options = parse("--iterations auto --iterations 50").unwrap();
+        assert!(!options.auto_iterations);
+        assert_eq!(options.iterations, 50);
+        assert!(parse("--iterations 50 --iterations auto").unwrap().auto_iterations);
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let cases = [
+            ("--mode julia", "invalid value 'julia' for --mode"),
+            ("--size", "missing value for --size"),
+            ("--tile 0", "--tile must be at least 1"),
+            ("--iterations 0", "--iterations must be at least 1"),
+            ("--iterations many", "invalid value 'many' for --iterations"),
+            ("--bench 0", "--bench must be at least 1"),
+            ("--samples 0", "--samples must be at least 1"),
+            ("--zoom 0", "--zoom must be positive"),
+            ("--zoom -2", "--zoom must be positive"),
+            ("--fps 0", "--fps must be at least 1"),
+            ("--fov 0", "--fov must be between 0 and 180 degrees"),
+            ("--fov 180", "--fov must be between 0 and 180 degrees"),
+            ("--orbits 0", "--orbits must be at least 1"),
+            ("--polynomial", "missing value for --polynomial"),
+            ("--shading flat", "invalid value 'flat' for --shading"),
+            ("--frobnicate", "unknown argument '--frobnicate'"),
+            ("a.png b.png", "unknown argument 'b.png'"),
+            ("--bit-depth 12", "PNG files can't have 12 bits per channel"),
+            ("image.jpg", "can't tell the format of 'image.jpg', use .png, .exr or .npy"),
+        ];
+        for &(args, message) in &cases {
+            assert_eq!(error(args), message, "{}", args);
+        }
+    }
+
+    #[test]
+    fn conflicting_options_are_rejected() {
+        let cases = [
+            (
+                "--mode buddhabrot image.exr",
+                "only single mandelbrot and newton images can be written as Exr",
+            ),
+            (
+                "--explore --bit-depth 16",
+                "only single mandelbrot and newton images can be written as Png16",
+            ),
+            ("--samples 2 data.npy", "--samples must be 1 when writing .npy files"),
+            (
+                "--heatmap heat.png --progressive",
+                "--heatmap only applies to single mandelbrot and newton images",
+            ),
+            ("--heatmap heat.png --mode mandelbulb", "--heatmap only applies to single mandelbrot and newton images"),
+            ("--check reference.png --bit-depth 16", "--check only applies to single images written as 8 bit PNG"),
+            ("--check reference.png --bench 3", "--check only applies to single images written as 8 bit PNG"),
+            ("--gif zoom.gif --fps 51", "--fps can be at most 50 with --gif"),
+            ("--gif zoom.gif --size 65536x100", "--gif frames can be at most 65535x65535"),
+            ("--serve 127.0.0.1:8080 --mode mandelbulb", "--serve only renders the mandelbrot and newton fractals"),
+            ("--explore --mode nebulabrot", "--explore only renders the mandelbrot and newton fractals"),
+            ("--mode newton --shading distance", "--shading only applies to the mandelbrot set"),
+            (
+                "--fragment --mode newton",
+                "--fragment and --bench only render the escape time of the mandelbrot set to 8 bit PNG",
+            ),
+            (
+                "--bench 2 --shading point-trap",
+                "--fragment and --bench only render the escape time of the mandelbrot set to 8 bit PNG",
+            ),
+            (
+                "--fragment --progressive",
+                "--fragment and --bench only render the escape time of the mandelbrot set to 8 bit PNG",
+            ),
+            ("--mode newton --iterations auto", "--iterations auto only applies to the mandelbrot set"),
+            (
+                "--progressive --samples 2",
+                "--progressive only renders the escape time of the mandelbrot set with a single sample",
+            ),
+            (
+                "--progressive --shading distance",
+                "--progressive only renders the escape time of the mandelbrot set with a single sample",
+            ),
+            ("--camera 1,2,3 --target 1,2,3", "--camera and --target must be different points"),
+        ];
+        for &(args, message) in &cases {
+            assert_eq!(error(args), message, "{}", args);
+        }
+    }
+
+    #[test]
+    fn numbered_outputs_keep_the_directory() {
+        let options = parse("frames/zoom.png").unwrap();
+        assert_eq!(options.numbered_output(3), Path::new("frames").join("zoom-00003.png").to_string_lossy());
+        assert_eq!(parse("").unwrap().numbered_output(12345), "image-12345.png");
+    }
+}
@@ -0,0 +1,157 @@
+use vulkano::format::Format;
+
+use vulkano::instance::Features;
+
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::Path;
+
+use exr::ExrStreamWriter;
+use npy::NpyStreamWriter;
+use png::PngStreamWriter;
+
+/// The kinds of file the escape time renderer writes, chosen from the
+/// extension of the output path.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Png8,
+    Png16,
+    /// 32 bit float OpenEXR.
+    Exr,
+    /// Raw per pixel data instead of colours: the iteration count, the final
+    /// magnitude of `z` (or the root reached by the Newton fractal) and whether
+    /// the point escaped (or converged), as a `height x width x 3` NumPy array.
+    Npy,
+}
+
+impl OutputFormat {
+    /// `bit_depth` only matters for PNG files, where it can be 8 or 16.
+    pub fn from_path(path: &str, bit_depth: u32) -> Result<OutputFormat, String> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+
+        match (extension.as_ref().map(|e| &e[..]), bit_depth) {
+            (Some("png"), 8) => Ok(OutputFormat::Png8),
+            (Some("png"), 16) => Ok(OutputFormat::Png16),
+            (Some("png"), _) => Err(format!("PNG files can't have {} bits per channel", bit_depth)),
+            (Some("exr"), _) => Ok(OutputFormat::Exr),
+            (Some("npy"), _) => Ok(OutputFormat::Npy),
+            _ => Err(format!("can't tell the format of '{}', use .png, .exr or .npy", path)),
+        }
+    }
+
+    /// Format of the image the colours are rendered to.
+    pub fn image_format(&self) -> Format {
+        match *self {
+            OutputFormat::Png8 | OutputFormat::Npy => Format::R8G8B8A8Unorm,
+            OutputFormat::Png16 => Format::R16G16B16A16Unorm,
+            OutputFormat::Exr => Format::R32G32B32A32Sfloat,
+        }
+    }
+
+    /// Device features the shaders need to write the image, 16 bit storage
+    /// images are an extended format.
+    pub fn required_features(&self) -> Features {
+        match *self {
+            OutputFormat::Png16 => Features {
+                shader_storage_image_extended_formats: true,
+                ..Features::none()
+            },
+            _ => Features::none(),
+        }
+    }
+
+    /// Whether the file holds the raw data rather than the colours.
+    pub fn is_raw(&self) -> bool {
+        *self == OutputFormat::Npy
+    }
+
+    /// Size of the pixels read back from the device, the raw data is stored
+    /// as four 32 bit floats.
+    pub fn pixel_bytes(&self) -> usize {
+        match *self {
+            OutputFormat::Png8 => 4,
+            OutputFormat::Png16 => 8,
+            OutputFormat::Exr | OutputFormat::Npy => 16,
+        }
+    }
+}
+
+/// Streams rows to a file of any of the output formats.
+pub enum OutputWriter {
+    Png(PngStreamWriter<BufWriter<File>>),
+    Exr(ExrStreamWriter<BufWriter<File>>),
+    Npy(NpyStreamWriter<BufWriter<File>>),
+}
+
+impl OutputWriter {
+    pub fn create(path: &str, format: OutputFormat, width: u32, height: u32) -> io::Result<OutputWriter> {
+        let file = BufWriter::new(File::create(path)?);
+
+        Ok(match format {
+            OutputFormat::Png8 => OutputWriter::Png(PngStreamWriter::new(file, width, height)?),
+            OutputFormat::Png16 => {
+                OutputWriter::Png(PngStreamWriter::with_bit_depth(file, width, height, 16)?)
+            }
+            OutputFormat::Exr => OutputWriter::Exr(ExrStreamWriter::new(file, width, height)?),
+            OutputFormat::Npy => OutputWriter::Npy(NpyStreamWriter::new(file, width, height, 3)?),
+        })
+    }
+
+    /// Appends the next row, made of `width` pixels as read back from the
+    /// device for the format of the file.
+    pub fn write_row(&mut self, row: &[u8]) -> io::Result<()> {
+        match *self {
+            OutputWriter::Png(ref mut writer) => writer.write_row(row),
+            OutputWriter::Exr(ref mut writer) => writer.write_row(row),
+            OutputWriter::Npy(ref mut writer) => {
+                // the fourth float is padding
+                let values = row
+                    .chunks(16)
+                    .flat_map(|pixel| pixel[.. 12].iter().cloned())
+                    .collect::<Vec<_>>();
+                writer.write_row(&values)
+            }
+        }
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            OutputWriter::Png(writer) => writer.finish().map(|_| ()),
+            OutputWriter::Exr(writer) => writer.finish().map(|_| ()),
+            OutputWriter::Npy(writer) => writer.finish().map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extensions_tell_the_format() {
+        assert_eq!(OutputFormat::from_path("image.png", 8), Ok(OutputFormat::Png8));
+        assert_eq!(OutputFormat::from_path("out/image.png", 16), Ok(OutputFormat::Png16));
+        assert_eq!(OutputFormat::from_path("image.PNG", 8), Ok(OutputFormat::Png8));
+        assert_eq!(OutputFormat::from_path("image.exr", 8), Ok(OutputFormat::Exr));
+        assert_eq!(OutputFormat::from_path("image.Exr", 16), Ok(OutputFormat::Exr));
+        assert_eq!(OutputFormat::from_path("data.npy", 8), Ok(OutputFormat::Npy));
+    }
+
+    #[test]
+    fn unknown_formats_are_rejected() {
+        assert_eq!(
+            OutputFormat::from_path("image.png", 12),
+            Err("PNG files can't have 12 bits per channel".to_owned())
+        );
+        for path in &["image.jpg", "image", "png", "image.png.gz"] {
+            assert_eq!(
+                OutputFormat::from_path(path, 8),
+                Err(format!("can't tell the format of '{}', use .png, .exr or .npy", path))
+            );
+        }
+    }
+}
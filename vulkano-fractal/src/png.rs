@@ -0,0 +1,281 @@
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+// Size of the IDAT chunks we emit, the encoder never holds more than this in memory.
+const IDAT_SIZE: usize = 256 * 1024;
+
+/// Writes an RGBA PNG row by row, so that images much bigger than the
+/// available memory can be encoded while they are being rendered.
+pub struct PngStreamWriter<W: Write> {
+    encoder: ZlibEncoder<IdatWriter<W>>,
+    row_bytes: usize,
+    pixel_bytes: usize,
+    rows_left: u32,
+    filtered: Vec<u8>,
+}
+
+impl<W: Write> PngStreamWriter<W> {
+    pub fn new(inner: W, width: u32, height: u32) -> io::Result<PngStreamWriter<W>> {
+        PngStreamWriter::with_bit_depth(inner, width, height, 8)
+    }
+
+    /// Creates a writer for 8 or 16 bits per channel.
+    pub fn with_bit_depth(
+        mut inner: W,
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+    ) -> io::Result<PngStreamWriter<W>> {
+        assert!(bit_depth == 8 || bit_depth == 16, "unsupported bit depth");
+        inner.write_all(&SIGNATURE)?;
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&be_bytes(width));
+        header.extend_from_slice(&be_bytes(height));
+        // colour type 6 (RGBA), default compression and filter method, no interlace
+        header.extend_from_slice(&[bit_depth, 6, 0, 0, 0]);
+        write_chunk(&mut inner, b"IHDR", &header)?;
+
+        let pixel_bytes = bit_depth as usize / 2;
+        let row_bytes = width as usize * pixel_bytes;
+        Ok(PngStreamWriter {
+            encoder: ZlibEncoder::new(
+                IdatWriter {
+                    inner,
+                    buffer: Vec::with_capacity(IDAT_SIZE),
+                },
+                Compression::default(),
+            ),
+            row_bytes,
+            pixel_bytes,
+            rows_left: height,
+            filtered: Vec::with_capacity(row_bytes + 1),
+        })
+    }
+
+    /// Appends the next row of the image, `row` must contain exactly `width`
+    /// RGBA pixels. 16 bit channels are in native endianness.
+    pub fn write_row(&mut self, row: &[u8]) -> io::Result<()> {
+        assert_eq!(row.len(), self.row_bytes, "row has the wrong size");
+        assert!(self.rows_left > 0, "too many rows written");
+        self.rows_left -= 1;
+
+        // PNG stores 16 bit samples in big endian
+        let swap = self.pixel_bytes == 8 && cfg!(target_endian = "little");
+        let byte = |i: usize| if swap { row[i ^ 1] } else { row[i] };
+
+        // Use the `Sub` filter, cheap to compute and it helps a lot on smooth gradients.
+        let bpp = self.pixel_bytes;
+        self.filtered.clear();
+        self.filtered.push(1);
+        for i in 0 .. bpp {
+            self.filtered.push(byte(i));
+        }
+        for i in bpp .. row.len() {
+            self.filtered.push(byte(i).wrapping_sub(byte(i - bpp)));
+        }
+
+        self.encoder.write_all(&self.filtered)
+    }
+
+    /// Flushes the compressed data and terminates the file.
+    pub fn finish(self) -> io::Result<W> {
+        assert_eq!(self.rows_left, 0, "not all the rows have been written");
+
+        let mut idat = self.encoder.finish()?;
+        idat.flush_chunk()?;
+        write_chunk(&mut idat.inner, b"IEND", &[])?;
+        idat.inner.flush()?;
+        Ok(idat.inner)
+    }
+}
+
+/// Saves a whole RGBA8 image held in memory.
+pub fn write_png(path: &str, width: u32, height: u32, pixels: &[u8]) -> io::Result<()> {
+    let file = File::create(path)?;
+    let mut writer = PngStreamWriter::new(BufWriter::new(file), width, height)?;
+    for row in pixels.chunks(width as usize * 4) {
+        writer.write_row(row)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Encodes a whole RGBA8 image held in memory to a PNG file, also in memory.
+pub fn encode_png(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    let mut writer = PngStreamWriter::new(Vec::new(), width, height).expect("failed to write in memory");
+    for row in pixels.chunks(width as usize * 4) {
+        writer.write_row(row).expect("failed to write in memory");
+    }
+    writer.finish().expect("failed to write in memory")
+}
+
+/// Splits the compressed stream into IDAT chunks.
+struct IdatWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> IdatWriter<W> {
+    fn flush_chunk(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            write_chunk(&mut self.inner, b"IDAT", &self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for IdatWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let len = data.len().min(IDAT_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&data[.. len]);
+        if self.buffer.len() == IDAT_SIZE {
+            self.flush_chunk()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&be_bytes(data.len() as u32))?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+
+    let crc = crc32_update(crc32_update(0xffff_ffff, kind), data) ^ 0xffff_ffff;
+    out.write_all(&be_bytes(crc))
+}
+
+fn be_bytes(value: u32) -> [u8; 4] {
+    [
+        (value >> 24) as u8,
+        (value >> 16) as u8,
+        (value >> 8) as u8,
+        value as u8,
+    ]
+}
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0 .. 8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image;
+
+    // Bytes that barely compress, so that the rows outgrow the IDAT chunks.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x1234_5678u32;
+        (0 .. len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 24) as u8
+            }).collect()
+    }
+
+    // The kinds of the chunks of a PNG file, in order.
+    fn chunk_kinds(png: &[u8]) -> Vec<Vec<u8>> {
+        assert_eq!(&png[.. 8], &SIGNATURE);
+        let mut kinds = Vec::new();
+        let mut offset = 8;
+        while offset < png.len() {
+            let length = png[offset .. offset + 4]
+                .iter()
+                .fold(0, |length, &byte| length << 8 | byte as usize);
+            kinds.push(png[offset + 4 .. offset + 8].to_vec());
+            offset += 12 + length;
+        }
+        assert_eq!(offset, png.len());
+        kinds
+    }
+
+    fn assert_idat_chunks(png: &[u8]) {
+        let kinds = chunk_kinds(png);
+        assert_eq!(kinds[0], b"IHDR");
+        assert_eq!(kinds[kinds.len() - 1], b"IEND");
+        assert!(kinds.len() > 4, "the image fits in {} chunks", kinds.len());
+        assert!(kinds[1 .. kinds.len() - 1].iter().all(|kind| kind == b"IDAT"));
+    }
+
+    #[test]
+    fn chunks_end_with_their_crc() {
+        // the CRC of a chunk covers its kind, IEND has no data
+        let png = encode_png(1, 1, &[0; 4]);
+        assert_eq!(&png[png.len() - 12 ..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+    }
+
+    #[test]
+    fn rgba8_images_read_back() {
+        // rows longer than a chunk, of an odd width
+        let (width, height) = (70_001, 3);
+        let pixels = noise(width as usize * height as usize * 4);
+        let png = encode_png(width, height, &pixels);
+        assert_idat_chunks(&png);
+
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!(image.to_rgba().into_raw(), pixels);
+    }
+
+    // Encodes 16 bit `samples` from rows of native endian bytes.
+    fn encode_png16(width: u32, height: u32, samples: &[u16]) -> Vec<u8> {
+        let mut writer = PngStreamWriter::with_bit_depth(Vec::new(), width, height, 16).unwrap();
+        for row in samples.chunks(width as usize * 4) {
+            let bytes = row
+                .iter()
+                .flat_map(|&sample| {
+                    let (high, low) = ((sample >> 8) as u8, sample as u8);
+                    if cfg!(target_endian = "little") {
+                        vec![low, high]
+                    } else {
+                        vec![high, low]
+                    }
+                }).collect::<Vec<_>>();
+            writer.write_row(&bytes).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn rgba16_images_read_back() {
+        let (width, height) = (33_335, 3);
+        let samples = noise(width as usize * height as usize * 8)
+            .chunks(2)
+            .map(|pair| u16::from(pair[0]) << 8 | u16::from(pair[1]))
+            .collect::<Vec<_>>();
+        let png = encode_png16(width, height, &samples);
+        assert_idat_chunks(&png);
+
+        // the image crate keeps the high byte of the samples, the low ones
+        // are checked by swapping the bytes of every sample
+        let image = image::load_from_memory(&png).unwrap();
+        let high_bytes = samples.iter().map(|&sample| (sample >> 8) as u8).collect::<Vec<_>>();
+        assert!(image.to_rgba().into_raw() == high_bytes);
+
+        let swapped = samples.iter().map(|&sample| sample.rotate_left(8)).collect::<Vec<_>>();
+        let image = image::load_from_memory(&encode_png16(width, height, &swapped)).unwrap();
+        let low_bytes = samples.iter().map(|&sample| sample as u8).collect::<Vec<_>>();
+        assert!(image.to_rgba().into_raw() == low_bytes);
+    }
+}
@@ -0,0 +1,224 @@
+/// Highest degree supported by the Newton fractal shader.
+pub const MAX_DEGREE: usize = 8;
+
+/// Distance under which the shader takes a point for a root.
+pub const ROOT_TOLERANCE: f64 = 1e-3;
+
+/// A polynomial with complex coefficients, stored from the highest degree down.
+pub struct Polynomial {
+    pub coefficients: Vec<[f64; 2]>,
+}
+
+impl Default for Polynomial {
+    /// z^3 - 1, the classic Newton fractal.
+    fn default() -> Polynomial {
+        Polynomial {
+            coefficients: vec![[1.0, 0.0], [0.0, 0.0], [0.0, 0.0], [-1.0, 0.0]],
+        }
+    }
+}
+
+impl Polynomial {
+    /// Parses a comma separated list of coefficients, from the highest degree
+    /// down, each of them either real (`-2.5`) or complex (`1+2i`, `-i`).
+    pub fn parse(s: &str) -> Result<Polynomial, String> {
+        let coefficients = s
+            .split(',')
+            .map(|c| parse_complex(c.trim()).ok_or_else(|| format!("invalid coefficient '{}'", c)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let polynomial = Polynomial { coefficients };
+        if polynomial.coefficients[0] == [0.0, 0.0] {
+            return Err("the leading coefficient can't be zero".to_owned());
+        }
+        if polynomial.degree() < 2 || polynomial.degree() > MAX_DEGREE {
+            return Err(format!("the degree must be between 2 and {}", MAX_DEGREE));
+        }
+        if polynomial
+            .roots()
+            .iter()
+            .any(|root| !(root[0].is_finite() && root[1].is_finite()))
+        {
+            return Err("failed to find the roots of the polynomial".to_owned());
+        }
+
+        Ok(polynomial)
+    }
+
+    pub fn degree(&self) -> usize {
+        self.coefficients.len() - 1
+    }
+
+    pub fn eval(&self, z: [f64; 2]) -> [f64; 2] {
+        // Horner's method
+        self.coefficients
+            .iter()
+            .fold([0.0, 0.0], |acc, &c| add(mul(acc, z), c))
+    }
+
+    /// Finds all the distinct roots at once with the Durand-Kerner method.
+    ///
+    /// The approximations of a repeated root only get close to each other,
+    /// the ones nearer than the shader can tell apart are merged into their
+    /// mean, which is much closer to the actual root.
+    pub fn roots(&self) -> Vec<[f64; 2]> {
+        let leading = self.coefficients[0];
+        let monic = Polynomial {
+            coefficients: self.coefficients.iter().map(|&c| div(c, leading)).collect(),
+        };
+
+        // the starting points must not be real nor roots of unity
+        let seed = [0.4, 0.9];
+        let mut roots = (0 .. self.degree())
+            .scan([1.0, 0.0], |power, _| {
+                *power = mul(*power, seed);
+                Some(*power)
+            }).collect::<Vec<_>>();
+
+        for _ in 0 .. 500 {
+            let mut change: f64 = 0.0;
+            for i in 0 .. roots.len() {
+                let denominator = (0 .. roots.len())
+                    .filter(|&j| j != i)
+                    .fold([1.0, 0.0], |acc, j| mul(acc, sub(roots[i], roots[j])));
+                let delta = div(monic.eval(roots[i]), denominator);
+                if !(delta[0].is_finite() && delta[1].is_finite()) {
+                    // two approximations met, move one away to keep them apart
+                    roots[i] = add(roots[i], [ROOT_TOLERANCE * 1e-3, ROOT_TOLERANCE * 1e-3]);
+                    change = change.max(ROOT_TOLERANCE);
+                    continue;
+                }
+                roots[i] = sub(roots[i], delta);
+                change = change.max(delta[0].abs() + delta[1].abs());
+            }
+            if change < 1e-12 {
+                break;
+            }
+        }
+
+        let mut clusters: Vec<Vec<[f64; 2]>> = Vec::new();
+        for root in roots {
+            match clusters
+                .iter_mut()
+                .find(|cluster| distance(mean(cluster), root) < ROOT_TOLERANCE)
+            {
+                Some(cluster) => cluster.push(root),
+                None => clusters.push(vec![root]),
+            }
+        }
+        clusters.iter().map(|cluster| mean(cluster)).collect()
+    }
+}
+
+fn parse_complex(s: &str) -> Option<[f64; 2]> {
+    if !s.ends_with('i') {
+        return s.parse().ok().map(|re| [re, 0.0]);
+    }
+
+    // split at the sign of the imaginary part, if there is a real part
+    let body = &s[.. s.len() - 1];
+    let split = body
+        .char_indices()
+        .skip(1)
+        .filter(|&(i, c)| (c == '+' || c == '-') && !body[.. i].ends_with(&['e', 'E'][..]))
+        .map(|(i, _)| i)
+        .last();
+    let (re, im) = match split {
+        Some(i) => (body[.. i].parse().ok()?, &body[i ..]),
+        None => (0.0, body),
+    };
+
+    let im = match im {
+        "" | "+" => 1.0,
+        "-" => -1.0,
+        im => im.parse().ok()?,
+    };
+
+    Some([re, im])
+}
+
+fn add(a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+    [a[0] + b[0], a[1] + b[1]]
+}
+
+fn sub(a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    let d = sub(a, b);
+    (d[0] * d[0] + d[1] * d[1]).sqrt()
+}
+
+fn mean(points: &[[f64; 2]]) -> [f64; 2] {
+    let sum = points.iter().fold([0.0, 0.0], |acc, &point| add(acc, point));
+    [sum[0] / points.len() as f64, sum[1] / points.len() as f64]
+}
+
+fn mul(a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+    [a[0] * b[0] - a[1] * b[1], a[0] * b[1] + a[1] * b[0]]
+}
+
+fn div(a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+    let d = b[0] * b[0] + b[1] * b[1];
+    [
+        (a[0] * b[0] + a[1] * b[1]) / d,
+        (a[1] * b[0] - a[0] * b[1]) / d,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted_roots(coefficients: &str) -> Vec<[f64; 2]> {
+        let mut roots = Polynomial::parse(coefficients).unwrap().roots();
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        roots
+    }
+
+    #[test]
+    fn finds_the_cube_roots_of_unity() {
+        let roots = sorted_roots("1,0,0,-1");
+        let half = 3f64.sqrt() / 2.0;
+        let expected = [[-0.5, -half], [-0.5, half], [1.0, 0.0]];
+        assert_eq!(roots.len(), 3);
+        for (root, expected) in roots.iter().zip(&expected) {
+            assert!(distance(*root, *expected) < 1e-9, "{:?} != {:?}", root, expected);
+        }
+    }
+
+    #[test]
+    fn merges_repeated_roots() {
+        for &(coefficients, root) in &[("1,0,0", [0.0, 0.0]), ("1,-2,1", [1.0, 0.0]), ("1,0,0,0,0,0,0,0,0", [0.0, 0.0])] {
+            let roots = sorted_roots(coefficients);
+            assert_eq!(roots.len(), 1, "{}: {:?}", coefficients, roots);
+            assert!(distance(roots[0], root) < ROOT_TOLERANCE, "{}: {:?}", coefficients, roots);
+        }
+
+        // (z - 1)^2 (z + 1)
+        let roots = sorted_roots("1,-1,-1,1");
+        assert_eq!(roots.len(), 2, "{:?}", roots);
+        assert!(distance(roots[0], [-1.0, 0.0]) < 1e-9);
+        assert!(distance(roots[1], [1.0, 0.0]) < ROOT_TOLERANCE);
+    }
+
+    #[test]
+    fn parses_complex_coefficients() {
+        assert_eq!(parse_complex("-2.5"), Some([-2.5, 0.0]));
+        assert_eq!(parse_complex("1+2i"), Some([1.0, 2.0]));
+        assert_eq!(parse_complex("-i"), Some([0.0, -1.0]));
+        assert_eq!(parse_complex("2e-3i"), Some([0.0, 2e-3]));
+        assert_eq!(parse_complex("1e2-2E+1i"), Some([100.0, -20.0]));
+        assert_eq!(parse_complex("1e-3"), Some([1e-3, 0.0]));
+        assert_eq!(parse_complex("1+x"), None);
+    }
+
+    #[test]
+    fn rejects_invalid_polynomials() {
+        assert!(Polynomial::parse("0,1,1").is_err());
+        assert!(Polynomial::parse("1,1").is_err());
+        assert!(Polynomial::parse("1,0,0,0,0,0,0,0,0,1").is_err());
+        assert!(Polynomial::parse("1,a,1").is_err());
+    }
+}
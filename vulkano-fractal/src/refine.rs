@@ -0,0 +1,221 @@
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::CpuAccessibleBuffer;
+
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::command_buffer::CommandBuffer;
+
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSetBuf;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSetImg;
+
+use vulkano::device::Device;
+use vulkano::device::Queue;
+
+use vulkano::format::Format;
+
+use vulkano::image::StorageImage;
+
+use vulkano::pipeline::ComputePipeline;
+use vulkano::pipeline::ComputePipelineAbstract;
+
+use vulkano::sync::GpuFuture;
+
+use std::sync::Arc;
+
+use output::OutputFormat;
+use render::{Fractal, Params, Shading};
+use shaders;
+
+// Iterations of the first pass, each of the following ones allows four times more.
+const FIRST_BUDGET: u32 = 64;
+
+// Groups along the first axis of a dispatch, the rest goes along the second one.
+const ROW_GROUPS: u32 = 1024;
+
+type RefinePipeline = ComputePipelineAbstract + Send + Sync;
+type RefineSet = PersistentDescriptorSet<
+    Arc<RefinePipeline>,
+    (
+        (
+            (
+                (
+                    ((), PersistentDescriptorSetImg<Arc<StorageImage<Format>>>),
+                    PersistentDescriptorSetBuf<Arc<CpuAccessibleBuffer<[[f32; 4]]>>>,
+                ),
+                PersistentDescriptorSetBuf<Arc<CpuAccessibleBuffer<[u32]>>>,
+            ),
+            PersistentDescriptorSetBuf<Arc<CpuAccessibleBuffer<[u32]>>>,
+        ),
+        PersistentDescriptorSetBuf<Arc<CpuAccessibleBuffer<shaders::refine::ty::Counter>>>,
+    ),
+>;
+
+/// Renders the Mandelbrot set in passes of growing iteration budgets.
+///
+/// Most pixels escape after a few iterations, so instead of running every
+/// pixel up to the maximum, the pixels still undecided after a pass are
+/// compacted in a list and only those are dispatched again, starting from
+/// where they stopped. The image is the same as the one of a single pass.
+pub struct Refiner {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    pipeline: Arc<RefinePipeline>,
+    counter: Arc<CpuAccessibleBuffer<shaders::refine::ty::Counter>>,
+    // the first set reads the first list and appends to the second one, the
+    // other set does the opposite
+    sets: [Arc<RefineSet>; 2],
+    // width and height of the tile
+    tile: [u32; 2],
+}
+
+impl Refiner {
+    /// Creates a refiner drawing in `image`, a tile of `tile[0]` x `tile[1]`
+    /// pixels whose colours are those of `format`.
+    pub fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        image: Arc<StorageImage<Format>>,
+        tile: [u32; 2],
+        format: OutputFormat,
+    ) -> Refiner {
+        let pipeline = create_pipeline(&device, format);
+
+        let pixels = tile[0] as usize * tile[1] as usize;
+        let state = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::all(),
+            (0 .. pixels).map(|_| [0.0f32; 4]),
+        ).expect("failed to create the buffer");
+
+        let lists = [
+            CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::all(), (0 .. pixels).map(|_| 0u32))
+                .expect("failed to create the buffer"),
+            CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::all(), (0 .. pixels).map(|_| 0u32))
+                .expect("failed to create the buffer"),
+        ];
+
+        let counter = CpuAccessibleBuffer::from_data(
+            device.clone(),
+            BufferUsage::all(),
+            shaders::refine::ty::Counter { count: 0 },
+        ).expect("failed to create the buffer");
+
+        let sets = {
+            let create_set = |pending: usize| {
+                Arc::new(
+                    PersistentDescriptorSet::start(pipeline.clone(), 0)
+                        .add_image(image.clone())
+                        .unwrap()
+                        .add_buffer(state.clone())
+                        .unwrap()
+                        .add_buffer(lists[pending].clone())
+                        .unwrap()
+                        .add_buffer(lists[1 - pending].clone())
+                        .unwrap()
+                        .add_buffer(counter.clone())
+                        .unwrap()
+                        .build()
+                        .unwrap(),
+                )
+            };
+            [create_set(0), create_set(1)]
+        };
+
+        Refiner {
+            device,
+            queue,
+            pipeline,
+            counter,
+            sets,
+            tile,
+        }
+    }
+
+    /// Whether the fractal described by `params` can be refined, only the
+    /// escape time of the Mandelbrot set with a single sample per pixel is.
+    pub fn supports(params: &Params) -> bool {
+        params.fractal == Fractal::Mandelbrot
+            && params.shading == Shading::EscapeTime
+            && params.samples == 1
+    }
+
+    /// Renders the tile at `offset` in an image of `image_size` pixels, the
+    /// function only returns once the whole tile has been drawn.
+    pub fn render_tile(&self, params: &Params, offset: [u32; 2], image_size: [u32; 2]) {
+        let mut count = self.tile[0] * self.tile[1];
+        let mut budget = FIRST_BUDGET.min(params.iterations);
+        let mut first_pass = true;
+        let mut pending = 0;
+
+        while count > 0 {
+            *self.counter.write().unwrap() = shaders::refine::ty::Counter { count: 0 };
+
+            let push_constants = shaders::refine::ty::PushConstantData {
+                offset,
+                image_size,
+                center: params.view.center,
+                scale: 1.0 / params.view.zoom,
+                rotation: params.view.rotation.to_radians(),
+                palette_offset: params.view.palette_offset,
+                max_iterations: params.iterations,
+                budget,
+                count,
+                tile_size: self.tile[0],
+                first_pass: first_pass as u32,
+            };
+
+            let groups = (count + 63) / 64;
+            let command_buffer = AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
+                .unwrap()
+                .dispatch(
+                    [groups.min(ROW_GROUPS), (groups + ROW_GROUPS - 1) / ROW_GROUPS, 1],
+                    self.pipeline.clone(),
+                    self.sets[pending].clone(),
+                    push_constants,
+                ).unwrap()
+                .build()
+                .unwrap();
+
+            let finished = command_buffer.execute(self.queue.clone()).unwrap();
+            finished
+                .then_signal_fence_and_flush()
+                .unwrap()
+                .wait(None)
+                .unwrap();
+
+            // once the budget reaches the maximum no pixel is left undecided
+            count = self.counter.read().unwrap().count;
+            budget = budget.saturating_mul(4).min(params.iterations);
+            first_pass = false;
+            pending = 1 - pending;
+        }
+    }
+}
+
+/// Creates the refinement pipeline with the shader writing the colours to
+/// images of `format`.
+fn create_pipeline(device: &Arc<Device>, format: OutputFormat) -> Arc<RefinePipeline> {
+    match format {
+        OutputFormat::Png8 | OutputFormat::Npy => {
+            let shader = shaders::refine::Shader::load(device.clone()).expect("failed to load shader module");
+            Arc::new(
+                ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+                    .expect("failed to create compute pipeline"),
+            )
+        }
+        OutputFormat::Png16 => {
+            let shader = shaders::refine_rgba16::Shader::load(device.clone()).expect("failed to load shader module");
+            Arc::new(
+                ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+                    .expect("failed to create compute pipeline"),
+            )
+        }
+        OutputFormat::Exr => {
+            let shader = shaders::refine_rgba32f::Shader::load(device.clone()).expect("failed to load shader module");
+            Arc::new(
+                ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+                    .expect("failed to create compute pipeline"),
+            )
+        }
+    }
+}
@@ -0,0 +1,572 @@
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::CpuAccessibleBuffer;
+
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::command_buffer::CommandBuffer;
+
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSetBuf;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSetImg;
+
+use vulkano::device::Device;
+use vulkano::device::Queue;
+
+use vulkano::format::Format;
+
+use vulkano::image::Dimensions;
+use vulkano::image::StorageImage;
+
+use vulkano::pipeline::ComputePipeline;
+use vulkano::pipeline::ComputePipelineAbstract;
+
+use vulkano::sync::GpuFuture;
+
+use std::io;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use heatmap::HeatMap;
+use output::{OutputFormat, OutputWriter};
+use polynomial::Polynomial;
+use refine::Refiner;
+use shaders;
+
+// The shaders of every image format have the same layout and push constants,
+// those of `shaders::cs` are used for all of them.
+pub type FractalPipeline = ComputePipelineAbstract + Send + Sync;
+pub type FractalSet = PersistentDescriptorSet<
+    Arc<FractalPipeline>,
+    (
+        (
+            ((), PersistentDescriptorSetImg<Arc<StorageImage<Format>>>),
+            PersistentDescriptorSetBuf<Arc<CpuAccessibleBuffer<shaders::cs::ty::Polynomial>>>,
+        ),
+        PersistentDescriptorSetImg<Arc<StorageImage<Format>>>,
+    ),
+>;
+
+/// The escape time fractals drawn by the tile renderer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fractal {
+    Mandelbrot,
+    /// Basins of attraction of the roots of a polynomial under Newton's method.
+    Newton,
+}
+
+/// What the colours of the Mandelbrot set are derived from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shading {
+    /// The number of iterations before the point escaped.
+    EscapeTime,
+    /// The estimated distance to the set, which keeps the thinnest filaments
+    /// visible where the escape time misses them.
+    Distance,
+    /// How close the orbit came to the point of the trap.
+    PointTrap,
+    /// How close the orbit came to the line going through the point of the trap.
+    LineTrap,
+    /// How close the orbit came to two perpendicular lines crossing at the
+    /// point of the trap.
+    CrossTrap,
+}
+
+impl FromStr for Shading {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Shading, ()> {
+        match s {
+            "escape" => Ok(Shading::EscapeTime),
+            "distance" => Ok(Shading::Distance),
+            "point-trap" => Ok(Shading::PointTrap),
+            "line-trap" => Ok(Shading::LineTrap),
+            "cross-trap" => Ok(Shading::CrossTrap),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Position and orientation of the orbit traps.
+#[derive(Clone, Copy, Debug)]
+pub struct Trap {
+    pub point: [f32; 2],
+    /// Angle of the line trap, and of the first line of the cross, in degrees.
+    pub angle: f32,
+}
+
+impl Default for Trap {
+    fn default() -> Trap {
+        Trap {
+            point: [0.0, 0.0],
+            angle: 0.0,
+        }
+    }
+}
+
+/// The region of the complex plane that ends up in the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+    pub center: [f32; 2],
+    /// Magnification, at 1.0 the image is 2.0 units tall.
+    pub zoom: f32,
+    /// Rotation around the center, in degrees.
+    pub rotation: f32,
+    pub palette_offset: f32,
+}
+
+impl Default for View {
+    fn default() -> View {
+        View {
+            center: [-1.0, 0.0],
+            zoom: 1.0,
+            rotation: 0.0,
+            palette_offset: 0.0,
+        }
+    }
+}
+
+/// Parameters of the fractal, independent from how the image is split in tiles.
+#[derive(Clone, Copy)]
+pub struct Params {
+    pub fractal: Fractal,
+    pub view: View,
+    pub iterations: u32,
+    pub samples: u32,
+    pub jitter: bool,
+    pub shading: Shading,
+    pub trap: Trap,
+}
+
+/// Renders the fractal one tile at a time.
+///
+/// The storage image and the readback buffer only cover a single tile, so the
+/// size of the final image is not bounded by `maxImageDimension2D` nor by the
+/// memory of the device.
+pub struct TileRenderer {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    pipeline: Arc<FractalPipeline>,
+    image: Arc<StorageImage<Format>>,
+    data: Arc<StorageImage<Format>>,
+    polynomial: Arc<CpuAccessibleBuffer<shaders::cs::ty::Polynomial>>,
+    set: Arc<FractalSet>,
+    buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+    // reads the raw data along with the colours, for the heat map
+    data_buffer: Option<Arc<CpuAccessibleBuffer<[u8]>>>,
+    // width and height of the tiles
+    tile: [u32; 2],
+    format: OutputFormat,
+    refiner: Option<Refiner>,
+}
+
+impl TileRenderer {
+    /// Creates a renderer for square tiles of `tile_size` pixels, `polynomial`
+    /// is the one used by the Newton fractal and `format` decides what the
+    /// tiles contain.
+    pub fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        tile_size: u32,
+        polynomial: &Polynomial,
+        format: OutputFormat,
+    ) -> TileRenderer {
+        TileRenderer::with_tile(device, queue, [tile_size, tile_size], polynomial, format)
+    }
+
+    /// Same as `new` with tiles of `tile[0]` x `tile[1]` pixels, an image of
+    /// that size is rendered in a single tile.
+    pub fn with_tile(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        tile: [u32; 2],
+        polynomial: &Polynomial,
+        format: OutputFormat,
+    ) -> TileRenderer {
+        // the tile can't be bigger than what the device supports
+        let max_size = device.physical_device().limits().max_image_dimension_2d();
+        let tile = [tile[0].min(max_size), tile[1].min(max_size)];
+
+        // create the compute pipeline
+        let pipeline = create_pipeline(&device, format);
+
+        // allocate images for the colours and the raw data of a single tile
+        let dimensions = Dimensions::Dim2d {
+            width: tile[0],
+            height: tile[1],
+        };
+        let image = StorageImage::new(
+            device.clone(),
+            dimensions,
+            format.image_format(),
+            Some(queue.family()),
+        ).unwrap();
+        let data = StorageImage::new(
+            device.clone(),
+            dimensions,
+            Format::R32G32B32A32Sfloat,
+            Some(queue.family()),
+        ).unwrap();
+
+        // the polynomial and its roots are passed to the shader in a uniform buffer
+        let polynomial = CpuAccessibleBuffer::from_data(
+            device.clone(),
+            BufferUsage::uniform_buffer(),
+            polynomial_data(polynomial),
+        ).expect("failed to create the buffer");
+
+        // Create a buffer to read the resulting tile
+        let buffer = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::all(),
+            (0 .. tile[0] as usize * tile[1] as usize * format.pixel_bytes()).map(|_| 0u8),
+        ).expect("failed to create the buffer");
+
+        // bind the images to the shade with a descriptor set
+        let set = create_set(&pipeline, image.clone(), &polynomial, data.clone());
+
+        TileRenderer {
+            device,
+            queue,
+            pipeline,
+            image,
+            data,
+            polynomial,
+            set,
+            buffer,
+            data_buffer: None,
+            tile,
+            format,
+            refiner: None,
+        }
+    }
+
+    /// Reads back the raw data of the tiles along with their colours, so that
+    /// `render_to_file` can write a heat map of the iterations. The tiles are
+    /// no longer refined when this is enabled, the refiner doesn't count them.
+    pub fn enable_heatmap(&mut self) {
+        let pixels = self.tile[0] as usize * self.tile[1] as usize;
+        self.data_buffer = Some(
+            CpuAccessibleBuffer::from_iter(
+                self.device.clone(),
+                BufferUsage::all(),
+                (0 .. pixels * 16).map(|_| 0u8),
+            ).expect("failed to create the buffer"),
+        );
+    }
+
+    /// Renders the tiles in passes of growing iteration budgets whenever the
+    /// fractal allows it, see `Refiner`.
+    pub fn enable_refinement(&mut self) {
+        self.refiner = Some(Refiner::new(
+            self.device.clone(),
+            self.queue.clone(),
+            self.image.clone(),
+            self.tile,
+            self.format,
+        ));
+    }
+
+    /// Binds another image to `pipeline`, for the ones that render somewhere
+    /// else than in the tile image. The raw data can't be written with this
+    /// set.
+    pub fn descriptor_set(&self, pipeline: &Arc<FractalPipeline>, image: Arc<StorageImage<Format>>) -> Arc<FractalSet> {
+        create_set(pipeline, image, &self.polynomial, self.data.clone())
+    }
+
+    pub fn tile(&self) -> [u32; 2] {
+        self.tile
+    }
+
+    /// Renders an image of any size and streams it to a file of the format
+    /// of the renderer, only one row of tiles is kept in memory at any time.
+    ///
+    /// The iterations of every pixel are also written to `heatmap`, when
+    /// given, which requires `enable_heatmap` to have been called.
+    pub fn render_to_file(
+        &self,
+        params: &Params,
+        width: u32,
+        height: u32,
+        path: &str,
+        mut heatmap: Option<&mut HeatMap>,
+    ) -> io::Result<()> {
+        assert!(
+            heatmap.is_none() || self.data_buffer.is_some(),
+            "the heat map hasn't been enabled"
+        );
+        let mut writer = OutputWriter::create(path, self.format, width, height)?;
+
+        let pixel_bytes = self.format.pixel_bytes();
+        let row_bytes = width as usize * pixel_bytes;
+        let mut strip = vec![0u8; row_bytes * self.tile[1] as usize];
+        let data_row_bytes = width as usize * 16;
+        let data_strip_size = if self.data_buffer.is_some() {
+            data_row_bytes * self.tile[1] as usize
+        } else {
+            0
+        };
+        let mut data_strip = vec![0u8; data_strip_size];
+
+        for tile_y in (0 .. height).step_by(self.tile[1] as usize) {
+            let strip_height = self.tile[1].min(height - tile_y);
+
+            for tile_x in (0 .. width).step_by(self.tile[0] as usize) {
+                let tile_width = self.tile[0].min(width - tile_x) as usize;
+                let tile_bytes = tile_width * pixel_bytes;
+
+                self.render_tile_with_data(params, [tile_x, tile_y], [width, height], |y, row, data| {
+                    if y < strip_height {
+                        let start = y as usize * row_bytes + tile_x as usize * pixel_bytes;
+                        strip[start .. start + tile_bytes].copy_from_slice(&row[.. tile_bytes]);
+
+                        if let Some(data) = data {
+                            let start = y as usize * data_row_bytes + tile_x as usize * 16;
+                            data_strip[start .. start + tile_width * 16].copy_from_slice(&data[.. tile_width * 16]);
+                        }
+                    }
+                });
+            }
+
+            // once all the tiles of a row are rendered their lines can be written
+            for line in strip.chunks(row_bytes).take(strip_height as usize) {
+                writer.write_row(line)?;
+            }
+            if let Some(ref mut heatmap) = heatmap {
+                for line in data_strip.chunks(data_row_bytes).take(strip_height as usize) {
+                    heatmap.write_row(line)?;
+                }
+            }
+
+            println!("Rendered {} of {} lines", tile_y + strip_height, height);
+        }
+
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Renders a whole image and returns its RGBA8 pixels.
+    ///
+    /// Unlike `render_tile` the result is kept in memory, so this is meant for
+    /// images of a reasonable size.
+    pub fn render_image(&self, params: &Params, width: u32, height: u32) -> Vec<u8> {
+        assert_eq!(self.format, OutputFormat::Png8, "only 8 bit images are kept in memory");
+
+        let row_bytes = width as usize * 4;
+        let mut pixels = vec![0u8; row_bytes * height as usize];
+
+        for tile_y in (0 .. height).step_by(self.tile[1] as usize) {
+            let tile_height = self.tile[1].min(height - tile_y);
+
+            for tile_x in (0 .. width).step_by(self.tile[0] as usize) {
+                let tile_width = self.tile[0].min(width - tile_x) as usize;
+
+                self.render_tile(params, [tile_x, tile_y], [width, height], |y, row| {
+                    if y < tile_height {
+                        let start = (tile_y + y) as usize * row_bytes + tile_x as usize * 4;
+                        pixels[start .. start + tile_width * 4].copy_from_slice(&row[.. tile_width * 4]);
+                    }
+                });
+            }
+        }
+
+        pixels
+    }
+
+    /// Renders the tile whose top left corner is at `offset` in an image of
+    /// `image_size` pixels and calls `f` with every row of the tile.
+    ///
+    /// Rows are `tile()[0]` pixels long, the ones falling outside of the image
+    /// contain garbage and must be ignored by the caller. Their pixels are
+    /// either colours or raw data, depending on the format of the renderer.
+    pub fn render_tile<F>(
+        &self,
+        params: &Params,
+        offset: [u32; 2],
+        image_size: [u32; 2],
+        mut f: F,
+    ) where
+        F: FnMut(u32, &[u8]),
+    {
+        self.render_tile_with_data(params, offset, image_size, |y, row, _| f(y, row))
+    }
+
+    /// Same as `render_tile`, but `f` also gets the rows of raw data when the
+    /// heat map is enabled.
+    fn render_tile_with_data<F>(
+        &self,
+        params: &Params,
+        offset: [u32; 2],
+        image_size: [u32; 2],
+        mut f: F,
+    ) where
+        F: FnMut(u32, &[u8], Option<&[u8]>),
+    {
+        let raw = self.format.is_raw();
+        let write_data = raw || self.data_buffer.is_some();
+        let source = if raw { self.data.clone() } else { self.image.clone() };
+
+        let mut builder = AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family()).unwrap();
+        match self.refiner {
+            // the refiner only draws colours
+            Some(ref refiner) if !write_data && Refiner::supports(params) => {
+                refiner.render_tile(params, offset, image_size)
+            }
+            _ => {
+                builder = builder
+                    .dispatch(
+                        [(self.tile[0] + 7) / 8, (self.tile[1] + 7) / 8, 1],
+                        self.pipeline.clone(),
+                        self.set.clone(),
+                        push_constants(params, offset, image_size, write_data),
+                    )
+                    .unwrap();
+            }
+        }
+
+        builder = builder.copy_image_to_buffer(source, self.buffer.clone()).unwrap();
+        if let Some(ref data_buffer) = self.data_buffer {
+            builder = builder
+                .copy_image_to_buffer(self.data.clone(), data_buffer.clone())
+                .unwrap();
+        }
+        let command_buffer = builder.build().unwrap();
+
+        // execute the commands
+        let finished = command_buffer.execute(self.queue.clone()).unwrap();
+        finished
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        let buffer_content = self.buffer.read().unwrap();
+        let row_bytes = self.tile[0] as usize * self.format.pixel_bytes();
+        match self.data_buffer {
+            Some(ref data_buffer) => {
+                let data_content = data_buffer.read().unwrap();
+                let data_rows = data_content.chunks(self.tile[0] as usize * 16);
+                for (y, (row, data)) in buffer_content.chunks(row_bytes).zip(data_rows).enumerate() {
+                    f(y as u32, row, Some(data));
+                }
+            }
+            None => {
+                for (y, row) in buffer_content.chunks(row_bytes).enumerate() {
+                    f(y as u32, row, None);
+                }
+            }
+        }
+    }
+}
+
+/// Creates the fractal pipeline with the shader writing the colours to images
+/// of `format`.
+pub fn create_pipeline(device: &Arc<Device>, format: OutputFormat) -> Arc<FractalPipeline> {
+    match format {
+        OutputFormat::Png8 | OutputFormat::Npy => {
+            let shader = shaders::cs::Shader::load(device.clone()).expect("failed to load shader module");
+            Arc::new(
+                ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+                    .expect("failed to create compute pipeline"),
+            )
+        }
+        OutputFormat::Png16 => {
+            let shader = shaders::cs_rgba16::Shader::load(device.clone()).expect("failed to load shader module");
+            Arc::new(
+                ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+                    .expect("failed to create compute pipeline"),
+            )
+        }
+        OutputFormat::Exr => {
+            let shader = shaders::cs_rgba32f::Shader::load(device.clone()).expect("failed to load shader module");
+            Arc::new(
+                ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+                    .expect("failed to create compute pipeline"),
+            )
+        }
+    }
+}
+
+fn create_set(
+    pipeline: &Arc<FractalPipeline>,
+    image: Arc<StorageImage<Format>>,
+    polynomial: &Arc<CpuAccessibleBuffer<shaders::cs::ty::Polynomial>>,
+    data: Arc<StorageImage<Format>>,
+) -> Arc<FractalSet> {
+    Arc::new(
+        PersistentDescriptorSet::start(pipeline.clone(), 0)
+            .add_image(image)
+            .unwrap()
+            .add_buffer(polynomial.clone())
+            .unwrap()
+            .add_image(data)
+            .unwrap()
+            .build()
+            .unwrap(),
+    )
+}
+
+fn polynomial_data(polynomial: &Polynomial) -> shaders::cs::ty::Polynomial {
+    let roots = polynomial.roots();
+    let mut data = shaders::cs::ty::Polynomial {
+        coefficients: [[0.0; 4]; 9],
+        roots: [[0.0; 4]; 8],
+        degree: polynomial.degree() as u32,
+        root_count: roots.len() as u32,
+    };
+
+    for (dst, c) in data.coefficients.iter_mut().zip(&polynomial.coefficients) {
+        dst[0] = c[0] as f32;
+        dst[1] = c[1] as f32;
+    }
+    for (dst, r) in data.roots.iter_mut().zip(roots) {
+        dst[0] = r[0] as f32;
+        dst[1] = r[1] as f32;
+    }
+
+    data
+}
+
+/// A maximum number of iterations good enough for the Mandelbrot set at the
+/// given zoom. Deeper views need more iterations before the points close to
+/// the boundary escape, this grows a bit faster than the number of digits of
+/// the zoom.
+pub fn auto_iterations(zoom: f32) -> u32 {
+    let digits = zoom.max(1.0).log10();
+    (200.0 + 250.0 * digits * digits.sqrt()) as u32
+}
+
+/// Fills the push constants of the fractal shader for a tile at `offset` in an
+/// image of `image_size` pixels, the raw data is only written when
+/// `write_data` is set.
+pub fn push_constants(
+    params: &Params,
+    offset: [u32; 2],
+    image_size: [u32; 2],
+    write_data: bool,
+) -> shaders::cs::ty::PushConstantData {
+    shaders::cs::ty::PushConstantData {
+        offset,
+        image_size,
+        samples: params.samples,
+        jitter: params.jitter as u32,
+        center: params.view.center,
+        scale: 1.0 / params.view.zoom,
+        rotation: params.view.rotation.to_radians(),
+        palette_offset: params.view.palette_offset,
+        max_iterations: params.iterations,
+        fractal: match params.fractal {
+            Fractal::Mandelbrot => 0,
+            Fractal::Newton => 1,
+        },
+        write_data: write_data as u32,
+        trap_point: params.trap.point,
+        trap_angle: params.trap.angle.to_radians(),
+        shading: match params.shading {
+            Shading::EscapeTime => 0,
+            Shading::Distance => 1,
+            Shading::PointTrap => 2,
+            Shading::LineTrap => 3,
+            Shading::CrossTrap => 4,
+        },
+        decode_srgb: 0,
+    }
+}
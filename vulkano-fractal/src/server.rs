@@ -0,0 +1,449 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use core;
+use options::Options;
+use output::OutputFormat;
+use png;
+use render;
+use render::{Params, TileRenderer, View};
+
+const TILE_SIZE: u32 = 256;
+
+// Deeper than this the tiles are only made of the rounding errors of 32 bit
+// floats. The viewer has the same limit.
+const MAX_ZOOM: u32 = 16;
+
+// Tiles waiting for the GPU, the requests coming when the queue is full are
+// turned away instead of piling up.
+const QUEUE_SIZE: usize = 32;
+
+// Threads answering the connections, and connections accepted while they are
+// all busy. The ones after that wait in the backlog of the listener.
+const CONNECTION_THREADS: usize = 8;
+const PENDING_CONNECTIONS: usize = 64;
+
+// Seconds a client has to send its request before its thread moves on.
+const READ_TIMEOUT: u64 = 10;
+
+const VIEWER: &str = include_str!("viewer.html");
+
+/// Position of a tile: at zoom level `z` the view is split in `2^z x 2^z`
+/// tiles, `x` and `y` start at the top left.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct TileKey {
+    z: u32,
+    x: u32,
+    y: u32,
+}
+
+/// Keeps the PNG files of the most recently used tiles.
+struct TileCache {
+    capacity: usize,
+    tiles: HashMap<TileKey, (Arc<Vec<u8>>, u64)>,
+    // the tiles by time of last use, the first one is the next to go
+    usage: BTreeMap<u64, TileKey>,
+    clock: u64,
+}
+
+impl TileCache {
+    fn new(capacity: usize) -> TileCache {
+        TileCache {
+            capacity,
+            tiles: HashMap::new(),
+            usage: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, key: &TileKey) -> Option<Arc<Vec<u8>>> {
+        let entry = self.tiles.get_mut(key)?;
+        self.usage.remove(&entry.1);
+        self.clock += 1;
+        entry.1 = self.clock;
+        self.usage.insert(self.clock, *key);
+        Some(entry.0.clone())
+    }
+
+    fn insert(&mut self, key: TileKey, tile: Arc<Vec<u8>>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.clock += 1;
+        if let Some((_, used)) = self.tiles.insert(key, (tile, self.clock)) {
+            self.usage.remove(&used);
+        }
+        self.usage.insert(self.clock, key);
+
+        while self.tiles.len() > self.capacity {
+            let oldest = *self.usage.keys().next().unwrap();
+            let key = self.usage.remove(&oldest).unwrap();
+            self.tiles.remove(&key);
+        }
+    }
+}
+
+/// How a connection gets a tile.
+enum Lookup {
+    Cached(Arc<Vec<u8>>),
+    /// The tile is being rendered, the PNG file comes through the receiver.
+    Pending(Receiver<Arc<Vec<u8>>>),
+}
+
+/// The cached tiles and the connections waiting for the ones being rendered,
+/// so that a tile asked by several clients at once is only rendered once.
+struct Tiles {
+    cache: TileCache,
+    waiting: HashMap<TileKey, Vec<Sender<Arc<Vec<u8>>>>>,
+}
+
+impl Tiles {
+    fn new(capacity: usize) -> Tiles {
+        Tiles {
+            cache: TileCache::new(capacity),
+            waiting: HashMap::new(),
+        }
+    }
+
+    /// Looks `key` up, and queues it to `jobs` unless it is cached or already
+    /// being rendered. Fails when the tile can't be queued.
+    fn request(&mut self, key: TileKey, jobs: &SyncSender<TileKey>) -> Result<Lookup, TrySendError<TileKey>> {
+        if let Some(tile) = self.cache.get(&key) {
+            return Ok(Lookup::Cached(tile));
+        }
+
+        let (reply, result) = mpsc::channel();
+        if let Some(waiting) = self.waiting.get_mut(&key) {
+            waiting.push(reply);
+            return Ok(Lookup::Pending(result));
+        }
+
+        jobs.try_send(key)?;
+        self.waiting.insert(key, vec![reply]);
+        Ok(Lookup::Pending(result))
+    }
+
+    /// Caches the rendered `tile` and hands it to the connections waiting for it.
+    fn finish(&mut self, key: TileKey, tile: Arc<Vec<u8>>) {
+        for reply in self.waiting.remove(&key).unwrap_or_default() {
+            // the client may have given up in the meantime
+            let _ = reply.send(tile.clone());
+        }
+        self.cache.insert(key, tile);
+    }
+}
+
+/// Serves the fractal as `/{z}/{x}/{y}.png` tiles on `address`, along with a
+/// viewer at `/`.
+///
+/// The connections are answered by a fixed number of threads, but the tiles
+/// are all rendered on the calling thread with the same renderer, the other
+/// threads hand them over through a bounded queue.
+pub fn run(options: &Options, address: &str) {
+    let listener = TcpListener::bind(address).unwrap_or_else(|err| {
+        eprintln!("failed to listen on {}: {}", address, err);
+        ::std::process::exit(1);
+    });
+
+    let (device, mut queues) = core::init(&OutputFormat::Png8.required_features());
+    let queue = queues.next().expect("Couldn't get the first queue");
+
+    let mut renderer = TileRenderer::new(
+        device.clone(),
+        queue.clone(),
+        TILE_SIZE,
+        &options.polynomial,
+        OutputFormat::Png8,
+    );
+    if options.progressive {
+        renderer.enable_refinement();
+    }
+
+    let tiles = Arc::new(Mutex::new(Tiles::new(options.cache_size)));
+    let (jobs, receiver) = mpsc::sync_channel::<TileKey>(QUEUE_SIZE);
+
+    // the threads take turns at picking the next connection
+    let (connections, pending) = mpsc::sync_channel::<TcpStream>(PENDING_CONNECTIONS);
+    let pending = Arc::new(Mutex::new(pending));
+    for _ in 0 .. CONNECTION_THREADS {
+        let pending = pending.clone();
+        let tiles = tiles.clone();
+        let jobs = jobs.clone();
+        thread::spawn(move || loop {
+            let stream = match pending.lock().unwrap().recv() {
+                Ok(stream) => stream,
+                Err(_) => return,
+            };
+            if let Err(err) = handle_connection(stream, &tiles, &jobs) {
+                eprintln!("failed to answer a request: {}", err);
+            }
+        });
+    }
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => connections.send(stream).expect("the connection threads stopped"),
+                Err(err) => eprintln!("failed to accept a connection: {}", err),
+            }
+        }
+    });
+
+    println!("Serving the fractal on http://{}/", address);
+
+    for key in receiver {
+        let tile = render_tile(&renderer, options, key);
+        tiles.lock().unwrap().finish(key, Arc::new(tile));
+    }
+}
+
+/// Renders a tile and encodes it as a PNG file.
+fn render_tile(renderer: &TileRenderer, options: &Options, key: TileKey) -> Vec<u8> {
+    // level 0 is a single tile covering twice the height of the view given on
+    // the command line, computed in double precision so that the deep tiles
+    // still line up
+    let world = 4.0 / options.view.zoom as f64;
+    let size = world / (1u64 << key.z) as f64;
+    let tile_center = |center: f32, index: u32| center as f64 - world * 0.5 + (index as f64 + 0.5) * size;
+
+    let view = View {
+        center: [
+            tile_center(options.view.center[0], key.x) as f32,
+            tile_center(options.view.center[1], key.y) as f32,
+        ],
+        zoom: (2.0 / size) as f32,
+        rotation: 0.0,
+        palette_offset: options.view.palette_offset,
+    };
+
+    let mut params = Params {
+        view,
+        ..options.params()
+    };
+    if options.auto_iterations {
+        params.iterations = render::auto_iterations(view.zoom);
+    }
+
+    let pixels = renderer.render_image(&params, TILE_SIZE, TILE_SIZE);
+    png::encode_png(TILE_SIZE, TILE_SIZE, &pixels)
+}
+
+fn handle_connection(stream: TcpStream, tiles: &Mutex<Tiles>, jobs: &SyncSender<TileKey>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(READ_TIMEOUT)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+
+    // skip the headers, nothing in them matters here
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method, path),
+        _ => return respond(&mut stream, "400 Bad Request", "text/plain", b"bad request"),
+    };
+
+    if method != "GET" {
+        return respond(&mut stream, "405 Method Not Allowed", "text/plain", b"only GET is supported");
+    }
+
+    if path == "/" || path == "/index.html" {
+        return respond(&mut stream, "200 OK", "text/html; charset=utf-8", VIEWER.as_bytes());
+    }
+
+    let key = match parse_tile_path(path) {
+        Some(key) => key,
+        None => return respond(&mut stream, "404 Not Found", "text/plain", b"not found"),
+    };
+
+    let lookup = tiles.lock().unwrap().request(key, jobs);
+    let tile = match lookup {
+        Ok(Lookup::Cached(tile)) => tile,
+        Ok(Lookup::Pending(result)) => match result.recv() {
+            Ok(tile) => tile,
+            Err(_) => return respond(&mut stream, "500 Internal Server Error", "text/plain", b"renderer stopped"),
+        },
+        Err(TrySendError::Full(_)) => {
+            return respond(
+                &mut stream,
+                "503 Service Unavailable",
+                "text/plain",
+                b"too many tiles waiting to be rendered",
+            )
+        }
+        Err(TrySendError::Disconnected(_)) => {
+            return respond(&mut stream, "500 Internal Server Error", "text/plain", b"renderer stopped")
+        }
+    };
+
+    respond(&mut stream, "200 OK", "image/png", &tile)
+}
+
+/// Parses `/{z}/{x}/{y}.png`, only accepting tiles that exist.
+fn parse_tile_path(path: &str) -> Option<TileKey> {
+    if !path.starts_with('/') || !path.ends_with(".png") {
+        return None;
+    }
+
+    let mut parts = path[1 .. path.len() - 4].split('/').map(|part| part.parse::<u32>());
+    let key = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(z)), Some(Ok(x)), Some(Ok(y)), None) => TileKey { z, x, y },
+        _ => return None,
+    };
+
+    if key.z > MAX_ZOOM || key.x >= 1 << key.z || key.y >= 1 << key.z {
+        return None;
+    }
+
+    Some(key)
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(z: u32, x: u32, y: u32) -> TileKey {
+        TileKey { z, x, y }
+    }
+
+    fn tile(byte: u8) -> Arc<Vec<u8>> {
+        Arc::new(vec![byte])
+    }
+
+    fn rendered(lookup: Lookup) -> Arc<Vec<u8>> {
+        match lookup {
+            Lookup::Pending(result) => result.recv().unwrap(),
+            Lookup::Cached(_) => panic!("the tile wasn't cached yet"),
+        }
+    }
+
+    #[test]
+    fn least_recently_used_tiles_go_first() {
+        let mut cache = TileCache::new(2);
+        cache.insert(key(0, 0, 0), tile(0));
+        cache.insert(key(1, 0, 0), tile(1));
+        cache.insert(key(1, 1, 0), tile(2));
+
+        assert_eq!(cache.get(&key(0, 0, 0)), None);
+        assert_eq!(cache.get(&key(1, 0, 0)), Some(tile(1)));
+        assert_eq!(cache.get(&key(1, 1, 0)), Some(tile(2)));
+        assert_eq!(cache.tiles.len(), 2);
+        assert_eq!(cache.usage.len(), 2);
+    }
+
+    #[test]
+    fn hits_refresh_the_tiles() {
+        let mut cache = TileCache::new(2);
+        cache.insert(key(0, 0, 0), tile(0));
+        cache.insert(key(1, 0, 0), tile(1));
+        cache.get(&key(0, 0, 0));
+        cache.insert(key(1, 1, 0), tile(2));
+
+        assert_eq!(cache.get(&key(0, 0, 0)), Some(tile(0)));
+        assert_eq!(cache.get(&key(1, 0, 0)), None);
+
+        // inserting a tile again refreshes it too, without taking more room
+        cache.insert(key(1, 1, 0), tile(3));
+        cache.insert(key(0, 0, 0), tile(4));
+        cache.insert(key(2, 0, 0), tile(5));
+        assert_eq!(cache.get(&key(1, 1, 0)), None);
+        assert_eq!(cache.get(&key(0, 0, 0)), Some(tile(4)));
+        assert_eq!(cache.usage.len(), 2);
+    }
+
+    #[test]
+    fn empty_caches_keep_nothing() {
+        let mut cache = TileCache::new(0);
+        cache.insert(key(0, 0, 0), tile(0));
+        assert_eq!(cache.get(&key(0, 0, 0)), None);
+    }
+
+    #[test]
+    fn tiles_asked_twice_are_rendered_once() {
+        let (jobs, queued) = mpsc::sync_channel(4);
+        let mut tiles = Tiles::new(4);
+
+        let first = tiles.request(key(1, 0, 1), &jobs).unwrap();
+        let second = tiles.request(key(1, 0, 1), &jobs).unwrap();
+        assert_eq!(queued.try_iter().collect::<Vec<_>>(), vec![key(1, 0, 1)]);
+
+        tiles.finish(key(1, 0, 1), tile(7));
+        assert_eq!(rendered(first), tile(7));
+        assert_eq!(rendered(second), tile(7));
+
+        match tiles.request(key(1, 0, 1), &jobs).unwrap() {
+            Lookup::Cached(cached) => assert_eq!(cached, tile(7)),
+            Lookup::Pending(_) => panic!("the tile was rendered again"),
+        }
+        assert!(queued.try_recv().is_err());
+    }
+
+    #[test]
+    fn tiles_that_cant_be_queued_are_asked_again() {
+        let (jobs, queued) = mpsc::sync_channel(1);
+        let mut tiles = Tiles::new(4);
+
+        assert!(tiles.request(key(1, 0, 0), &jobs).is_ok());
+        match tiles.request(key(1, 1, 0), &jobs) {
+            Err(TrySendError::Full(full)) => assert_eq!(full, key(1, 1, 0)),
+            _ => panic!("the queue should be full"),
+        }
+
+        assert_eq!(queued.recv().unwrap(), key(1, 0, 0));
+        assert!(tiles.request(key(1, 1, 0), &jobs).is_ok());
+        assert_eq!(queued.recv().unwrap(), key(1, 1, 0));
+    }
+
+    #[test]
+    fn tile_paths_are_parsed() {
+        assert_eq!(parse_tile_path("/0/0/0.png"), Some(key(0, 0, 0)));
+        assert_eq!(parse_tile_path("/3/7/5.png"), Some(key(3, 7, 5)));
+        assert_eq!(parse_tile_path("/16/65535/0.png"), Some(key(16, 65535, 0)));
+    }
+
+    #[test]
+    fn malformed_tile_paths_are_rejected() {
+        let paths = [
+            "", "/", "/.png", "0/0/0.png", "/0/0/0", "/0/0/0.jpg", "/0/0.png", "/0/0/0/0.png", "/0//0.png",
+            "/a/0/0.png", "/0/-1/0.png", "/0/0/ 0.png", "/0/0/0.png?x=1", "/0/0/99999999999.png",
+        ];
+        for path in &paths {
+            assert_eq!(parse_tile_path(path), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn tiles_out_of_range_are_rejected() {
+        assert_eq!(parse_tile_path("/0/1/0.png"), None);
+        assert_eq!(parse_tile_path("/3/8/0.png"), None);
+        assert_eq!(parse_tile_path("/3/0/8.png"), None);
+        assert_eq!(parse_tile_path("/17/0/0.png"), None);
+        assert_eq!(parse_tile_path("/16/65536/0.png"), None);
+        assert_eq!(parse_tile_path("/4294967295/0/0.png"), None);
+    }
+}
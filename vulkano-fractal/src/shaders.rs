@@ -0,0 +1,352 @@
+// The escape time shaders, built from `glsl/` by the build script.
+include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
+
+pub mod buddhabrot {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+
+#version 450
+
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+// how many times an orbit went through each pixel
+layout(set = 0, binding = 0, r32ui) uniform uimage2D counts;
+
+layout(set = 0, binding = 1) buffer Stats {
+    // highest count of each channel, used to normalize the image
+    uint max_count[3];
+} stats;
+
+layout(push_constant) uniform PushConstantData {
+    // point of the complex plane at the center of the image
+    vec2 center;
+    // half of the height of the visible region of the complex plane
+    float scale;
+    // rotation of the view around its center, in radians
+    float rotation;
+    // changes at every dispatch, so that each one samples different points
+    uint seed;
+    // points sampled by each invocation
+    uint samples;
+    // orbits longer than this are considered part of the set and ignored
+    uint max_iterations;
+    // index of the channel in the stats buffer
+    uint channel;
+} pc;
+
+uint hash(uint x) {
+    x ^= x >> 16;
+    x *= 0x7feb352du;
+    x ^= x >> 15;
+    x *= 0x846ca68bu;
+    x ^= x >> 16;
+    return x;
+}
+
+// PCG step, returns a random number in [0, 1]
+float random(inout uint state) {
+    state = state * 747796405u + 2891336453u;
+    uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return float((word >> 22u) ^ word) / 4294967295.0;
+}
+
+// Inverse of the mapping used by the escape time shader.
+ivec2 to_pixel(vec2 z, ivec2 size) {
+    float s = sin(-pc.rotation);
+    float c = cos(-pc.rotation);
+    vec2 p = mat2(c, s, -s, c) * (z - pc.center) / pc.scale;
+    return ivec2(floor(p * float(size.y) * 0.5 + vec2(size) * 0.5));
+}
+
+vec2 step_orbit(vec2 z, vec2 c) {
+    return vec2(z.x * z.x - z.y * z.y + c.x, 2.0 * z.x * z.y + c.y);
+}
+
+void main() {
+    ivec2 size = imageSize(counts);
+    uint state = hash(gl_GlobalInvocationID.x ^ hash(pc.seed));
+    uint local_max = 0;
+
+    for (uint s = 0; s < pc.samples; s++) {
+        vec2 c = vec2(random(state) * 3.0 - 2.0, random(state) * 3.0 - 1.5);
+
+        // the orbits of the main cardioid and of the period 2 bulb never
+        // escape, skip them without iterating
+        float q = (c.x - 0.25) * (c.x - 0.25) + c.y * c.y;
+        if (q * (q + (c.x - 0.25)) <= 0.25 * c.y * c.y ||
+            (c.x + 1.0) * (c.x + 1.0) + c.y * c.y <= 0.0625) {
+            continue;
+        }
+
+        vec2 z = vec2(0.0);
+        uint n;
+        for (n = 0; n < pc.max_iterations; n++) {
+            z = step_orbit(z, c);
+            if (dot(z, z) > 4.0) {
+                break;
+            }
+        }
+        if (n == pc.max_iterations) {
+            continue;
+        }
+
+        // the orbit escapes: trace it again, this time recording it
+        z = vec2(0.0);
+        for (uint k = 0; k < n; k++) {
+            z = step_orbit(z, c);
+            ivec2 pixel = to_pixel(z, size);
+            if (all(greaterThanEqual(pixel, ivec2(0))) && all(lessThan(pixel, size))) {
+                local_max = max(local_max, imageAtomicAdd(counts, pixel, 1u) + 1u);
+            }
+        }
+    }
+
+    atomicMax(stats.max_count[pc.channel], local_max);
+}"]
+    struct Dummy;
+}
+
+pub mod tonemap {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, r32ui) uniform readonly uimage2D red;
+layout(set = 0, binding = 1, r32ui) uniform readonly uimage2D green;
+layout(set = 0, binding = 2, r32ui) uniform readonly uimage2D blue;
+
+layout(set = 0, binding = 3) buffer Stats {
+    uint max_count[3];
+} stats;
+
+layout(set = 0, binding = 4, rgba8) uniform writeonly image2D img;
+
+layout(push_constant) uniform PushConstantData {
+    // 1 for a grey scale image using only the red counts, 3 for colours
+    uint channels;
+} pc;
+
+// Counts span several orders of magnitude, a logarithmic curve keeps the faint
+// orbits visible without saturating the dense regions.
+float tone(uint count, uint max_count) {
+    if (max_count == 0) {
+        return 0.0;
+    }
+    return log(1.0 + float(count)) / log(1.0 + float(max_count));
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(pixel, imageSize(img)))) {
+        return;
+    }
+
+    float r = tone(imageLoad(red, pixel).x, stats.max_count[0]);
+    vec3 color = vec3(r);
+    if (pc.channels == 3) {
+        color.g = tone(imageLoad(green, pixel).x, stats.max_count[1]);
+        color.b = tone(imageLoad(blue, pixel).x, stats.max_count[2]);
+    }
+
+    imageStore(img, pixel, vec4(color, 1.0));
+}"]
+    struct Dummy;
+}
+
+pub mod raymarch {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba8) uniform writeonly image2D img;
+
+layout(push_constant) uniform PushConstantData {
+    // position of the camera, xyz only
+    vec4 camera;
+    // point the camera looks at, xyz only
+    vec4 target;
+    // vertical field of view, in radians
+    float fov;
+    // exponent of the Mandelbulb formula
+    float power;
+    // 0 for the Mandelbulb, 1 for the Mandelbox
+    uint fractal;
+    // number of samples per pixel along each axis
+    uint samples;
+    // first row of the band of the image rendered by this dispatch
+    uint first_row;
+} pc;
+
+const int MAX_STEPS = 300;
+const float MAX_DISTANCE = 50.0;
+// hit threshold, relative to the distance travelled by the ray
+const float EPSILON = 0.0005;
+// normalized direction towards the light
+const vec3 LIGHT = vec3(0.557, 0.743, -0.371);
+
+// Distance estimators, they return the distance from the surface in x and
+// an orbit trap used for colouring in y.
+vec2 mandelbulb(vec3 p) {
+    vec3 z = p;
+    float dr = 1.0;
+    float r = length(z);
+    float trap = 1e10;
+    for (int i = 0; i < 12 && r <= 2.0; i++) {
+        // the origin has no direction, any angle will do
+        float theta = acos(r > 0.0 ? clamp(z.z / r, -1.0, 1.0) : 0.0) * pc.power;
+        float phi = atan(z.y, z.x) * pc.power;
+        dr = pow(r, pc.power - 1.0) * pc.power * dr + 1.0;
+        z = pow(r, pc.power) * vec3(sin(theta) * cos(phi), sin(phi) * sin(theta), cos(theta)) + p;
+        trap = min(trap, dot(z, z));
+        r = length(z);
+    }
+    // the origin stays put and is inside
+    return vec2(r > 0.0 ? 0.5 * log(r) * r / dr : 0.0, trap);
+}
+
+vec2 mandelbox(vec3 p) {
+    const float scale = 2.0;
+    // w keeps track of the running derivative
+    vec4 z = vec4(p, 1.0);
+    float trap = 1e10;
+    for (int i = 0; i < 15; i++) {
+        // box fold
+        z.xyz = clamp(z.xyz, -1.0, 1.0) * 2.0 - z.xyz;
+        // sphere fold
+        float r2 = dot(z.xyz, z.xyz);
+        if (r2 < 0.25) {
+            z *= 4.0;
+        } else if (r2 < 1.0) {
+            z /= r2;
+        }
+        z = z * vec4(vec3(scale), abs(scale)) + vec4(p, 1.0);
+        trap = min(trap, r2);
+    }
+    return vec2(length(z.xyz) / abs(z.w), trap);
+}
+
+vec2 scene(vec3 p) {
+    return pc.fractal == 1 ? mandelbox(p) : mandelbulb(p);
+}
+
+vec3 normal(vec3 p, float e) {
+    vec2 h = vec2(e, 0.0);
+    return normalize(vec3(
+        scene(p + h.xyy).x - scene(p - h.xyy).x,
+        scene(p + h.yxy).x - scene(p - h.yxy).x,
+        scene(p + h.yyx).x - scene(p - h.yyx).x
+    ));
+}
+
+// Penumbra estimated from how close the shadow ray gets to the surface.
+float soft_shadow(vec3 origin, vec3 direction) {
+    float result = 1.0;
+    float t = 0.01;
+    for (int i = 0; i < 96 && t < MAX_DISTANCE; i++) {
+        float h = scene(origin + direction * t).x;
+        if (h < EPSILON * t) {
+            return 0.0;
+        }
+        result = min(result, 8.0 * h / t);
+        t += clamp(h, 0.005, 0.5);
+    }
+    return clamp(result, 0.0, 1.0);
+}
+
+// Compares the distance from the surface along the normal with the one
+// expected on a flat surface, creases get darker.
+float ambient_occlusion(vec3 p, vec3 n) {
+    float occlusion = 0.0;
+    float weight = 1.0;
+    for (int i = 1; i <= 5; i++) {
+        float d = 0.03 * float(i);
+        occlusion += weight * (d - scene(p + n * d).x);
+        weight *= 0.5;
+    }
+    return clamp(1.0 - 4.0 * occlusion, 0.0, 1.0);
+}
+
+vec3 shade(vec3 origin, vec3 direction) {
+    vec3 background = mix(vec3(0.05, 0.05, 0.08), vec3(0.25, 0.3, 0.4), direction.y * 0.5 + 0.5);
+
+    float t = 0.0;
+    for (int i = 0; i < MAX_STEPS; i++) {
+        vec3 p = origin + direction * t;
+        vec2 d = scene(p);
+        if (d.x < EPSILON * t) {
+            vec3 n = normal(p, max(EPSILON * t, 1e-5));
+            vec3 base = mix(vec3(0.95, 0.65, 0.35), vec3(0.35, 0.55, 0.95), clamp(d.y, 0.0, 1.0));
+
+            float diffuse = max(dot(n, LIGHT), 0.0);
+            float shadow = diffuse > 0.0 ? soft_shadow(p + n * EPSILON * t * 2.0, LIGHT) : 0.0;
+            float occlusion = ambient_occlusion(p, n);
+            float specular = pow(max(dot(reflect(direction, n), LIGHT), 0.0), 32.0);
+
+            vec3 color = base * (0.25 * occlusion + diffuse * shadow) + vec3(0.4) * specular * shadow;
+            // fade to the background with the distance
+            return mix(color, background, smoothstep(0.0, MAX_DISTANCE, t));
+        }
+        t += d.x;
+        if (t > MAX_DISTANCE) {
+            break;
+        }
+    }
+
+    return background;
+}
+
+void main() {
+    ivec2 size = imageSize(img);
+    uvec2 pixel = gl_GlobalInvocationID.xy + uvec2(0, pc.first_row);
+    if (any(greaterThanEqual(pixel, uvec2(size)))) {
+        return;
+    }
+
+    vec3 forward = normalize(pc.target.xyz - pc.camera.xyz);
+    // looking straight up or down, the image is kept upright along z instead
+    vec3 world_up = abs(forward.y) > 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(0.0, 1.0, 0.0);
+    vec3 right = normalize(cross(world_up, forward));
+    vec3 up = cross(forward, right);
+    float tan_half_fov = tan(pc.fov * 0.5);
+
+    vec3 color = vec3(0.0);
+    for (uint sy = 0; sy < pc.samples; sy++) {
+        for (uint sx = 0; sx < pc.samples; sx++) {
+            vec2 position = pixel + (vec2(sx, sy) + vec2(0.5)) / float(pc.samples);
+            // y goes up in camera space and down in the image
+            vec2 uv = (position - vec2(size) * 0.5) / (float(size.y) * 0.5) * vec2(1.0, -1.0);
+            vec3 direction = normalize(forward + (uv.x * right + uv.y * up) * tan_half_fov);
+            color += shade(pc.camera.xyz, direction);
+        }
+    }
+    color /= float(pc.samples * pc.samples);
+
+    // gamma correction
+    imageStore(img, ivec2(pixel), vec4(pow(color, vec3(1.0 / 2.2)), 1.0));
+}"]
+    struct Dummy;
+}
+
+pub mod vs {
+    #[derive(VulkanoShader)]
+    #[ty = "vertex"]
+    #[src = "
+#version 450
+
+layout(location = 0) in vec2 position;
+
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+}
+"]
+    struct Dummy;
+}
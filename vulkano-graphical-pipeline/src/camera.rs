@@ -0,0 +1,172 @@
+//! Where a scene is seen from.
+
+use mesh;
+
+/// A perspective camera, in the right handed space of OBJ files where Y is
+/// up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+    pub up: [f32; 3],
+    /// Vertical field of view, in radians.
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Camera {
+    /// Looks at the box from `min` to `max` from the front, a bit from above
+    /// and from the right, far enough for all of it to be in view.
+    pub fn framing(min: [f32; 3], max: [f32; 3]) -> Camera {
+        let fov_y = 45f32.to_radians();
+        let target = [
+            (min[0] + max[0]) / 2.0,
+            (min[1] + max[1]) / 2.0,
+            (min[2] + max[2]) / 2.0,
+        ];
+        let radius = (0 .. 3)
+            .map(|axis| (max[axis] - min[axis]) / 2.0)
+            .map(|half| half * half)
+            .sum::<f32>()
+            .sqrt()
+            .max(1e-3);
+
+        // the sphere around the box fits in the vertical field of view, the
+        // images at least as wide as high show all of it
+        let distance = radius / (fov_y / 2.0).sin();
+        let direction = mesh::normalize([0.5, 0.5, 1.0]);
+        Camera {
+            eye: [
+                target[0] + direction[0] * distance,
+                target[1] + direction[1] * distance,
+                target[2] + direction[2] * distance,
+            ],
+            target,
+            up: [0.0, 1.0, 0.0],
+            fov_y,
+            near: (distance - radius) / 2.0,
+            far: distance + radius * 2.0,
+        }
+    }
+
+    /// The matrix from world space to the clip space of Vulkan, whose Y axis
+    /// points down and whose depth goes from 0 to 1, column by column like a
+    /// GLSL `mat4`.
+    pub fn view_projection(&self, aspect: f32) -> [[f32; 4]; 4] {
+        let forward = mesh::normalize(sub(self.target, self.eye));
+        let side = mesh::normalize(cross(forward, self.up));
+        let up = cross(side, forward);
+
+        // row by row, looking down -Z
+        let view = [
+            [side[0], side[1], side[2], -dot(side, self.eye)],
+            [up[0], up[1], up[2], -dot(up, self.eye)],
+            [-forward[0], -forward[1], -forward[2], dot(forward, self.eye)],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+
+        let focal = 1.0 / (self.fov_y / 2.0).tan();
+        let (near, far) = (self.near, self.far);
+        let projection = [
+            [focal / aspect, 0.0, 0.0, 0.0],
+            [0.0, -focal, 0.0, 0.0],
+            [0.0, 0.0, far / (near - far), near * far / (near - far)],
+            [0.0, 0.0, -1.0, 0.0],
+        ];
+
+        let mut columns = [[0.0; 4]; 4];
+        for (column, values) in columns.iter_mut().enumerate() {
+            for (row, value) in values.iter_mut().enumerate() {
+                *value = (0 .. 4).map(|i| projection[row][i] * view[i][column]).sum();
+            }
+        }
+        columns
+    }
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The normalized device coordinates of `point`.
+    fn project(matrix: &[[f32; 4]; 4], point: [f32; 3]) -> [f32; 3] {
+        let mut clip = [0.0; 4];
+        for (row, value) in clip.iter_mut().enumerate() {
+            *value = (0 .. 3).map(|i| matrix[i][row] * point[i]).sum::<f32>() + matrix[3][row];
+        }
+        [clip[0] / clip[3], clip[1] / clip[3], clip[2] / clip[3]]
+    }
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        assert!((0 .. 3).all(|i| (a[i] - b[i]).abs() < 1e-4), "{:?} != {:?}", a, b);
+    }
+
+    fn camera() -> Camera {
+        Camera {
+            eye: [0.0, 0.0, 5.0],
+            target: [0.0, 0.0, 0.0],
+            up: [0.0, 1.0, 0.0],
+            fov_y: 90f32.to_radians(),
+            near: 1.0,
+            far: 9.0,
+        }
+    }
+
+    #[test]
+    fn target_is_at_the_centre() {
+        let ndc = project(&camera().view_projection(1.0), [0.0, 0.0, 0.0]);
+        assert_close(ndc, [0.0, 0.0, ndc[2]]);
+        assert!(ndc[2] > 0.0 && ndc[2] < 1.0);
+    }
+
+    #[test]
+    fn depth_goes_from_near_to_far() {
+        let matrix = camera().view_projection(1.0);
+        assert_close(project(&matrix, [0.0, 0.0, 4.0]), [0.0, 0.0, 0.0]);
+        assert_close(project(&matrix, [0.0, 0.0, -4.0]), [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn y_points_down_and_x_right() {
+        // at a distance of 5, the field of view of 90 degrees is 10 high
+        let matrix = camera().view_projection(2.0);
+        assert_close(project(&matrix, [5.0, 5.0, 0.0]), [0.5, -1.0, project(&matrix, [0.0, 0.0, 0.0])[2]]);
+    }
+
+    #[test]
+    fn framing_shows_the_whole_box() {
+        let (min, max) = ([-1.0, 0.0, -3.0], [2.0, 1.0, 0.5]);
+        let camera = Camera::framing(min, max);
+        let matrix = camera.view_projection(1.0);
+
+        let centre = project(&matrix, camera.target);
+        assert_close(centre, [0.0, 0.0, centre[2]]);
+        for corner in 0 .. 8 {
+            let point = [
+                if corner & 1 == 0 { min[0] } else { max[0] },
+                if corner & 2 == 0 { min[1] } else { max[1] },
+                if corner & 4 == 0 { min[2] } else { max[2] },
+            ];
+            let ndc = project(&matrix, point);
+            assert!(ndc.iter().take(2).all(|value| value.abs() <= 1.0), "{:?}", ndc);
+            assert!(ndc[2] > 0.0 && ndc[2] < 1.0, "{:?}", ndc);
+        }
+    }
+}
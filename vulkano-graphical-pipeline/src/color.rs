@@ -0,0 +1,112 @@
+//! How the colours are handled, from the shaders to the PNG file.
+//!
+//! The shaders work on linear values and write them to an sRGB render target,
+//! which encodes them when they are stored. The bytes read back are then
+//! already what a PNG file holds, since viewers assume it is sRGB, and are
+//! saved as they are.
+//!
+//! The windows do the same with an sRGB swapchain whenever the surface
+//! supports one, see `choose_format`.
+
+use vulkano::format::Format;
+use vulkano::swapchain::ColorSpace;
+
+/// Format of the images rendered to.
+pub const RENDER_FORMAT: Format = Format::R8G8B8A8Srgb;
+
+/// The sRGB transfer function, from a linear value in `[0, 1]` to the
+/// encoded one.
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The byte a linear value is stored as in the render target.
+pub fn encode(value: f32) -> u8 {
+    (linear_to_srgb(value.max(0.0).min(1.0)) * 255.0).round() as u8
+}
+
+/// Picks an sRGB format for the swapchain when the surface supports one, so that the linear
+/// colours written by the shaders are encoded for display. The first supported format is used
+/// otherwise.
+pub fn choose_format(formats: &[(Format, ColorSpace)]) -> Format {
+    formats
+        .iter()
+        .find(|&&(format, color_space)| is_srgb(format) && color_space == ColorSpace::SrgbNonLinear)
+        .unwrap_or(&formats[0])
+        .0
+}
+
+/// Whether the images of `format` encode the colours to sRGB when they are written.
+pub fn is_srgb(format: Format) -> bool {
+    match format {
+        Format::B8G8R8A8Srgb | Format::R8G8B8A8Srgb | Format::A8B8G8R8SrgbPack32 => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_function_ends() {
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn transfer_function_is_continuous() {
+        // both pieces meet at the threshold
+        let threshold = 0.003_130_8f32;
+        let linear = threshold * 12.92;
+        let curve = 1.055 * threshold.powf(1.0 / 2.4) - 0.055;
+        assert!((linear - curve).abs() < 1e-4);
+        assert!((linear_to_srgb(threshold) - linear_to_srgb(threshold + 1e-6)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn transfer_function_is_increasing() {
+        let values = (0 ..= 100).map(|i| linear_to_srgb(i as f32 / 100.0)).collect::<Vec<_>>();
+        assert!(values.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn encoded_bytes() {
+        assert_eq!(encode(0.5), 188);
+        assert_eq!(encode(0.0), 0);
+        assert_eq!(encode(1.0), 255);
+        // the byte 128 stands for a linear value of about 0.216
+        assert_eq!(encode(0.216), 128);
+        // the linear piece near black
+        assert_eq!(encode(0.001), 3);
+    }
+
+    #[test]
+    fn encode_clamps() {
+        assert_eq!(encode(-0.5), 0);
+        assert_eq!(encode(2.0), 255);
+    }
+
+    #[test]
+    fn swapchains_prefer_srgb() {
+        let formats = [
+            (Format::B8G8R8A8Unorm, ColorSpace::SrgbNonLinear),
+            (Format::R8G8B8A8Srgb, ColorSpace::ExtendedSrgbLinear),
+            (Format::B8G8R8A8Srgb, ColorSpace::SrgbNonLinear),
+        ];
+        assert_eq!(choose_format(&formats), Format::B8G8R8A8Srgb);
+        assert_eq!(choose_format(&formats[.. 2]), Format::B8G8R8A8Unorm);
+    }
+
+    #[test]
+    fn srgb_formats() {
+        assert!(is_srgb(RENDER_FORMAT));
+        assert!(is_srgb(Format::B8G8R8A8Srgb));
+        assert!(!is_srgb(Format::B8G8R8A8Unorm));
+        assert!(!is_srgb(Format::R16G16B16A16Sfloat));
+    }
+}
@@ -0,0 +1,20 @@
+//! Headless rendering: images are drawn offscreen by an `OffscreenRenderer`
+//! and read back as `image` buffers, ready to be saved or compared.
+
+extern crate image;
+
+#[macro_use]
+extern crate vulkano;
+
+#[macro_use]
+extern crate vulkano_shader_derive;
+
+pub mod camera;
+pub mod color;
+pub mod core;
+pub mod lit;
+pub mod mesh;
+mod offscreen;
+pub mod shaders;
+
+pub use offscreen::{OffscreenRenderer, DEPTH_FORMAT};
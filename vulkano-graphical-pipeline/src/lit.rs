@@ -0,0 +1,104 @@
+//! Renders meshes with their materials and a single directional light.
+
+use image::RgbaImage;
+
+use vulkano::buffer::BufferSlice;
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::CpuAccessibleBuffer;
+
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+
+use vulkano::pipeline::GraphicsPipeline;
+
+use std::sync::Arc;
+
+use camera::Camera;
+use mesh;
+use mesh::Mesh;
+use offscreen::OffscreenRenderer;
+use shaders;
+
+/// Direction the light goes to, from above, the left and the front.
+pub const LIGHT_DIRECTION: [f32; 3] = [0.4, -1.0, -0.6];
+
+/// Draws `mesh` as seen from `camera` on a background of the linear
+/// `clear_color`, each part with its material.
+pub fn render(renderer: &OffscreenRenderer, mesh: &Mesh, camera: &Camera, clear_color: [f32; 4]) -> RgbaImage {
+    let device = renderer.device().clone();
+
+    let vertex_buffer = CpuAccessibleBuffer::from_iter(
+        device.clone(),
+        BufferUsage::vertex_buffer(),
+        mesh.vertices.iter().cloned(),
+    ).expect("failed to create buffer");
+    let index_buffer = CpuAccessibleBuffer::from_iter(
+        device.clone(),
+        BufferUsage::index_buffer(),
+        mesh.indices.iter().cloned(),
+    ).expect("failed to create buffer");
+
+    let vs = shaders::lit_vs::Shader::load(device.clone()).expect("failed to create shader module");
+    let fs = shaders::lit_fs::Shader::load(device.clone()).expect("failed to create shader module");
+
+    // the faces aren't culled, OBJ files don't agree on their winding
+    let pipeline = Arc::new(
+        GraphicsPipeline::start()
+            .vertex_input_single_buffer::<mesh::Vertex>()
+            .vertex_shader(vs.main_entry_point(), ())
+            .triangle_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs.main_entry_point(), ())
+            .depth_stencil_simple_depth()
+            .render_pass(renderer.subpass())
+            .build(device.clone())
+            .unwrap(),
+    );
+
+    let (width, height) = renderer.dimensions();
+    let light = mesh::normalize(LIGHT_DIRECTION);
+    let scene = CpuAccessibleBuffer::from_data(
+        device.clone(),
+        BufferUsage::uniform_buffer(),
+        shaders::lit_vs::ty::Scene {
+            view_projection: camera.view_projection(width as f32 / height as f32),
+            eye: [camera.eye[0], camera.eye[1], camera.eye[2], 1.0],
+            light_direction: [light[0], light[1], light[2], 0.0],
+        },
+    ).expect("failed to create buffer");
+
+    let set = Arc::new(
+        PersistentDescriptorSet::start(pipeline.clone(), 0)
+            .add_buffer(scene)
+            .unwrap()
+            .build()
+            .unwrap(),
+    );
+
+    renderer.render(clear_color, |builder, dynamic_state| {
+        mesh.parts.iter().fold(builder, |builder, part| {
+            let material = mesh.material(part);
+            let indices = BufferSlice::from_typed_buffer_access(index_buffer.clone())
+                .slice(part.indices.clone())
+                .unwrap();
+            let push_constants = shaders::lit_fs::ty::PushConstantData {
+                ambient: extend(material.ambient, 1.0),
+                diffuse: extend(material.diffuse, 1.0),
+                specular: extend(material.specular, material.shininess),
+            };
+
+            builder
+                .draw_indexed(
+                    pipeline.clone(),
+                    dynamic_state,
+                    vertex_buffer.clone(),
+                    indices,
+                    set.clone(),
+                    push_constants,
+                ).unwrap()
+        })
+    })
+}
+
+fn extend(color: [f32; 3], w: f32) -> [f32; 4] {
+    [color[0], color[1], color[2], w]
+}
@@ -1,37 +1,30 @@
+extern crate golden;
 extern crate image;
 
 #[macro_use]
 extern crate vulkano;
 
-#[macro_use]
-extern crate vulkano_shader_derive;
-
-mod core;
-mod shaders;
+extern crate vulkano_graphical_pipeline;
 
-use image::{ImageBuffer, Rgba};
+use image::RgbaImage;
 
 use vulkano::buffer::BufferUsage;
 use vulkano::buffer::CpuAccessibleBuffer;
 
-use vulkano::command_buffer::AutoCommandBufferBuilder;
-use vulkano::command_buffer::CommandBuffer;
-use vulkano::command_buffer::DynamicState;
-
-use vulkano::format::Format;
-use vulkano::framebuffer::Framebuffer;
-use vulkano::framebuffer::Subpass;
-
-use vulkano::image::Dimensions;
-use vulkano::image::StorageImage;
-
 use vulkano::pipeline::GraphicsPipeline;
-use vulkano::pipeline::viewport::Viewport;
-
-use vulkano::sync::GpuFuture;
 
+use std::env;
+use std::process;
 use std::sync::Arc;
 
+use vulkano_graphical_pipeline::camera::Camera;
+use vulkano_graphical_pipeline::color;
+use vulkano_graphical_pipeline::core;
+use vulkano_graphical_pipeline::lit;
+use vulkano_graphical_pipeline::mesh::Mesh;
+use vulkano_graphical_pipeline::shaders;
+use vulkano_graphical_pipeline::OffscreenRenderer;
+
 #[derive(Copy, Clone)]
 struct Vertex {
     position: [f32; 2],
@@ -39,79 +32,166 @@ struct Vertex {
 
 impl_vertex!(Vertex, position);
 
-fn main() {
-    let size_x = 1024;
-    let size_y = 1024;
+const USAGE: &str = "usage: vulkano-graphical-pipeline [--check REFERENCE | --check-grey]
+       vulkano-graphical-pipeline --mesh MODEL [OUTPUT]
+
+Renders a red triangle to image.png, or the Wavefront OBJ file MODEL lit by
+a directional light to OUTPUT (default image.png).
 
-    let vertex1 = Vertex {
+options:
+    --check REFERENCE  compare the image with REFERENCE and fail when they
+                       differ, writing the differences to REFERENCE-diff.png
+    --check-grey       render a linear mid-grey instead and check that it is
+                       stored as the expected sRGB byte
+    --mesh MODEL       render MODEL with the materials of its MTL files,
+                       cube.obj for example";
+
+// The triangle drawn in image.png.
+const TRIANGLE: [Vertex; 3] = [
+    Vertex {
         position: [-0.5, -0.5],
-    };
-    let vertex2 = Vertex {
+    },
+    Vertex {
         position: [0.0, 0.5],
-    };
-    let vertex3 = Vertex {
+    },
+    Vertex {
         position: [0.5, -0.25],
+    },
+];
+
+// The sRGB conversion of the render target may be off by one.
+const GREY_TOLERANCE: u8 = 1;
+
+enum Command {
+    Triangle { reference: Option<String> },
+    CheckGrey,
+    Mesh { model: String, output: String },
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let command = match args.iter().map(|arg| &arg[..]).collect::<Vec<_>>()[..] {
+        [] => Command::Triangle { reference: None },
+        ["--check", reference] => Command::Triangle {
+            reference: Some(reference.to_owned()),
+        },
+        ["--check-grey"] => Command::CheckGrey,
+        ["--mesh", model] => Command::Mesh {
+            model: model.to_owned(),
+            output: "image.png".to_owned(),
+        },
+        ["--mesh", model, output] => Command::Mesh {
+            model: model.to_owned(),
+            output: output.to_owned(),
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(1);
+        }
+    };
+
+    let reference = match command {
+        Command::Triangle { reference } => reference,
+        Command::CheckGrey => {
+            let (device, mut queues) = core::init();
+            let queue = queues.next().expect("Couldn't get the first queue");
+            if !grey_matches(&OffscreenRenderer::new(device, queue, 16, 16)) {
+                process::exit(1);
+            }
+            return;
+        }
+        Command::Mesh { model, output } => {
+            // the file is checked before the device is set up
+            let mesh = Mesh::load(&model).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            });
+            println!(
+                "{}: {} vertices, {} triangles, {} materials",
+                model,
+                mesh.vertices.len(),
+                mesh.indices.len() / 3,
+                mesh.materials.len()
+            );
+
+            let (device, mut queues) = core::init();
+            let queue = queues.next().expect("Couldn't get the first queue");
+            let renderer = OffscreenRenderer::new(device, queue, 1024, 1024);
+            let (min, max) = mesh.bounds();
+            let image = lit::render(&renderer, &mesh, &Camera::framing(min, max), [0.05, 0.05, 0.05, 1.0]);
+            image.save(&output).unwrap();
+            return;
+        }
     };
 
     let (device, mut queues) = core::init();
 
     let queue = queues.next().expect("Couldn't get the first queue");
 
-    // Create a buffer to read the resulting image
-    let buffer = CpuAccessibleBuffer::from_iter(
-        device.clone(),
-        BufferUsage::all(),
-        (0 .. size_x * size_y * 4).map(|_| 0u8),
-    ).expect("failed to create the buffer");
+    let renderer = OffscreenRenderer::new(device, queue, 1024, 1024);
+    let image = render(&renderer, &TRIANGLE, [1.0, 0.0, 0.0, 1.0]);
+    image.save("image.png").unwrap();
+
+    if let Some(reference) = reference {
+        match golden::check(&image, &reference, &golden::Tolerance::default()) {
+            Ok(comparison) => println!("matches {}: {}", reference, comparison),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Covers the viewport with a linear grey of 0.5 and checks the byte it is
+/// stored as, 188 once encoded to sRGB rather than the 128 of a linear target.
+fn grey_matches(renderer: &OffscreenRenderer) -> bool {
+    let fullscreen = [
+        Vertex {
+            position: [-1.0, -1.0],
+        },
+        Vertex {
+            position: [3.0, -1.0],
+        },
+        Vertex {
+            position: [-1.0, 3.0],
+        },
+    ];
+
+    let image = render(renderer, &fullscreen, [0.5, 0.5, 0.5, 1.0]);
+    let expected = color::encode(0.5);
+    let (width, height) = renderer.dimensions();
+    let pixel = image.get_pixel(width / 2, height / 2);
+
+    let matches = pixel.data[.. 3]
+        .iter()
+        .all(|&value| value.max(expected) - value.min(expected) <= GREY_TOLERANCE);
+    println!(
+        "{} linear grey 0.5: expected {}, got {:?}",
+        if matches { "ok" } else { "FAILED" },
+        expected,
+        &pixel.data[.. 3]
+    );
+
+    matches
+}
+
+/// Draws `vertices` as triangles of the linear `color` on a blue background
+/// and reads the image back.
+fn render(renderer: &OffscreenRenderer, vertices: &[Vertex], color: [f32; 4]) -> RgbaImage {
+    let device = renderer.device().clone();
 
     // create a buffer for vertices
     let vertex_buffer = CpuAccessibleBuffer::from_iter(
         device.clone(),
         BufferUsage::all(),
-        vec![vertex1, vertex2, vertex3].into_iter(),
+        vertices.iter().cloned(),
     ).expect("failed to create buffer");
 
     // create the vertex and fragment shader
     let vs = shaders::vs::Shader::load(device.clone()).expect("failed to create shader module");
     let fs = shaders::fs::Shader::load(device.clone()).expect("failed to create shader module");
 
-    // Create render pass
-    let render_pass = Arc::new(
-        single_pass_renderpass!(device.clone(),
-            attachments: {
-                color: {
-                    load: Clear,
-                    store: Store,
-                    format: Format::R8G8B8A8Unorm,
-                    samples: 1,
-                }
-            },
-            pass: {
-                color: [color],
-                depth_stencil: {}
-            }
-        ).unwrap(),
-    );
-
-    let image = StorageImage::new(
-        device.clone(),
-        Dimensions::Dim2d {
-            width: size_x,
-            height: size_y,
-        },
-        Format::R8G8B8A8Unorm,
-        Some(queue.family()),
-    ).unwrap();
-
-    // Create a framebuffer
-    let framebuffer = Arc::new(
-        Framebuffer::start(render_pass.clone())
-            .add(image.clone())
-            .unwrap()
-            .build()
-            .unwrap(),
-    );
-
     // Create the graphical pipeline
     let pipeline = Arc::new(
         GraphicsPipeline::start()
@@ -119,58 +199,52 @@ fn main() {
         .vertex_input_single_buffer::<Vertex>()
         // The vertex shader.
         .vertex_shader(vs.main_entry_point(), ())
-        // Defines the viewport (explanations below).
+        // The viewport is set by the renderer when drawing.
         .viewports_dynamic_scissors_irrelevant(1)
         // The fragment shader.
         .fragment_shader(fs.main_entry_point(), ())
-        // This graphics pipeline object concerns the first pass of the render pass.
-        .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+        // This graphics pipeline object concerns the only pass of the renderer.
+        .render_pass(renderer.subpass())
         // Now that everything is specified, we call `build`.
         .build(device.clone())
         .unwrap(),
     );
 
-    let dynamic_state = DynamicState {
-        viewports: Some(vec![Viewport {
-            origin: [0.0, 0.0],
-            dimensions: [1024.0, 1024.0],
-            depth_range: 0.0 .. 1.0,
-        }]),
-        .. DynamicState::none()
-    };
+    // the clear value is linear too, pure blue is the same once encoded
+    renderer.draw(
+        [0.0, 0.0, 1.0, 1.0],
+        pipeline,
+        vertex_buffer,
+        (),
+        shaders::fs::ty::PushConstantData { color },
+    )
+}
 
-    let command_buffer =
-        AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), queue.family())
-            .unwrap()
-            .begin_render_pass(
-                framebuffer.clone(),
-                false,
-                vec![[0.0, 0.0, 1.0, 1.0].into()],
-            ).unwrap()
-            .draw(
-                pipeline.clone(),
-                &dynamic_state,
-                vertex_buffer.clone(),
-                (),
-                (),
-            ).unwrap()
-            .end_render_pass()
-            .unwrap()
-            .copy_image_to_buffer(image.clone(), buffer.clone())
-            .unwrap()
-            .build()
-            .unwrap();
-
-    // execute the pipeline
-    let finished = command_buffer.execute(queue.clone()).unwrap();
-    finished
-        .then_signal_fence_and_flush()
-        .unwrap()
-        .wait(None)
-        .unwrap();
-
-    // save buffer to an image file
-    let buffer_content = buffer.read().unwrap();
-    let image = ImageBuffer::<Rgba<u8>, _>::from_raw(size_x, size_y, &buffer_content[..]).unwrap();
-    image.save("image.png").unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    // The renders need a Vulkan device, they only run with
+    // `cargo test -- --ignored`.
+    fn renderer(width: u32, height: u32) -> OffscreenRenderer {
+        let (device, mut queues) = core::init();
+        let queue = queues.next().expect("Couldn't get the first queue");
+        OffscreenRenderer::new(device, queue, width, height)
+    }
+
+    #[test]
+    #[ignore]
+    fn triangle_matches_the_reference() {
+        let image = render(&renderer(1024, 1024), &TRIANGLE, [1.0, 0.0, 0.0, 1.0]);
+        let reference = Path::new(env!("CARGO_MANIFEST_DIR")).join("image.png");
+        golden::check(&image, reference, &golden::Tolerance::default()).unwrap();
+    }
+
+    #[test]
+    #[ignore]
+    fn grey_is_stored_as_srgb() {
+        assert!(grey_matches(&renderer(16, 16)));
+    }
 }
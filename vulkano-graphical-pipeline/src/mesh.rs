@@ -0,0 +1,522 @@
+//! Wavefront OBJ meshes and their MTL materials.
+//!
+//! Only what a lit render needs is read: positions, texture coordinates and
+//! normals of the faces, which are split in triangles, and the colours of the
+//! materials. Groups, smoothing groups and texture maps are ignored.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path;
+
+/// A corner of a triangle, as read by the vertex shaders.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    /// Texture coordinates with the origin at the top left, as sampled by
+    /// Vulkan, while OBJ files have it at the bottom left.
+    pub uv: [f32; 2],
+}
+
+impl_vertex!(Vertex, position, normal, uv);
+
+/// The colours of a material, taken as linear values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    /// Exponent of the specular highlights.
+    pub shininess: f32,
+}
+
+impl Material {
+    /// A light grey without highlights, for the faces without a material.
+    pub fn default_material() -> Material {
+        Material {
+            name: "default".to_owned(),
+            ambient: [0.0; 3],
+            diffuse: [0.8; 3],
+            specular: [0.0; 3],
+            shininess: 1.0,
+        }
+    }
+}
+
+/// The triangles drawn with the same material.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Part {
+    /// Index in `Mesh::materials`, `None` for the default material.
+    pub material: Option<usize>,
+    /// Range of `Mesh::indices`.
+    pub indices: Range<usize>,
+}
+
+/// Triangles indexing a list of vertices, ready to be copied to a vertex and
+/// an index buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    /// Three per triangle.
+    pub indices: Vec<u32>,
+    pub parts: Vec<Part>,
+    pub materials: Vec<Material>,
+}
+
+// The indices of the attributes of a face corner, from 0.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+impl Mesh {
+    /// Reads the OBJ file at `path` and the MTL files it refers to, which are
+    /// looked for next to it. The MTL files that can't be read are skipped
+    /// with a warning, their faces get the default material.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Mesh, String> {
+        let path = path.as_ref();
+        let source = read_to_string(path)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+        Mesh::parse(&source, |name| {
+            let mtl_path = directory.join(name);
+            let source = match read_to_string(&mtl_path) {
+                Ok(source) => source,
+                Err(err) => {
+                    eprintln!("warning: {}, using the default material", err);
+                    return Ok(Vec::new());
+                }
+            };
+            parse_materials(&source).map_err(|err| format!("{}: {}", mtl_path.display(), err))
+        }).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Parses the `source` of an OBJ file, `load_materials` returning the
+    /// materials of each file name of the `mtllib` lines. The faces of a
+    /// material that none of the files has get the default one.
+    pub fn parse<F>(source: &str, mut load_materials: F) -> Result<Mesh, String>
+    where
+        F: FnMut(&str) -> Result<Vec<Material>, String>,
+    {
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut normals = Vec::new();
+
+        let mut mesh = Mesh {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            parts: vec![Part { material: None, indices: 0 .. 0 }],
+            materials: Vec::new(),
+        };
+        // the corners with a normal are shared by the faces, the others get
+        // the normal of their face
+        let mut shared = HashMap::new();
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut words = line.split_whitespace();
+            let keyword = match words.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+
+            match keyword {
+                "v" => {
+                    let position = parse_vector(words, 3).map_err(|_| error("invalid position"))?;
+                    positions.push([position[0], position[1], position[2]]);
+                }
+                "vt" => {
+                    let uv = parse_vector(words, 1).map_err(|_| error("invalid texture coordinates"))?;
+                    uvs.push([uv[0], 1.0 - uv.get(1).cloned().unwrap_or(0.0)]);
+                }
+                "vn" => {
+                    let normal = parse_vector(words, 3).map_err(|_| error("invalid normal"))?;
+                    normals.push(normalize([normal[0], normal[1], normal[2]]));
+                }
+                "f" => {
+                    let corners = words
+                        .map(|word| parse_corner(word, positions.len(), uvs.len(), normals.len()))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|err| error(&err))?;
+                    if corners.len() < 3 {
+                        return Err(error("a face needs at least 3 corners"));
+                    }
+
+                    let face_normal = polygon_normal(corners.iter().map(|corner| positions[corner.position]));
+                    let indices = corners
+                        .iter()
+                        .map(|&corner| {
+                            if corner.normal.is_some() {
+                                if let Some(&index) = shared.get(&corner) {
+                                    return index;
+                                }
+                            }
+
+                            let index = mesh.vertices.len() as u32;
+                            mesh.vertices.push(Vertex {
+                                position: positions[corner.position],
+                                normal: corner.normal.map_or(face_normal, |normal| normals[normal]),
+                                uv: corner.uv.map_or([0.0, 0.0], |uv| uvs[uv]),
+                            });
+                            if corner.normal.is_some() {
+                                shared.insert(corner, index);
+                            }
+                            index
+                        }).collect::<Vec<_>>();
+
+                    // the polygons are assumed convex and split in a fan
+                    for i in 1 .. indices.len() - 1 {
+                        mesh.indices.extend_from_slice(&[indices[0], indices[i], indices[i + 1]]);
+                    }
+                }
+                "mtllib" => {
+                    for name in words {
+                        mesh.materials.extend(load_materials(name)?);
+                    }
+                }
+                "usemtl" => {
+                    let name = line[keyword.len() ..].trim();
+                    let material = mesh.materials.iter().position(|material| material.name == name);
+
+                    let start = mesh.indices.len();
+                    let last = mesh.parts.last_mut().unwrap();
+                    if last.indices.start == start {
+                        last.material = material;
+                    } else {
+                        last.indices.end = start;
+                        mesh.parts.push(Part { material, indices: start .. start });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if mesh.indices.is_empty() {
+            return Err("no faces".to_owned());
+        }
+        mesh.parts.last_mut().unwrap().indices.end = mesh.indices.len();
+        mesh.parts.retain(|part| !part.indices.is_empty());
+
+        Ok(mesh)
+    }
+
+    /// The corners of the box around the vertices.
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        // a mesh has at least a triangle
+        let mut min = self.vertices[0].position;
+        let mut max = min;
+        for vertex in &self.vertices[1 ..] {
+            for axis in 0 .. 3 {
+                min[axis] = min[axis].min(vertex.position[axis]);
+                max[axis] = max[axis].max(vertex.position[axis]);
+            }
+        }
+        (min, max)
+    }
+
+    /// The material of `part`.
+    pub fn material(&self, part: &Part) -> Material {
+        part.material
+            .map_or_else(Material::default_material, |material| self.materials[material].clone())
+    }
+}
+
+/// Parses the `source` of an MTL file.
+pub fn parse_materials(source: &str) -> Result<Vec<Material>, String> {
+    let mut materials: Vec<Material> = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let error = |message: &str| format!("line {}: {}", number + 1, message);
+
+        if keyword == "newmtl" {
+            materials.push(Material {
+                name: line[keyword.len() ..].trim().to_owned(),
+                ..Material::default_material()
+            });
+            continue;
+        }
+
+        match keyword {
+            "Ka" | "Kd" | "Ks" | "Ns" => {}
+            _ => continue,
+        }
+        let material = materials
+            .last_mut()
+            .ok_or_else(|| error("property outside of a material"))?;
+        match keyword {
+            "Ns" => {
+                material.shininess = parse_vector(words, 1)
+                    .ok()
+                    .filter(|values| values.len() == 1)
+                    .map(|values| values[0])
+                    .ok_or_else(|| error("invalid shininess"))?;
+            }
+            _ => {
+                // a single value is a grey
+                let values = parse_vector(words, 1).map_err(|_| error("invalid colour"))?;
+                let color = match values.len() {
+                    1 => [values[0]; 3],
+                    3 => [values[0], values[1], values[2]],
+                    _ => return Err(error("invalid colour")),
+                };
+                match keyword {
+                    "Ka" => material.ambient = color,
+                    "Kd" => material.diffuse = color,
+                    _ => material.specular = color,
+                }
+            }
+        }
+    }
+
+    Ok(materials)
+}
+
+fn read_to_string(path: &Path) -> Result<String, String> {
+    let mut source = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    Ok(source)
+}
+
+/// Parses at least `count` numbers, and at most 4.
+fn parse_vector<'a, I: Iterator<Item = &'a str>>(words: I, count: usize) -> Result<Vec<f32>, ()> {
+    let values = words
+        .map(|word| word.parse::<f32>().map_err(|_| ()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if values.len() < count || values.len() > 4 || values.iter().any(|value| !value.is_finite()) {
+        return Err(());
+    }
+    Ok(values)
+}
+
+/// Parses a face corner, `v`, `v/vt`, `v//vn` or `v/vt/vn`, whose indices
+/// count from 1, or back from the last element when negative.
+fn parse_corner(word: &str, positions: usize, uvs: usize, normals: usize) -> Result<Corner, String> {
+    let parts = word.split('/').collect::<Vec<_>>();
+    if parts.len() > 3 {
+        return Err(format!("invalid face corner '{}'", word));
+    }
+
+    let index = |part: Option<&&str>, count: usize| -> Result<Option<usize>, String> {
+        match part {
+            None | Some(&"") => Ok(None),
+            Some(part) => {
+                let index = part.parse::<i64>().map_err(|_| format!("invalid face corner '{}'", word))?;
+                let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+                if index == 0 || resolved < 0 || resolved >= count as i64 {
+                    return Err(format!("index out of range in face corner '{}'", word));
+                }
+                Ok(Some(resolved as usize))
+            }
+        }
+    };
+
+    Ok(Corner {
+        position: index(parts.first(), positions)?.ok_or_else(|| format!("invalid face corner '{}'", word))?,
+        uv: index(parts.get(1), uvs)?,
+        normal: index(parts.get(2), normals)?,
+    })
+}
+
+/// The normal of a polygon, by Newell's method, which also works for the
+/// polygons that aren't quite planar.
+fn polygon_normal<I: Iterator<Item = [f32; 3]>>(positions: I) -> [f32; 3] {
+    let positions = positions.collect::<Vec<_>>();
+    let mut normal = [0.0; 3];
+    for (i, current) in positions.iter().enumerate() {
+        let next = &positions[(i + 1) % positions.len()];
+        normal[0] += (current[1] - next[1]) * (current[2] + next[2]);
+        normal[1] += (current[2] - next[2]) * (current[0] + next[0]);
+        normal[2] += (current[0] - next[0]) * (current[1] + next[1]);
+    }
+    normalize(normal)
+}
+
+/// Scales `vector` to a length of 1, a degenerate one is kept as it is.
+pub fn normalize(vector: [f32; 3]) -> [f32; 3] {
+    let length = (vector[0] * vector[0] + vector[1] * vector[1] + vector[2] * vector[2]).sqrt();
+    if length == 0.0 {
+        return vector;
+    }
+    [vector[0] / length, vector[1] / length, vector[2] / length]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    fn no_materials(name: &str) -> Result<Vec<Material>, String> {
+        Err(format!("no {}", name))
+    }
+
+    fn material(name: &str) -> Material {
+        Material {
+            name: name.to_owned(),
+            ..Material::default_material()
+        }
+    }
+
+    #[test]
+    fn polygons_are_split_in_fans() {
+        let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3\nf 1 2 3 4\n";
+        let mesh = Mesh::parse(source, no_materials).unwrap();
+
+        assert_eq!(mesh.indices, vec![0, 1, 2, 3, 4, 5, 3, 5, 6]);
+        assert_eq!(mesh.vertices.len(), 7);
+        assert_eq!(mesh.vertices[6].position, [0.0, 1.0, 0.0]);
+        // counter clockwise faces get the normal facing the viewer
+        assert!(mesh.vertices.iter().all(|vertex| vertex.normal == [0.0, 0.0, 1.0]));
+        assert_eq!(mesh.parts, vec![Part { material: None, indices: 0 .. 9 }]);
+    }
+
+    #[test]
+    fn corners_with_a_normal_are_shared() {
+        let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvn 0 0 2\n\
+                      f 1//1 2//1 3//1\nf 1//1 3//1 4//1\n";
+        let mesh = Mesh::parse(source, no_materials).unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.vertices[0].normal, [0.0, 0.0, 1.0]);
+
+        let mesh = Mesh::parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3\nf 1 3 4\n", no_materials).unwrap();
+        assert_eq!(mesh.vertices.len(), 6);
+    }
+
+    #[test]
+    fn texture_coordinates_are_flipped() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.25 0.75\nvt 1\nf 1/1 2/2 3\n";
+        let mesh = Mesh::parse(source, no_materials).unwrap();
+        assert_eq!(mesh.vertices[0].uv, [0.25, 0.25]);
+        assert_eq!(mesh.vertices[1].uv, [1.0, 1.0]);
+        assert_eq!(mesh.vertices[2].uv, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn materials_split_the_parts() {
+        let source = "mtllib a.mtl  b.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                      usemtl red\nf 1 2 3\nf 1 2 3\n\
+                      usemtl blue\nusemtl green\nf 1 2 3\n\
+                      usemtl unknown\nf 1 2 3\n";
+        let mut files = Vec::new();
+        let mesh = Mesh::parse(source, |name| {
+            files.push(name.to_owned());
+            Ok(match name {
+                "a.mtl" => vec![material("red"), material("blue")],
+                _ => vec![material("green")],
+            })
+        }).unwrap();
+
+        assert_eq!(files, vec!["a.mtl", "b.mtl"]);
+        assert_eq!(mesh.materials.len(), 3);
+        assert_eq!(
+            mesh.parts,
+            vec![
+                Part { material: Some(0), indices: 0 .. 6 },
+                Part { material: Some(2), indices: 6 .. 9 },
+                Part { material: None, indices: 9 .. 12 },
+            ]
+        );
+        assert_eq!(mesh.material(&mesh.parts[2]), Material::default_material());
+    }
+
+    #[test]
+    fn invalid_files_are_errors() {
+        let error = |source: &str| Mesh::parse(source, no_materials).unwrap_err();
+
+        assert_eq!(error("v 0 0 0\n"), "no faces");
+        assert_eq!(error("v 0 0\n"), "line 1: invalid position");
+        assert_eq!(error("v 0 0 0\nv 1 0 0\nf 1 2\n"), "line 3: a face needs at least 3 corners");
+        assert_eq!(
+            error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n"),
+            "line 4: index out of range in face corner '4'"
+        );
+        assert_eq!(error("# materials\nmtllib a.mtl\n"), "no a.mtl");
+    }
+
+    #[test]
+    fn missing_material_files_are_skipped() {
+        let path = env::temp_dir().join("vulkano-graphical-pipeline-missing-mtl.obj");
+        fs::write(&path, "mtllib missing.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\n").unwrap();
+        let mesh = Mesh::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        let mesh = mesh.unwrap();
+        assert!(mesh.materials.is_empty());
+        assert_eq!(mesh.parts, vec![Part { material: None, indices: 0 .. 3 }]);
+    }
+
+    #[test]
+    fn materials_are_parsed() {
+        let source = "# colours\nnewmtl red\nKd 0.8 0.1 0.1\nKs 0.5\nNs 64\n\nnewmtl plain grey\nKa 0.1 0.2 0.3\n";
+        let materials = parse_materials(source).unwrap();
+
+        assert_eq!(
+            materials,
+            vec![
+                Material {
+                    name: "red".to_owned(),
+                    ambient: [0.0; 3],
+                    diffuse: [0.8, 0.1, 0.1],
+                    specular: [0.5; 3],
+                    shininess: 64.0,
+                },
+                Material {
+                    name: "plain grey".to_owned(),
+                    ambient: [0.1, 0.2, 0.3],
+                    ..Material::default_material()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_materials_are_errors() {
+        assert_eq!(parse_materials("Kd 1 1 1\n").unwrap_err(), "line 1: property outside of a material");
+        assert_eq!(parse_materials("newmtl a\nKd 1 1\n").unwrap_err(), "line 2: invalid colour");
+        assert_eq!(parse_materials("newmtl a\nNs 1 2\n").unwrap_err(), "line 2: invalid shininess");
+        // the properties that aren't colours are ignored
+        assert_eq!(parse_materials("newmtl a\nmap_Kd a.png\nillum 2\n").unwrap(), vec![material("a")]);
+    }
+
+    #[test]
+    fn corners_count_from_one() {
+        let corner = |position, uv, normal| Corner { position, uv, normal };
+
+        assert_eq!(parse_corner("3", 4, 0, 0), Ok(corner(2, None, None)));
+        assert_eq!(parse_corner("1/2", 4, 2, 0), Ok(corner(0, Some(1), None)));
+        assert_eq!(parse_corner("1//2", 4, 0, 2), Ok(corner(0, None, Some(1))));
+        assert_eq!(parse_corner("4/1/2", 4, 2, 2), Ok(corner(3, Some(0), Some(1))));
+    }
+
+    #[test]
+    fn negative_corners_count_from_the_end() {
+        let corner = |position, uv, normal| Corner { position, uv, normal };
+
+        assert_eq!(parse_corner("-1", 4, 0, 0), Ok(corner(3, None, None)));
+        assert_eq!(parse_corner("-4/-1/-2", 4, 3, 2), Ok(corner(0, Some(2), Some(0))));
+        assert!(parse_corner("-5", 4, 0, 0).is_err());
+    }
+
+    #[test]
+    fn invalid_corners_are_errors() {
+        for word in &["0", "5", "1/3", "1//3", "/1", "a", "1/a", "1/1/1/1", ""] {
+            assert!(parse_corner(word, 4, 2, 2).is_err(), "{}", word);
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use image::{ImageBuffer, Rgba, RgbaImage};
+
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::CpuAccessibleBuffer;
+
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::command_buffer::CommandBuffer;
+use vulkano::command_buffer::DynamicState;
+
+use vulkano::descriptor::descriptor_set::DescriptorSetsCollection;
+
+use vulkano::device::Device;
+use vulkano::device::Queue;
+
+use vulkano::format::Format;
+
+use vulkano::framebuffer::Framebuffer;
+use vulkano::framebuffer::FramebufferAbstract;
+use vulkano::framebuffer::RenderPassAbstract;
+use vulkano::framebuffer::Subpass;
+
+use vulkano::image::AttachmentImage;
+use vulkano::image::ImageUsage;
+
+use vulkano::pipeline::GraphicsPipelineAbstract;
+use vulkano::pipeline::vertex::VertexSource;
+use vulkano::pipeline::viewport::Viewport;
+
+use vulkano::sync::GpuFuture;
+
+use std::sync::Arc;
+
+use color;
+
+/// Format of the depth buffer.
+pub const DEPTH_FORMAT: Format = Format::D16Unorm;
+
+/// Renders to an image instead of a window and reads the result back.
+///
+/// The renderer owns a single pass render pass with a colour attachment of
+/// `color::RENDER_FORMAT`, unless told otherwise, and a depth attachment
+/// cleared to 1, the image and buffer they are read back through, so that
+/// rendering a frame only takes the draw calls. The pipelines are built for
+/// `subpass()` and can leave the depth test disabled.
+pub struct OffscreenRenderer {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    width: u32,
+    height: u32,
+    render_pass: Arc<RenderPassAbstract + Send + Sync>,
+    image: Arc<AttachmentImage<Format>>,
+    framebuffer: Arc<FramebufferAbstract + Send + Sync>,
+    buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+    dynamic_state: DynamicState,
+}
+
+impl OffscreenRenderer {
+    /// Creates the attachments and readback buffer for images of `width` x
+    /// `height` pixels.
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>, width: u32, height: u32) -> OffscreenRenderer {
+        OffscreenRenderer::with_format(device, queue, width, height, color::RENDER_FORMAT)
+    }
+
+    /// Same as `new` with a colour attachment of another `format` of four
+    /// bytes per pixel, such as `R8G8B8A8Unorm` for the shaders whose colours
+    /// are already encoded.
+    pub fn with_format(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        width: u32,
+        height: u32,
+        format: Format,
+    ) -> OffscreenRenderer {
+        assert_eq!(format.size(), Some(4), "the readback buffer holds four bytes per pixel");
+
+        let render_pass: Arc<RenderPassAbstract + Send + Sync> = Arc::new(
+            single_pass_renderpass!(device.clone(),
+                attachments: {
+                    color: {
+                        load: Clear,
+                        store: Store,
+                        format: format,
+                        samples: 1,
+                    },
+                    depth: {
+                        load: Clear,
+                        store: DontCare,
+                        format: DEPTH_FORMAT,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {depth}
+                }
+            ).unwrap(),
+        );
+
+        // sRGB formats can rarely be used as storage images, this one is only
+        // drawn to and copied from
+        let usage = ImageUsage {
+            color_attachment: true,
+            transfer_source: true,
+            ..ImageUsage::none()
+        };
+        let image = AttachmentImage::with_usage(device.clone(), [width, height], format, usage)
+            .expect("failed to create the image");
+        let depth = AttachmentImage::transient(device.clone(), [width, height], DEPTH_FORMAT)
+            .expect("failed to create the depth buffer");
+
+        let framebuffer = Arc::new(
+            Framebuffer::start(render_pass.clone())
+                .add(image.clone())
+                .unwrap()
+                .add(depth)
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        // Create a buffer to read the resulting image
+        let buffer = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::all(),
+            (0 .. width as usize * height as usize * 4).map(|_| 0u8),
+        ).expect("failed to create the buffer");
+
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [width as f32, height as f32],
+                depth_range: 0.0 .. 1.0,
+            }]),
+            .. DynamicState::none()
+        };
+
+        OffscreenRenderer {
+            device,
+            queue,
+            width,
+            height,
+            render_pass,
+            image,
+            framebuffer,
+            buffer,
+            dynamic_state,
+        }
+    }
+
+    pub fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &Arc<Queue> {
+        &self.queue
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The subpass the pipelines have to be built for.
+    pub fn subpass(&self) -> Subpass<Arc<RenderPassAbstract + Send + Sync>> {
+        Subpass::from(self.render_pass.clone(), 0).unwrap()
+    }
+
+    /// Viewport covering the whole image, for the pipelines with a dynamic
+    /// one.
+    pub fn dynamic_state(&self) -> &DynamicState {
+        &self.dynamic_state
+    }
+
+    /// Clears the image to the linear `clear_color`, lets `record` add its
+    /// draw calls inside the render pass and reads the image back.
+    pub fn render<F>(&self, clear_color: [f32; 4], record: F) -> RgbaImage
+    where
+        F: FnOnce(AutoCommandBufferBuilder, &DynamicState) -> AutoCommandBufferBuilder,
+    {
+        let builder = AutoCommandBufferBuilder::primary_one_time_submit(self.device.clone(), self.queue.family())
+            .unwrap()
+            .begin_render_pass(
+                self.framebuffer.clone(),
+                false,
+                vec![clear_color.into(), 1f32.into()],
+            ).unwrap();
+
+        let command_buffer = record(builder, &self.dynamic_state)
+            .end_render_pass()
+            .unwrap()
+            .copy_image_to_buffer(self.image.clone(), self.buffer.clone())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        // execute the pipeline
+        let finished = command_buffer.execute(self.queue.clone()).unwrap();
+        finished
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        // the bytes of the sRGB format are already encoded, as PNG files
+        // expect them
+        let buffer_content = self.buffer.read().unwrap();
+        ImageBuffer::<Rgba<u8>, _>::from_raw(self.width, self.height, buffer_content.to_vec()).unwrap()
+    }
+
+    /// Renders a single draw call of `pipeline`.
+    pub fn draw<Gp, V, S, Pc>(&self, clear_color: [f32; 4], pipeline: Gp, vertices: V, sets: S, push_constants: Pc) -> RgbaImage
+    where
+        Gp: GraphicsPipelineAbstract + VertexSource<V> + Send + Sync + 'static + Clone,
+        S: DescriptorSetsCollection,
+    {
+        self.render(clear_color, |builder, dynamic_state| {
+            builder
+                .draw(pipeline, dynamic_state, vertices, sets, push_constants)
+                .unwrap()
+        })
+    }
+}
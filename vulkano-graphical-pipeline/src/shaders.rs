@@ -21,8 +21,98 @@ pub mod fs {
 
 layout(location = 0) out vec4 f_color;
 
+// linear colour of the shape, the render target encodes it to sRGB
+layout(push_constant) uniform PushConstantData {
+    vec4 color;
+} pc;
+
+void main() {
+    f_color = pc.color;
+}
+"]
+    struct Dummy;
+}
+
+pub mod lit_vs {
+    #[derive(VulkanoShader)]
+    #[ty = "vertex"]
+    #[src = "
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec2 uv;
+
+layout(location = 0) out vec3 v_position;
+layout(location = 1) out vec3 v_normal;
+layout(location = 2) out vec2 v_uv;
+
+layout(set = 0, binding = 0) uniform Scene {
+    mat4 view_projection;
+    vec4 eye;
+    vec4 light_direction;
+} scene;
+
+void main() {
+    // the meshes are already in world space
+    v_position = position;
+    v_normal = normal;
+    v_uv = uv;
+    gl_Position = scene.view_projection * vec4(position, 1.0);
+}
+"]
+    struct Dummy;
+}
+
+pub mod lit_fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[src = "
+#version 450
+
+layout(location = 0) in vec3 v_position;
+layout(location = 1) in vec3 v_normal;
+layout(location = 2) in vec2 v_uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform Scene {
+    mat4 view_projection;
+    vec4 eye;
+    vec4 light_direction;
+} scene;
+
+// linear colours of the material, the shininess in specular.w
+layout(push_constant) uniform PushConstantData {
+    vec4 ambient;
+    vec4 diffuse;
+    vec4 specular;
+} material;
+
+// light coming from everywhere, so that the faces turned away from the
+// light aren't black
+const float AMBIENT = 0.15;
+
 void main() {
-    f_color = vec4(1.0, 0.0, 0.0, 1.0);
+    vec3 normal = normalize(v_normal);
+    vec3 to_eye = normalize(scene.eye.xyz - v_position);
+    // the faces are lit from both sides, whatever their winding
+    if (dot(normal, to_eye) < 0.0) {
+        normal = -normal;
+    }
+
+    // Blinn-Phong with a single white directional light
+    vec3 to_light = normalize(-scene.light_direction.xyz);
+    float lambert = max(dot(normal, to_light), 0.0);
+    vec3 halfway = normalize(to_light + to_eye);
+    float highlight = lambert > 0.0
+        ? pow(max(dot(normal, halfway), 0.0), max(material.specular.w, 1.0))
+        : 0.0;
+
+    vec3 color = material.ambient.rgb
+        + material.diffuse.rgb * (AMBIENT + lambert)
+        + material.specular.rgb * highlight;
+    f_color = vec4(color, 1.0);
 }
 "]
     struct Dummy;
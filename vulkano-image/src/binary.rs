@@ -0,0 +1,60 @@
+//! Little-endian integers, the byte order of the KTX2 and DDS files.
+
+pub fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend_from_slice(&[value as u8, (value >> 8) as u8]);
+}
+
+pub fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]);
+}
+
+pub fn push_u64(bytes: &mut Vec<u8>, value: u64) {
+    push_u32(bytes, value as u32);
+    push_u32(bytes, (value >> 32) as u32);
+}
+
+pub fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    let bytes = offset
+        .checked_add(4)
+        .and_then(|end| bytes.get(offset .. end))
+        .ok_or_else(|| "the file is truncated".to_owned())?;
+    Ok(u32::from(bytes[0]) | u32::from(bytes[1]) << 8 | u32::from(bytes[2]) << 16 | u32::from(bytes[3]) << 24)
+}
+
+pub fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, String> {
+    Ok(u64::from(read_u32(bytes, offset)?) | u64::from(read_u32(bytes, offset + 4)?) << 32)
+}
+
+/// Overwrites the integer at `offset`, for the tests to tamper with files.
+#[cfg(test)]
+pub fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    let mut value_bytes = Vec::with_capacity(4);
+    push_u32(&mut value_bytes, value);
+    bytes[offset .. offset + 4].copy_from_slice(&value_bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers_read_back() {
+        let mut bytes = Vec::new();
+        push_u16(&mut bytes, 0xBEEF);
+        push_u32(&mut bytes, 0x0123_4567);
+        push_u64(&mut bytes, 0x89AB_CDEF_0011_2233);
+
+        assert_eq!(&bytes[.. 6], &[0xEF, 0xBE, 0x67, 0x45, 0x23, 0x01]);
+        assert_eq!(read_u32(&bytes, 2), Ok(0x0123_4567));
+        assert_eq!(read_u64(&bytes, 6), Ok(0x89AB_CDEF_0011_2233));
+    }
+
+    #[test]
+    fn truncated_integers_are_errors() {
+        let bytes = [0; 6];
+        assert!(read_u32(&bytes, 2).is_ok());
+        assert!(read_u32(&bytes, 3).is_err());
+        assert!(read_u32(&bytes, !0).is_err());
+        assert!(read_u64(&bytes, 0).is_err());
+    }
+}
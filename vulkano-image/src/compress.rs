@@ -0,0 +1,351 @@
+use image::{Rgba, RgbaImage};
+
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::CpuAccessibleBuffer;
+
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+
+use vulkano::format::Format;
+
+use vulkano::image::StorageImage;
+
+use vulkano::pipeline::ComputePipeline;
+
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use dds;
+use ktx2;
+use processor;
+use processor::Processor;
+use shaders;
+
+/// The block compressed formats the images can be encoded to, both made of
+/// blocks of 4 x 4 texels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockFormat {
+    /// 8 bytes per block: two RGB 565 endpoints and 2 bit indices, with
+    /// optional punch-through alpha.
+    Bc1,
+    /// 16 bytes per block: an alpha block with 8 interpolated values followed
+    /// by a BC1 colour block.
+    Bc3,
+}
+
+impl FromStr for BlockFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<BlockFormat, ()> {
+        match s {
+            "bc1" => Ok(BlockFormat::Bc1),
+            "bc3" => Ok(BlockFormat::Bc3),
+            _ => Err(()),
+        }
+    }
+}
+
+impl BlockFormat {
+    pub fn block_bytes(&self) -> usize {
+        match *self {
+            BlockFormat::Bc1 => 8,
+            BlockFormat::Bc3 => 16,
+        }
+    }
+}
+
+/// An image encoded in blocks, row by row, whose colours are the sRGB bytes
+/// of the image they come from.
+pub struct Compressed {
+    pub format: BlockFormat,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl Compressed {
+    /// Encodes `image` in a compute shader, each invocation encoding a block.
+    pub fn encode(processor: &Processor, image: Arc<StorageImage<Format>>, format: BlockFormat) -> Compressed {
+        let device = processor.device().clone();
+        let shader = shaders::block_compress::Shader::load(device.clone()).expect("failed to load shader module");
+        let pipeline = Arc::new(
+            ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+                .expect("failed to create compute pipeline"),
+        );
+
+        let (width, height) = processor::size(&image);
+        let (blocks_x, blocks_y) = block_count(width, height);
+        let words = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::all(),
+            (0 .. blocks_x as usize * blocks_y as usize * format.block_bytes() / 4).map(|_| 0u32),
+        ).expect("failed to create the buffer");
+
+        let set = Arc::new(
+            PersistentDescriptorSet::start(pipeline.clone(), 0)
+                .add_image(image)
+                .unwrap()
+                .add_buffer(words.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        let push_constants = shaders::block_compress::ty::PushConstantData {
+            bc3: (format == BlockFormat::Bc3) as u32,
+        };
+        processor.dispatch_groups(pipeline, set, [(blocks_x + 7) / 8, (blocks_y + 7) / 8, 1], push_constants);
+
+        // the blocks are stored in little endian
+        let words = words.read().unwrap();
+        let data = words
+            .iter()
+            .flat_map(|&word| vec![word as u8, (word >> 8) as u8, (word >> 16) as u8, (word >> 24) as u8])
+            .collect();
+
+        Compressed { format, width, height, data }
+    }
+
+    /// Decodes the blocks on the CPU, like a device sampling them would.
+    pub fn decode(&self) -> RgbaImage {
+        let (blocks_x, _) = block_count(self.width, self.height);
+        let block_bytes = self.format.block_bytes();
+
+        let mut image = RgbaImage::new(self.width, self.height);
+        for (index, block) in self.data.chunks(block_bytes).enumerate() {
+            let texels = match self.format {
+                BlockFormat::Bc1 => decode_color(block, true),
+                BlockFormat::Bc3 => {
+                    let mut texels = decode_color(&block[8 ..], false);
+                    for (texel, alpha) in texels.iter_mut().zip(decode_alpha(&block[.. 8]).iter()) {
+                        texel[3] = *alpha;
+                    }
+                    texels
+                }
+            };
+
+            let (block_x, block_y) = ((index as u32 % blocks_x) * 4, (index as u32 / blocks_x) * 4);
+            for (i, texel) in texels.iter().enumerate() {
+                let (x, y) = (block_x + i as u32 % 4, block_y + i as u32 / 4);
+                if x < self.width && y < self.height {
+                    image.put_pixel(x, y, Rgba(*texel));
+                }
+            }
+        }
+
+        image
+    }
+
+    /// Saves the blocks to `path`, as KTX2 when it ends with `.ktx2` and as
+    /// DDS otherwise.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut file = BufWriter::new(File::create(path)?);
+
+        if path.extension().and_then(|e| e.to_str()) == Some("ktx2") {
+            ktx2::write(&mut file, self)?;
+        } else {
+            dds::write(&mut file, self)?;
+        }
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+impl Compressed {
+    /// Blocks of arbitrary bytes, for the tests of the file formats.
+    pub fn pattern(format: BlockFormat, width: u32, height: u32) -> Compressed {
+        let (blocks_x, blocks_y) = block_count(width, height);
+        let length = blocks_x as usize * blocks_y as usize * format.block_bytes();
+        Compressed {
+            format,
+            width,
+            height,
+            data: (0 .. length).map(|i| (i * 7) as u8).collect(),
+        }
+    }
+}
+
+/// Number of blocks of an image of `width` x `height` texels, the partial
+/// ones included. Any size read from a file can be counted.
+pub fn block_count(width: u32, height: u32) -> (u32, u32) {
+    (width / 4 + (width % 4 != 0) as u32, height / 4 + (height % 4 != 0) as u32)
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from(bytes[0]) | u16::from(bytes[1]) << 8
+}
+
+// the 5 and 6 bit channels are expanded by repeating their high bits
+fn expand_565(color: u16) -> [u32; 3] {
+    let (r, g, b) = (u32::from(color >> 11), u32::from(color >> 5 & 63), u32::from(color & 31));
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+}
+
+/// The 16 texels of a BC1 colour block. Only BC1 itself has the 3 colour
+/// mode, BC3 always interpolates 4 colours.
+fn decode_color(block: &[u8], bc1: bool) -> [[u8; 4]; 16] {
+    let (color0, color1) = (read_u16(&block[0 ..]), read_u16(&block[2 ..]));
+    let (c0, c1) = (expand_565(color0), expand_565(color1));
+
+    let mut palette = [[0u8; 4]; 4];
+    for channel in 0 .. 3 {
+        let (a, b) = (c0[channel], c1[channel]);
+        palette[0][channel] = a as u8;
+        palette[1][channel] = b as u8;
+        if bc1 && color0 <= color1 {
+            palette[2][channel] = ((a + b) / 2) as u8;
+        } else {
+            palette[2][channel] = ((2 * a + b) / 3) as u8;
+            palette[3][channel] = ((a + 2 * b) / 3) as u8;
+        }
+    }
+    palette[0][3] = 255;
+    palette[1][3] = 255;
+    palette[2][3] = 255;
+    palette[3][3] = if bc1 && color0 <= color1 { 0 } else { 255 };
+
+    let indices = u32::from(read_u16(&block[4 ..])) | u32::from(read_u16(&block[6 ..])) << 16;
+    let mut texels = [[0u8; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[(indices >> (2 * i) & 3) as usize];
+    }
+    texels
+}
+
+/// The 16 alphas of a BC3 alpha block.
+fn decode_alpha(block: &[u8]) -> [u8; 16] {
+    let (alpha0, alpha1) = (u32::from(block[0]), u32::from(block[1]));
+
+    let mut palette = [alpha0, alpha1, 0, 0, 0, 0, 0, 0];
+    if alpha0 > alpha1 {
+        for i in 2 .. 8 {
+            palette[i] = ((8 - i as u32) * alpha0 + (i as u32 - 1) * alpha1 + 3) / 7;
+        }
+    } else {
+        for i in 2 .. 6 {
+            palette[i] = ((6 - i as u32) * alpha0 + (i as u32 - 1) * alpha1 + 2) / 5;
+        }
+        palette[7] = 255;
+    }
+
+    let indices = block[2 ..]
+        .iter()
+        .enumerate()
+        .fold(0u64, |indices, (i, &byte)| indices | u64::from(byte) << (8 * i));
+    let mut alphas = [0u8; 16];
+    for (i, alpha) in alphas.iter_mut().enumerate() {
+        *alpha = palette[(indices >> (3 * i) & 7) as usize] as u8;
+    }
+    alphas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The indices of 16 texels of a colour block, 2 bits each.
+    fn color_indices(indices: &[u32]) -> [u8; 4] {
+        let packed = indices.iter().enumerate().fold(0u32, |packed, (i, &index)| packed | index << (2 * i));
+        [packed as u8, (packed >> 8) as u8, (packed >> 16) as u8, (packed >> 24) as u8]
+    }
+
+    // The indices of 16 texels of an alpha block, 3 bits each.
+    fn alpha_indices(indices: &[u64]) -> Vec<u8> {
+        let packed = indices.iter().enumerate().fold(0u64, |packed, (i, &index)| packed | index << (3 * i));
+        (0 .. 6).map(|i| (packed >> (8 * i)) as u8).collect()
+    }
+
+    fn color_block(color0: u16, color1: u16, indices: &[u32]) -> Vec<u8> {
+        let mut block = vec![color0 as u8, (color0 >> 8) as u8, color1 as u8, (color1 >> 8) as u8];
+        block.extend_from_slice(&color_indices(indices));
+        block
+    }
+
+    fn decode(format: BlockFormat, width: u32, height: u32, data: Vec<u8>) -> RgbaImage {
+        Compressed { format, width, height, data }.decode()
+    }
+
+    // The texels of a 4 x 4 image, row by row.
+    fn texels(image: &RgbaImage) -> Vec<[u8; 4]> {
+        image.pixels().map(|pixel| pixel.data).collect()
+    }
+
+    const RED: u16 = 0xF800;
+    const BLUE: u16 = 0x001F;
+
+    #[test]
+    fn bc1_interpolates_four_colours() {
+        let indices = (0 .. 16).map(|i| i % 4).collect::<Vec<_>>();
+        let image = decode(BlockFormat::Bc1, 4, 4, color_block(RED, BLUE, &indices));
+
+        let palette = [[255, 0, 0, 255], [0, 0, 255, 255], [170, 0, 85, 255], [85, 0, 170, 255]];
+        for (i, texel) in texels(&image).iter().enumerate() {
+            assert_eq!(*texel, palette[i % 4], "texel {}", i);
+        }
+    }
+
+    #[test]
+    fn bc1_has_a_three_colour_mode_with_transparent_black() {
+        let indices = (0 .. 16).map(|i| i % 4).collect::<Vec<_>>();
+        let image = decode(BlockFormat::Bc1, 4, 4, color_block(BLUE, RED, &indices));
+
+        let palette = [[0, 0, 255, 255], [255, 0, 0, 255], [127, 0, 127, 255], [0, 0, 0, 0]];
+        for (i, texel) in texels(&image).iter().enumerate() {
+            assert_eq!(*texel, palette[i % 4], "texel {}", i);
+        }
+
+        // equal endpoints are the three colour mode too
+        let image = decode(BlockFormat::Bc1, 4, 4, color_block(RED, RED, &[3; 16]));
+        assert!(texels(&image).iter().all(|texel| *texel == [0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn bc3_has_eight_alphas() {
+        let mut block = vec![255, 0];
+        block.extend(alpha_indices(&(0 .. 16).map(|i| i % 8).collect::<Vec<_>>()));
+        // the colours are always interpolated in BC3, whatever the order of
+        // the endpoints
+        block.extend(color_block(BLUE, RED, &(0 .. 16).map(|i| i % 4).collect::<Vec<_>>()));
+        let image = decode(BlockFormat::Bc3, 4, 4, block);
+
+        let alphas = [255, 0, 219, 182, 146, 109, 73, 36];
+        let colors = [[0, 0, 255], [255, 0, 0], [85, 0, 170], [170, 0, 85]];
+        for (i, texel) in texels(&image).iter().enumerate() {
+            let color = colors[i % 4];
+            assert_eq!(*texel, [color[0], color[1], color[2], alphas[i % 8]], "texel {}", i);
+        }
+    }
+
+    #[test]
+    fn bc3_has_six_alphas_with_transparent_and_opaque() {
+        let mut block = vec![50, 250];
+        block.extend(alpha_indices(&(0 .. 16).map(|i| 7 - i % 8).collect::<Vec<_>>()));
+        block.extend(color_block(RED, BLUE, &[0; 16]));
+        let image = decode(BlockFormat::Bc3, 4, 4, block);
+
+        let alphas = [50, 250, 90, 130, 170, 210, 0, 255];
+        for (i, texel) in texels(&image).iter().enumerate() {
+            assert_eq!(*texel, [255, 0, 0, alphas[7 - i % 8]], "texel {}", i);
+        }
+    }
+
+    #[test]
+    fn partial_blocks_are_cropped() {
+        // 2 x 2 blocks of a solid colour each: red, blue, black and white
+        let mut data = Vec::new();
+        for &color in &[RED, BLUE, 0x0000, 0xFFFF] {
+            data.extend(color_block(color, 0, &[0; 16]));
+        }
+        let image = decode(BlockFormat::Bc1, 6, 5, data);
+
+        assert_eq!(image.dimensions(), (6, 5));
+        assert_eq!(image.get_pixel(3, 3).data, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(4, 0).data, [0, 0, 255, 255]);
+        assert_eq!(image.get_pixel(0, 4).data, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(5, 4).data, [255, 255, 255, 255]);
+    }
+}
@@ -0,0 +1,316 @@
+//! DirectDraw Surface files, the container of block compressed textures of
+//! Direct3D.
+
+use std::io;
+use std::io::Write;
+
+use binary::{push_u32, read_u32};
+use compress::{BlockFormat, Compressed};
+use formats::PixelFormat;
+use texture::{Texture, TextureFormat};
+
+const MAGIC: &[u8; 4] = b"DDS ";
+const HEADER_SIZE: u32 = 124;
+const PIXEL_FORMAT_SIZE: u32 = 32;
+
+// flags of the header
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+const DDSD_LINEARSIZE: u32 = 0x8_0000;
+const DDSD_DEPTH: u32 = 0x80_0000;
+
+// flags of the pixel format
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x2_0000;
+
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALL_FACES: u32 = 0xFC00;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+
+// the extended header of the `DX10` four character code
+const DX10_HEADER_SIZE: usize = 20;
+const D3D10_RESOURCE_DIMENSION_TEXTURE1D: u32 = 2;
+const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+const D3D10_RESOURCE_DIMENSION_TEXTURE3D: u32 = 4;
+const D3D10_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+/// Whether `bytes` start like a DDS file.
+pub fn is_dds(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// The texel format of a `DXGI_FORMAT` value, among the ones that can be
+/// loaded.
+fn dxgi_format(format: u32) -> Option<TextureFormat> {
+    Some(match format {
+        2 => TextureFormat::Pixels(PixelFormat::R32G32B32A32Sfloat),
+        10 => TextureFormat::Pixels(PixelFormat::R16G16B16A16Sfloat),
+        28 => TextureFormat::Pixels(PixelFormat::R8G8B8A8Unorm),
+        29 => TextureFormat::Pixels(PixelFormat::R8G8B8A8Srgb),
+        61 => TextureFormat::Pixels(PixelFormat::R8Unorm),
+        71 => TextureFormat::Blocks {
+            format: BlockFormat::Bc1,
+            srgb: false,
+            alpha: true,
+        },
+        72 => TextureFormat::Blocks {
+            format: BlockFormat::Bc1,
+            srgb: true,
+            alpha: true,
+        },
+        77 => TextureFormat::Blocks {
+            format: BlockFormat::Bc3,
+            srgb: false,
+            alpha: true,
+        },
+        78 => TextureFormat::Blocks {
+            format: BlockFormat::Bc3,
+            srgb: true,
+            alpha: true,
+        },
+        87 => TextureFormat::Pixels(PixelFormat::B8G8R8A8Unorm),
+        _ => return None,
+    })
+}
+
+/// The texel format described by the pixel format of the header, when it
+/// isn't an extended one.
+fn legacy_format(flags: u32, four_cc: &[u8], bit_count: u32, red_mask: u32) -> Option<TextureFormat> {
+    if flags & DDPF_FOURCC != 0 {
+        return match four_cc {
+            b"DXT1" => Some(TextureFormat::Blocks {
+                format: BlockFormat::Bc1,
+                srgb: false,
+                alpha: true,
+            }),
+            b"DXT5" => Some(TextureFormat::Blocks {
+                format: BlockFormat::Bc3,
+                srgb: false,
+                alpha: true,
+            }),
+            // D3DFMT_A16B16G16R16F and D3DFMT_A32B32G32R32F
+            [113, 0, 0, 0] => Some(TextureFormat::Pixels(PixelFormat::R16G16B16A16Sfloat)),
+            [116, 0, 0, 0] => Some(TextureFormat::Pixels(PixelFormat::R32G32B32A32Sfloat)),
+            _ => None,
+        };
+    }
+
+    match (flags & (DDPF_RGB | DDPF_LUMINANCE), bit_count, red_mask) {
+        (DDPF_RGB, 32, 0xFF) => Some(TextureFormat::Pixels(PixelFormat::R8G8B8A8Unorm)),
+        (DDPF_RGB, 32, 0xFF_0000) => Some(TextureFormat::Pixels(PixelFormat::B8G8R8A8Unorm)),
+        (DDPF_LUMINANCE, 8, _) => Some(TextureFormat::Pixels(PixelFormat::R8Unorm)),
+        _ => None,
+    }
+}
+
+/// Reads a DDS file, with or without the extended header.
+pub fn read(bytes: &[u8]) -> Result<Texture, String> {
+    if !is_dds(bytes) || read_u32(bytes, 4)? != HEADER_SIZE {
+        return Err("not a DDS file".to_owned());
+    }
+
+    // the offsets of the header fields, after the magic number
+    let flags = read_u32(bytes, 8)?;
+    let (height, width) = (read_u32(bytes, 12)?, read_u32(bytes, 16)?);
+    let depth = if flags & DDSD_DEPTH != 0 { read_u32(bytes, 24)? } else { 1 };
+    let level_count = if flags & DDSD_MIPMAPCOUNT != 0 { read_u32(bytes, 28)? } else { 1 };
+    let pixel_flags = read_u32(bytes, 80)?;
+    let four_cc = bytes.get(84 .. 88).ok_or_else(|| "the file is truncated".to_owned())?;
+    let caps2 = read_u32(bytes, 112)?;
+
+    if width == 0 || height == 0 {
+        return Err("the texture is empty".to_owned());
+    }
+
+    let mut texture = Texture {
+        format: TextureFormat::Pixels(PixelFormat::R8G8B8A8Unorm),
+        width,
+        height,
+        depth: 1,
+        layers: 1,
+        faces: 1,
+        array: false,
+        one_dimensional: false,
+        levels: Vec::new(),
+    };
+
+    let mut offset = 4 + HEADER_SIZE as usize;
+    if pixel_flags & DDPF_FOURCC != 0 && four_cc == b"DX10" {
+        let format = read_u32(bytes, offset)?;
+        texture.format = dxgi_format(format).ok_or_else(|| format!("unsupported DXGI format {}", format))?;
+
+        let resource_dimension = read_u32(bytes, offset + 4)?;
+        let array_size = read_u32(bytes, offset + 12)?.max(1);
+        if read_u32(bytes, offset + 8)? & D3D10_RESOURCE_MISC_TEXTURECUBE != 0 {
+            texture.faces = 6;
+        }
+        if resource_dimension == D3D10_RESOURCE_DIMENSION_TEXTURE3D {
+            texture.depth = depth.max(1);
+        }
+        texture.one_dimensional = resource_dimension == D3D10_RESOURCE_DIMENSION_TEXTURE1D;
+        texture.layers = array_size;
+        texture.array = array_size > 1;
+        offset += DX10_HEADER_SIZE;
+    } else {
+        texture.format = legacy_format(pixel_flags, four_cc, read_u32(bytes, 88)?, read_u32(bytes, 92)?)
+            .ok_or_else(|| "unsupported pixel format".to_owned())?;
+
+        if caps2 & DDSCAPS2_CUBEMAP != 0 {
+            if caps2 & DDSCAPS2_CUBEMAP_ALL_FACES != DDSCAPS2_CUBEMAP_ALL_FACES {
+                return Err("cube maps with missing faces are not supported".to_owned());
+            }
+            texture.faces = 6;
+        }
+        if caps2 & DDSCAPS2_VOLUME != 0 {
+            texture.depth = depth.max(1);
+        }
+    }
+
+    if texture.faces == 6 && width != height {
+        return Err("the faces of a cube map must be square".to_owned());
+    }
+
+    texture.check_counts(level_count)?;
+    texture.levels = vec![Vec::new(); level_count.max(1) as usize];
+
+    // the file holds the whole chain of each layer and face in turn, the
+    // levels gather them
+    for _ in 0 .. texture.layers * texture.faces {
+        for level in 0 .. texture.levels.len() {
+            let (level_width, level_height, level_depth) = texture.level_size(level as u32);
+            let length = texture
+                .format
+                .image_bytes(level_width, level_height)
+                .and_then(|length| length.checked_mul(level_depth as usize))
+                .ok_or_else(|| format!("level {} is too big", level))?;
+            let data = offset
+                .checked_add(length)
+                .and_then(|end| bytes.get(offset .. end))
+                .ok_or_else(|| format!("level {} is past the end of the file", level))?;
+            texture.levels[level].extend_from_slice(data);
+            offset += length;
+        }
+    }
+
+    Ok(texture)
+}
+
+/// Writes `image` as a DDS file with a single level, in the extended header
+/// of the `DX10` four character code since the legacy `DXT1` and `DXT5` ones
+/// can't tell that the colours are sRGB.
+pub fn write<W: Write>(writer: &mut W, image: &Compressed) -> io::Result<()> {
+    let dxgi_format = match image.format {
+        // DXGI_FORMAT_BC1_UNORM_SRGB
+        BlockFormat::Bc1 => 72,
+        // DXGI_FORMAT_BC3_UNORM_SRGB
+        BlockFormat::Bc3 => 78,
+    };
+
+    writer.write_all(MAGIC)?;
+    let mut header = Vec::with_capacity(HEADER_SIZE as usize + DX10_HEADER_SIZE);
+    push_u32(&mut header, HEADER_SIZE);
+    push_u32(&mut header, DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT | DDSD_LINEARSIZE);
+    push_u32(&mut header, image.height);
+    push_u32(&mut header, image.width);
+    // size of the top level
+    push_u32(&mut header, image.data.len() as u32);
+    // depth and number of mipmaps
+    push_u32(&mut header, 0);
+    push_u32(&mut header, 0);
+    header.extend_from_slice(&[0; 11 * 4]);
+
+    push_u32(&mut header, PIXEL_FORMAT_SIZE);
+    push_u32(&mut header, DDPF_FOURCC);
+    header.extend_from_slice(b"DX10");
+    // the bit counts and masks only apply to uncompressed formats
+    header.extend_from_slice(&[0; 5 * 4]);
+
+    push_u32(&mut header, DDSCAPS_TEXTURE);
+    header.extend_from_slice(&[0; 4 * 4]);
+    debug_assert_eq!(header.len(), HEADER_SIZE as usize);
+
+    // a single 2D image, no cube map flag and no alpha mode
+    push_u32(&mut header, dxgi_format);
+    push_u32(&mut header, D3D10_RESOURCE_DIMENSION_TEXTURE2D);
+    push_u32(&mut header, 0);
+    push_u32(&mut header, 1);
+    push_u32(&mut header, 0);
+    debug_assert_eq!(header.len(), HEADER_SIZE as usize + DX10_HEADER_SIZE);
+
+    writer.write_all(&header)?;
+    writer.write_all(&image.data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use binary::write_u32;
+
+    fn written(image: &Compressed) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(&mut bytes, image).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn written_blocks_read_back() {
+        for &format in &[BlockFormat::Bc1, BlockFormat::Bc3] {
+            let image = Compressed::pattern(format, 5, 3);
+            let texture = read(&written(&image)).unwrap();
+
+            assert_eq!(
+                texture.format,
+                TextureFormat::Blocks {
+                    format,
+                    srgb: true,
+                    alpha: true,
+                }
+            );
+            assert_eq!((texture.width, texture.height, texture.depth), (5, 3, 1));
+            assert_eq!((texture.layers, texture.faces, texture.array), (1, 1, false));
+            assert_eq!(texture.levels, vec![image.data.clone()]);
+        }
+    }
+
+    #[test]
+    fn levels_past_the_full_chain_are_rejected() {
+        let mut bytes = written(&Compressed::pattern(BlockFormat::Bc1, 5, 3));
+        let flags = read_u32(&bytes, 8).unwrap();
+        write_u32(&mut bytes, 8, flags | DDSD_MIPMAPCOUNT);
+
+        // 5x3, 2x1 and 1x1
+        write_u32(&mut bytes, 28, 4);
+        assert!(read(&bytes).err().unwrap().contains("full mipmap chain"));
+        write_u32(&mut bytes, 28, 0xFFFF_FFFF);
+        assert!(read(&bytes).err().unwrap().contains("full mipmap chain"));
+        write_u32(&mut bytes, 28, 3);
+        assert!(read(&bytes).err().unwrap().contains("past the end"));
+    }
+
+    #[test]
+    fn overflowing_layers_are_rejected() {
+        let mut bytes = written(&Compressed::pattern(BlockFormat::Bc1, 4, 4));
+        let dx10_header = 4 + HEADER_SIZE as usize;
+        write_u32(&mut bytes, dx10_header + 8, D3D10_RESOURCE_MISC_TEXTURECUBE);
+        write_u32(&mut bytes, dx10_header + 12, 0x8000_0000);
+        assert!(read(&bytes).err().unwrap().contains("too many"));
+    }
+
+    #[test]
+    fn overflowing_sizes_are_rejected() {
+        let mut bytes = written(&Compressed::pattern(BlockFormat::Bc3, 4, 4));
+        write_u32(&mut bytes, 12, 0xFFFF_FFFF);
+        write_u32(&mut bytes, 16, 0xFFFF_FFFF);
+        assert!(read(&bytes).err().unwrap().contains("too big"));
+
+        // DXGI_FORMAT_R32G32B32A32_FLOAT
+        write_u32(&mut bytes, 4 + HEADER_SIZE as usize, 2);
+        assert!(read(&bytes).err().unwrap().contains("too big"));
+    }
+}
@@ -0,0 +1,278 @@
+use image::RgbaImage;
+
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::CpuAccessibleBuffer;
+
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+
+use vulkano::format::Format;
+
+use vulkano::image::StorageImage;
+
+use vulkano::pipeline::ComputePipeline;
+use vulkano::pipeline::ComputePipelineAbstract;
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use kernel::Kernel;
+use processor;
+use processor::Processor;
+use reference;
+use shaders;
+
+/// Largest radius of the blurs.
+pub const MAX_RADIUS: u32 = 64;
+
+/// The compute filters that can be applied to a loaded image.
+///
+/// Every filter reads the `rgba8` storage image at binding 0 and writes the
+/// one of the same size at binding 1, the alpha channel is kept as is.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    Invert,
+    Grayscale,
+    /// Separable Gaussian blur of the given radius.
+    Blur(u32),
+    /// Separable box blur of the given radius.
+    BoxBlur(u32),
+    /// Unsharp mask, with the radius of its blur and how much of the details
+    /// are added back.
+    Unsharp(u32, f32),
+    Sharpen,
+    Emboss,
+    /// Magnitude of the Sobel gradient of the luma.
+    Sobel,
+    /// Magnitude of the Scharr gradient of the luma, more accurate on
+    /// diagonal edges.
+    Scharr,
+    /// Convolution with a kernel read from a file.
+    Kernel(Kernel),
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    /// Parses `name` or `name:argument:...`, like `blur:3` or `unsharp:2:1.5`.
+    fn from_str(s: &str) -> Result<Filter, String> {
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or("");
+        let arguments = parts.collect::<Vec<_>>();
+
+        let radius = |index: usize| -> Result<u32, String> {
+            let radius = arguments
+                .get(index)
+                .ok_or_else(|| format!("missing radius for the {} filter", name))?;
+            match radius.parse() {
+                Ok(radius) if radius >= 1 && radius <= MAX_RADIUS => Ok(radius),
+                _ => Err(format!("invalid radius '{}' for the {} filter, expected 1 to {}", radius, name, MAX_RADIUS)),
+            }
+        };
+
+        let (filter, count) = match name {
+            "invert" => (Filter::Invert, 0),
+            "grayscale" => (Filter::Grayscale, 0),
+            "blur" => (Filter::Blur(radius(0)?), 1),
+            "box" => (Filter::BoxBlur(radius(0)?), 1),
+            "unsharp" => {
+                let amount = match arguments.get(1) {
+                    Some(amount) => amount
+                        .parse()
+                        .map_err(|_| format!("invalid amount '{}' for the unsharp filter", amount))?,
+                    None => 1.0,
+                };
+                (Filter::Unsharp(radius(0)?, amount), 2)
+            }
+            "sharpen" => (Filter::Sharpen, 0),
+            "emboss" => (Filter::Emboss, 0),
+            "sobel" => (Filter::Sobel, 0),
+            "scharr" => (Filter::Scharr, 0),
+            "kernel" => {
+                let path = arguments
+                    .get(0)
+                    .ok_or_else(|| "missing file for the kernel filter".to_owned())?;
+                (Filter::Kernel(Kernel::load(path)?), 1)
+            }
+            _ => return Err(format!("unknown filter '{}'", s)),
+        };
+
+        if arguments.len() > count {
+            return Err(format!("too many arguments for the {} filter in '{}'", name, s));
+        }
+
+        Ok(filter)
+    }
+}
+
+impl Filter {
+    /// Runs the filter on `source` and returns the filtered image.
+    pub fn apply(&self, processor: &Processor, source: Arc<StorageImage<Format>>) -> Arc<StorageImage<Format>> {
+        let device = processor.device().clone();
+
+        match *self {
+            Filter::Invert => {
+                let shader = shaders::invert::Shader::load(device.clone()).expect("failed to load shader module");
+                let pipeline = Arc::new(
+                    ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+                        .expect("failed to create compute pipeline"),
+                );
+                run(processor, pipeline, source, ())
+            }
+            Filter::Grayscale => {
+                let shader = shaders::grayscale::Shader::load(device.clone()).expect("failed to load shader module");
+                let pipeline = Arc::new(
+                    ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+                        .expect("failed to create compute pipeline"),
+                );
+                run(processor, pipeline, source, ())
+            }
+            Filter::Blur(radius) => convolve_separable(processor, source, &Kernel::gaussian(radius)),
+            Filter::BoxBlur(radius) => convolve_separable(processor, source, &Kernel::average(radius)),
+            Filter::Unsharp(radius, amount) => {
+                let blurred = convolve_separable(processor, source.clone(), &Kernel::gaussian(radius));
+
+                let shader = shaders::unsharp::Shader::load(device.clone()).expect("failed to load shader module");
+                let pipeline = Arc::new(
+                    ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+                        .expect("failed to create compute pipeline"),
+                );
+
+                let (width, height) = processor::size(&source);
+                let destination = processor.create_image(width, height);
+                let set = Arc::new(
+                    PersistentDescriptorSet::start(pipeline.clone(), 0)
+                        .add_image(source)
+                        .unwrap()
+                        .add_image(destination.clone())
+                        .unwrap()
+                        .add_image(blurred)
+                        .unwrap()
+                        .build()
+                        .unwrap(),
+                );
+
+                let push_constants = shaders::unsharp::ty::PushConstantData { amount };
+                processor.dispatch(pipeline, set, (width, height), push_constants);
+                destination
+            }
+            Filter::Sharpen => convolve(processor, source, &Kernel::sharpen(), 0.0),
+            Filter::Emboss => convolve(processor, source, &Kernel::emboss(), 0.5),
+            Filter::Sobel | Filter::Scharr => {
+                let shader = shaders::gradient::Shader::load(device.clone()).expect("failed to load shader module");
+                let pipeline = Arc::new(
+                    ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+                        .expect("failed to create compute pipeline"),
+                );
+
+                let (side, center) = self.gradient_weights();
+                let push_constants = shaders::gradient::ty::PushConstantData { side, center };
+                run(processor, pipeline, source, push_constants)
+            }
+            Filter::Kernel(ref kernel) => convolve(processor, source, kernel, 0.0),
+        }
+    }
+
+    /// Runs the filter on the CPU, the result should be the same as the one
+    /// of `apply` give or take the rounding.
+    pub fn apply_cpu(&self, image: &RgbaImage) -> RgbaImage {
+        match *self {
+            Filter::Invert => reference::invert(image),
+            Filter::Grayscale => reference::grayscale(image),
+            Filter::Blur(radius) => reference::convolve_separable(image, &Kernel::gaussian(radius)),
+            Filter::BoxBlur(radius) => reference::convolve_separable(image, &Kernel::average(radius)),
+            Filter::Unsharp(radius, amount) => {
+                let blurred = reference::convolve_separable(image, &Kernel::gaussian(radius));
+                reference::unsharp(image, &blurred, amount)
+            }
+            Filter::Sharpen => reference::convolve(image, &Kernel::sharpen(), 0.0),
+            Filter::Emboss => reference::convolve(image, &Kernel::emboss(), 0.5),
+            Filter::Sobel | Filter::Scharr => {
+                let (side, center) = self.gradient_weights();
+                reference::gradient(image, side, center)
+            }
+            Filter::Kernel(ref kernel) => reference::convolve(image, kernel, 0.0),
+        }
+    }
+
+    // the weights smoothing the derivatives of the gradients
+    fn gradient_weights(&self) -> (f32, f32) {
+        match *self {
+            Filter::Scharr => (3.0, 10.0),
+            _ => (1.0, 2.0),
+        }
+    }
+}
+
+/// Runs `pipeline` with `source` and a new image of the same size bound.
+fn run<Pc>(
+    processor: &Processor,
+    pipeline: Arc<ComputePipelineAbstract + Send + Sync>,
+    source: Arc<StorageImage<Format>>,
+    push_constants: Pc,
+) -> Arc<StorageImage<Format>> {
+    let (width, height) = processor::size(&source);
+    let destination = processor.create_image(width, height);
+
+    let set = Arc::new(
+        PersistentDescriptorSet::start(pipeline.clone(), 0)
+            .add_image(source)
+            .unwrap()
+            .add_image(destination.clone())
+            .unwrap()
+            .build()
+            .unwrap(),
+    );
+
+    processor.dispatch(pipeline, set, (width, height), push_constants);
+    destination
+}
+
+/// Applies `kernel` to `source` and adds `bias` to the result.
+fn convolve(
+    processor: &Processor,
+    source: Arc<StorageImage<Format>>,
+    kernel: &Kernel,
+    bias: f32,
+) -> Arc<StorageImage<Format>> {
+    let device = processor.device().clone();
+    let shader = shaders::convolution::Shader::load(device.clone()).expect("failed to load shader module");
+    let pipeline = Arc::new(
+        ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+            .expect("failed to create compute pipeline"),
+    );
+
+    let weights = CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::all(), kernel.weights.iter().cloned())
+        .expect("failed to create the buffer");
+
+    let (width, height) = processor::size(&source);
+    let destination = processor.create_image(width, height);
+    let set = Arc::new(
+        PersistentDescriptorSet::start(pipeline.clone(), 0)
+            .add_image(source)
+            .unwrap()
+            .add_image(destination.clone())
+            .unwrap()
+            .add_buffer(weights)
+            .unwrap()
+            .build()
+            .unwrap(),
+    );
+
+    let push_constants = shaders::convolution::ty::PushConstantData {
+        kernel_size: [kernel.width as i32, kernel.height as i32],
+        bias,
+    };
+    processor.dispatch(pipeline, set, (width, height), push_constants);
+    destination
+}
+
+/// Applies the horizontal kernel `row`, then its transpose. A blur of radius
+/// `r` takes `4r + 2` samples per pixel this way instead of `(2r + 1)^2`.
+fn convolve_separable(
+    processor: &Processor,
+    source: Arc<StorageImage<Format>>,
+    row: &Kernel,
+) -> Arc<StorageImage<Format>> {
+    let horizontal = convolve(processor, source, row, 0.0);
+    convolve(processor, horizontal, &row.transpose(), 0.0)
+}
@@ -0,0 +1,325 @@
+use image::{DynamicImage, ImageBuffer, Luma, Rgba};
+
+use vulkano::format::ClearValue;
+use vulkano::format::Format;
+
+use std::str::FromStr;
+
+/// The image formats that can be cleared and read back.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFormat {
+    R8Unorm,
+    R8G8B8A8Unorm,
+    R8G8B8A8Srgb,
+    B8G8R8A8Unorm,
+    R16G16B16A16Sfloat,
+    R32G32B32A32Sfloat,
+    D16Unorm,
+    D32Sfloat,
+}
+
+pub const ALL_FORMATS: [PixelFormat; 8] = [
+    PixelFormat::R8Unorm,
+    PixelFormat::R8G8B8A8Unorm,
+    PixelFormat::R8G8B8A8Srgb,
+    PixelFormat::B8G8R8A8Unorm,
+    PixelFormat::R16G16B16A16Sfloat,
+    PixelFormat::R32G32B32A32Sfloat,
+    PixelFormat::D16Unorm,
+    PixelFormat::D32Sfloat,
+];
+
+impl FromStr for PixelFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<PixelFormat, ()> {
+        ALL_FORMATS
+            .iter()
+            .find(|format| format.name() == s)
+            .cloned()
+            .ok_or(())
+    }
+}
+
+impl PixelFormat {
+    /// Name of the format on the command line and in the output files.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PixelFormat::R8Unorm => "r8",
+            PixelFormat::R8G8B8A8Unorm => "rgba8",
+            PixelFormat::R8G8B8A8Srgb => "rgba8-srgb",
+            PixelFormat::B8G8R8A8Unorm => "bgra8",
+            PixelFormat::R16G16B16A16Sfloat => "rgba16f",
+            PixelFormat::R32G32B32A32Sfloat => "rgba32f",
+            PixelFormat::D16Unorm => "d16",
+            PixelFormat::D32Sfloat => "d32f",
+        }
+    }
+
+    pub fn format(&self) -> Format {
+        match *self {
+            PixelFormat::R8Unorm => Format::R8Unorm,
+            PixelFormat::R8G8B8A8Unorm => Format::R8G8B8A8Unorm,
+            PixelFormat::R8G8B8A8Srgb => Format::R8G8B8A8Srgb,
+            PixelFormat::B8G8R8A8Unorm => Format::B8G8R8A8Unorm,
+            PixelFormat::R16G16B16A16Sfloat => Format::R16G16B16A16Sfloat,
+            PixelFormat::R32G32B32A32Sfloat => Format::R32G32B32A32Sfloat,
+            PixelFormat::D16Unorm => Format::D16Unorm,
+            PixelFormat::D32Sfloat => Format::D32Sfloat,
+        }
+    }
+
+    pub fn is_depth(&self) -> bool {
+        match *self {
+            PixelFormat::D16Unorm | PixelFormat::D32Sfloat => true,
+            _ => false,
+        }
+    }
+
+    /// Bytes of a texel once read back. Depth images can't be copied to a
+    /// buffer directly, they are sampled into a buffer of floats instead.
+    pub fn texel_size(&self) -> usize {
+        match *self {
+            PixelFormat::R8Unorm => 1,
+            PixelFormat::R8G8B8A8Unorm | PixelFormat::R8G8B8A8Srgb | PixelFormat::B8G8R8A8Unorm => 4,
+            PixelFormat::R16G16B16A16Sfloat => 8,
+            PixelFormat::R32G32B32A32Sfloat => 16,
+            PixelFormat::D16Unorm | PixelFormat::D32Sfloat => 4,
+        }
+    }
+
+    /// The value the image is cleared with, `color` for the colour formats
+    /// and `depth` for the depth ones.
+    pub fn clear_value(&self, color: [f32; 4], depth: f32) -> ClearValue {
+        if self.is_depth() {
+            ClearValue::Depth(depth)
+        } else {
+            ClearValue::Float(color)
+        }
+    }
+
+    /// Decodes the `index`-th texel of the read back `data` as RGBA floats,
+    /// the single channel formats only fill the red one. The values of the
+    /// sRGB format are left encoded.
+    pub fn texel(&self, data: &[u8], index: usize) -> [f32; 4] {
+        let size = self.texel_size();
+        let texel = &data[index * size .. (index + 1) * size];
+        let unorm = |value: u8| value as f32 / 255.0;
+
+        match *self {
+            PixelFormat::R8Unorm => [unorm(texel[0]), 0.0, 0.0, 1.0],
+            PixelFormat::R8G8B8A8Unorm | PixelFormat::R8G8B8A8Srgb => [
+                unorm(texel[0]),
+                unorm(texel[1]),
+                unorm(texel[2]),
+                unorm(texel[3]),
+            ],
+            PixelFormat::B8G8R8A8Unorm => [
+                unorm(texel[2]),
+                unorm(texel[1]),
+                unorm(texel[0]),
+                unorm(texel[3]),
+            ],
+            PixelFormat::R16G16B16A16Sfloat => [
+                half_to_f32(read_u16(&texel[0 .. 2])),
+                half_to_f32(read_u16(&texel[2 .. 4])),
+                half_to_f32(read_u16(&texel[4 .. 6])),
+                half_to_f32(read_u16(&texel[6 .. 8])),
+            ],
+            PixelFormat::R32G32B32A32Sfloat => [
+                read_f32(&texel[0 .. 4]),
+                read_f32(&texel[4 .. 8]),
+                read_f32(&texel[8 .. 12]),
+                read_f32(&texel[12 .. 16]),
+            ],
+            PixelFormat::D16Unorm | PixelFormat::D32Sfloat => [read_f32(texel), 0.0, 0.0, 1.0],
+        }
+    }
+
+    /// Converts the read back `data` of a `width` x `height` image to the
+    /// matching pixel type of the `image` crate: the single channel formats
+    /// become grey images and the others RGBA ones. The float formats hold
+    /// linear colours, they are clamped to `[0, 1]` and encoded to sRGB like
+    /// PNG files expect, except for the alpha channel.
+    pub fn to_image(&self, width: u32, height: u32, data: &[u8]) -> DynamicImage {
+        let pixels = width as usize * height as usize;
+        assert_eq!(data.len(), pixels * self.texel_size());
+
+        match *self {
+            PixelFormat::R8Unorm => {
+                DynamicImage::ImageLuma8(ImageBuffer::<Luma<u8>, _>::from_raw(width, height, data.to_vec()).unwrap())
+            }
+            // the bytes of the sRGB format are already encoded for display
+            PixelFormat::R8G8B8A8Unorm | PixelFormat::R8G8B8A8Srgb => {
+                DynamicImage::ImageRgba8(ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, data.to_vec()).unwrap())
+            }
+            PixelFormat::B8G8R8A8Unorm => {
+                let mut bytes = data.to_vec();
+                for texel in bytes.chunks_mut(4) {
+                    texel.swap(0, 2);
+                }
+                DynamicImage::ImageRgba8(ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, bytes).unwrap())
+            }
+            PixelFormat::R16G16B16A16Sfloat | PixelFormat::R32G32B32A32Sfloat => {
+                let bytes = (0 .. pixels)
+                    .flat_map(|index| {
+                        let [r, g, b, a] = self.texel(data, index);
+                        vec![to_srgb_byte(r), to_srgb_byte(g), to_srgb_byte(b), to_byte(a)]
+                    }).collect();
+                DynamicImage::ImageRgba8(ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, bytes).unwrap())
+            }
+            PixelFormat::D16Unorm | PixelFormat::D32Sfloat => {
+                let bytes = (0 .. pixels).map(|index| to_byte(self.texel(data, index)[0])).collect();
+                DynamicImage::ImageLuma8(ImageBuffer::<Luma<u8>, _>::from_raw(width, height, bytes).unwrap())
+            }
+        }
+    }
+}
+
+fn to_byte(value: f32) -> u8 {
+    (value.max(0.0).min(1.0) * 255.0).round() as u8
+}
+
+fn to_srgb_byte(value: f32) -> u8 {
+    let value = value.max(0.0).min(1.0);
+    if value <= 0.003_130_8 {
+        to_byte(value * 12.92)
+    } else {
+        to_byte(1.055 * value.powf(1.0 / 2.4) - 0.055)
+    }
+}
+
+// The buffers are read back in the byte order of the host.
+fn read_u16(bytes: &[u8]) -> u16 {
+    let mut value = [0u8; 2];
+    value.copy_from_slice(bytes);
+    if cfg!(target_endian = "big") {
+        value.reverse();
+    }
+    u16::from(value[0]) | u16::from(value[1]) << 8
+}
+
+fn read_f32(bytes: &[u8]) -> f32 {
+    let mut value = [0u8; 4];
+    value.copy_from_slice(bytes);
+    if cfg!(target_endian = "big") {
+        value.reverse();
+    }
+    let bits = u32::from(value[0])
+        | u32::from(value[1]) << 8
+        | u32::from(value[2]) << 16
+        | u32::from(value[3]) << 24;
+    f32::from_bits(bits)
+}
+
+/// Converts an IEEE 754 half precision float to single precision.
+fn half_to_f32(half: u16) -> f32 {
+    let sign = u32::from(half >> 15) << 31;
+    let exponent = u32::from(half >> 10) & 0x1f;
+    let mantissa = u32::from(half) & 0x3ff;
+
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        // subnormal, normalized for single precision
+        (0, _) => {
+            let shift = mantissa.leading_zeros() - 21;
+            sign | (113 - shift) << 23 | (mantissa << shift & 0x3ff) << 13
+        }
+        // infinities and NaNs
+        (0x1f, _) => sign | 0xff << 23 | mantissa << 13,
+        _ => sign | (exponent + 112) << 23 | mantissa << 13,
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f32;
+
+    // The bytes of the values as a buffer read back on this host.
+    fn host_bytes(values: &[u32], size: usize) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|&value| {
+                let mut bytes = (0 .. size).map(|i| (value >> (8 * i)) as u8).collect::<Vec<_>>();
+                if cfg!(target_endian = "big") {
+                    bytes.reverse();
+                }
+                bytes
+            }).collect()
+    }
+
+    #[test]
+    fn halves_are_converted() {
+        assert_eq!(half_to_f32(0x0000), 0.0);
+        assert!(half_to_f32(0x0000).is_sign_positive());
+        assert_eq!(half_to_f32(0x8000), 0.0);
+        assert!(half_to_f32(0x8000).is_sign_negative());
+
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+        assert_eq!(half_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(half_to_f32(0x7bff), 65504.0);
+    }
+
+    #[test]
+    fn subnormal_halves_are_normalized() {
+        let smallest = 2.0f32.powi(-24);
+        assert_eq!(half_to_f32(0x0001), smallest);
+        assert_eq!(half_to_f32(0x8001), -smallest);
+        assert_eq!(half_to_f32(0x0200), 512.0 * smallest);
+        assert_eq!(half_to_f32(0x03ff), 1023.0 * smallest);
+    }
+
+    #[test]
+    fn infinite_halves_stay_special() {
+        assert_eq!(half_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(half_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!(half_to_f32(0x7e00).is_nan());
+        assert!(half_to_f32(0x7c01).is_nan());
+        assert!(half_to_f32(0xffff).is_nan());
+    }
+
+    #[test]
+    fn linear_values_are_encoded_to_srgb() {
+        assert_eq!(to_srgb_byte(0.0), 0);
+        assert_eq!(to_srgb_byte(0.002), 7);
+        assert_eq!(to_srgb_byte(0.5), 188);
+        assert_eq!(to_srgb_byte(1.0), 255);
+        assert_eq!(to_srgb_byte(-1.0), 0);
+        assert_eq!(to_srgb_byte(2.0), 255);
+        assert_eq!(to_srgb_byte(f32::INFINITY), 255);
+    }
+
+    #[test]
+    fn bgra_texels_come_out_as_rgba() {
+        let data = [10, 20, 30, 40, 50, 60, 70, 80];
+        let format = PixelFormat::B8G8R8A8Unorm;
+
+        let texel = format.texel(&data, 1);
+        assert_eq!(texel, [70.0 / 255.0, 60.0 / 255.0, 50.0 / 255.0, 80.0 / 255.0]);
+
+        match format.to_image(2, 1, &data) {
+            DynamicImage::ImageRgba8(image) => assert_eq!(image.into_raw(), vec![30, 20, 10, 40, 70, 60, 50, 80]),
+            _ => panic!("expected an RGBA image"),
+        }
+    }
+
+    #[test]
+    fn float_texels_are_encoded_to_srgb_except_alpha() {
+        let values = [0.5f32, -1.0, 4.0, 0.5];
+        let data = host_bytes(&values.iter().map(|value| value.to_bits()).collect::<Vec<_>>(), 4);
+        let format = PixelFormat::R32G32B32A32Sfloat;
+
+        assert_eq!(format.texel(&data, 0), values);
+        match format.to_image(1, 1, &data) {
+            DynamicImage::ImageRgba8(image) => assert_eq!(image.into_raw(), vec![188, 0, 255, 128]),
+            _ => panic!("expected an RGBA image"),
+        }
+
+        let halves = host_bytes(&[0x3800, 0xbc00, 0x7c00, 0x3800], 2);
+        assert_eq!(PixelFormat::R16G16B16A16Sfloat.texel(&halves, 0), [0.5, -1.0, f32::INFINITY, 0.5]);
+    }
+}
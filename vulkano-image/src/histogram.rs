@@ -0,0 +1,454 @@
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::CpuAccessibleBuffer;
+
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+
+use vulkano::format::Format;
+
+use vulkano::image::StorageImage;
+
+use vulkano::pipeline::ComputePipeline;
+
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use processor;
+use processor::Processor;
+use shaders;
+
+/// Number of bins of each channel, one per 8 bit value.
+pub const BINS: usize = 256;
+
+/// Names of the channels counted, in the order of the bins.
+pub const CHANNELS: [&str; 4] = ["red", "green", "blue", "luma"];
+
+// Side of the blocks of pixels counted by each work group of the shader.
+const BLOCK_SIZE: u32 = 64;
+
+/// Largest number of tiles per side of CLAHE.
+pub const MAX_TILES: u32 = 64;
+
+/// The counts of the 8 bit values of the red, green, blue and luma channels
+/// of an image, or of a tile of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    /// For each value, how many pixels have it in each channel.
+    pub bins: Vec<[u32; 4]>,
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram { bins: vec![[0; 4]; BINS] }
+    }
+
+    /// Counts the values of `image` on the device.
+    pub fn compute(processor: &Processor, image: Arc<StorageImage<Format>>) -> Histogram {
+        Histogram::compute_tiles(processor, image, (1, 1)).remove(0)
+    }
+
+    /// Splits `image` in `tiles` x `tiles` tiles of about the same size and
+    /// counts the values of each one, row by row.
+    ///
+    /// The work groups count the pixels of a block with atomics on shared
+    /// memory, then add their counts to the ones of the tile in the buffer,
+    /// which is much less contended than counting every pixel in it.
+    pub fn compute_tiles(
+        processor: &Processor,
+        image: Arc<StorageImage<Format>>,
+        tiles: (u32, u32),
+    ) -> Vec<Histogram> {
+        let device = processor.device().clone();
+        let shader = shaders::histogram::Shader::load(device.clone()).expect("failed to load shader module");
+        let pipeline = Arc::new(
+            ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+                .expect("failed to create compute pipeline"),
+        );
+
+        let tile_count = (tiles.0 * tiles.1) as usize;
+        let bins = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::all(),
+            (0 .. tile_count * BINS * 4).map(|_| 0u32),
+        ).expect("failed to create the buffer");
+
+        // the tiles may differ by a pixel, every block of the largest one
+        // gets a group
+        let (width, height) = processor::size(&image);
+        let blocks = (
+            ((width + tiles.0 - 1) / tiles.0 + BLOCK_SIZE - 1) / BLOCK_SIZE,
+            ((height + tiles.1 - 1) / tiles.1 + BLOCK_SIZE - 1) / BLOCK_SIZE,
+        );
+
+        let set = Arc::new(
+            PersistentDescriptorSet::start(pipeline.clone(), 0)
+                .add_image(image)
+                .unwrap()
+                .add_buffer(bins.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        let push_constants = shaders::histogram::ty::PushConstantData {
+            tiles: [tiles.0 as i32, tiles.1 as i32],
+            blocks: [blocks.0 as i32, blocks.1 as i32],
+        };
+        processor.dispatch_groups(
+            pipeline,
+            set,
+            [tiles.0 * blocks.0, tiles.1 * blocks.1, 1],
+            push_constants,
+        );
+
+        // the buffer holds the 256 bins of each channel one after the other
+        let counts = bins.read().unwrap();
+        counts
+            .chunks(BINS * 4)
+            .map(|tile| {
+                let mut histogram = Histogram::new();
+                for (value, bin) in histogram.bins.iter_mut().enumerate() {
+                    for channel in 0 .. 4 {
+                        bin[channel] = tile[channel * BINS + value];
+                    }
+                }
+                histogram
+            }).collect()
+    }
+
+    /// Number of pixels counted.
+    pub fn pixels(&self) -> u64 {
+        self.bins.iter().map(|bin| bin[3] as u64).sum()
+    }
+
+    /// Maps each luma to the one that spreads the pixels evenly over the
+    /// whole range, as a value between 0 and 1. With a `clip_limit`, no bin
+    /// counts more than that many times the mean before the mapping, what
+    /// goes over being spread across all of them.
+    pub fn equalization_table(&self, clip_limit: Option<f32>) -> Vec<f32> {
+        let pixels = self.pixels() as f32;
+        let mut counts = self.bins.iter().map(|bin| bin[3] as f32).collect::<Vec<_>>();
+
+        if let Some(clip_limit) = clip_limit {
+            let limit = (clip_limit * pixels / BINS as f32).max(1.0);
+            let excess: f32 = counts.iter().map(|&count| (count - limit).max(0.0)).sum();
+            for count in &mut counts {
+                *count = count.min(limit) + excess / BINS as f32;
+            }
+        }
+
+        let mut cumulated = 0.0;
+        let cdf = counts
+            .iter()
+            .map(|count| {
+                cumulated += count;
+                cumulated
+            }).collect::<Vec<_>>();
+
+        // the darkest luma present stays black
+        let first = cdf.iter().cloned().find(|&value| value > 0.0).unwrap_or(0.0);
+        if pixels - first <= 0.0 {
+            return (0 .. BINS).map(|value| value as f32 / 255.0).collect();
+        }
+        cdf.iter().map(|value| ((value - first) / (pixels - first)).max(0.0)).collect()
+    }
+
+    /// Writes the counts as CSV, with a row per value.
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "value,{}", CHANNELS.join(","))?;
+        for (value, bin) in self.bins.iter().enumerate() {
+            writeln!(writer, "{},{},{},{},{}", value, bin[0], bin[1], bin[2], bin[3])?;
+        }
+        Ok(())
+    }
+
+    /// Writes the counts as a JSON object with an array of 256 counts per
+    /// channel.
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{{")?;
+        for (channel, name) in CHANNELS.iter().enumerate() {
+            let counts = self
+                .bins
+                .iter()
+                .map(|bin| bin[channel].to_string())
+                .collect::<Vec<_>>();
+            let separator = if channel + 1 < CHANNELS.len() { "," } else { "" };
+            writeln!(writer, "  \"{}\": [{}]{}", name, counts.join(", "), separator)?;
+        }
+        writeln!(writer, "}}")
+    }
+
+    /// Saves the counts to `path`, as JSON when it ends with `.json` and as
+    /// CSV otherwise.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut file = BufWriter::new(File::create(path)?);
+
+        if path.extension().and_then(|e| e.to_str()) == Some("json") {
+            self.write_json(&mut file)?;
+        } else {
+            self.write_csv(&mut file)?;
+        }
+        file.flush()
+    }
+}
+
+/// The bin of the luma of a colour, the integer approximation of BT.601 the
+/// shaders use.
+pub fn luma_bin(r: u8, g: u8, b: u8) -> usize {
+    (77 * r as usize + 150 * g as usize + 29 * b as usize + 128) >> 8
+}
+
+/// How the contrast of an image is equalized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Equalization {
+    /// A single mapping of the luma for the whole image.
+    Global,
+    /// Contrast limited adaptive histogram equalization: the image is split
+    /// in `tiles` x `tiles` tiles each equalized on their own, with their
+    /// bins clipped to `clip_limit` times the mean, and the mappings of the
+    /// closest tiles are blended for each pixel.
+    Clahe { tiles: u32, clip_limit: f32 },
+}
+
+impl FromStr for Equalization {
+    type Err = String;
+
+    /// Parses `global`, `clahe`, `clahe:TILES` or `clahe:TILES:CLIP`.
+    fn from_str(s: &str) -> Result<Equalization, String> {
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or("");
+        let arguments = parts.collect::<Vec<_>>();
+
+        match (name, arguments.len()) {
+            ("global", 0) => Ok(Equalization::Global),
+            ("clahe", count) if count <= 2 => {
+                let tiles = match arguments.get(0) {
+                    Some(tiles) => match tiles.parse() {
+                        Ok(tiles) if tiles >= 1 && tiles <= MAX_TILES => tiles,
+                        _ => return Err(format!("invalid tiles '{}', expected 1 to {}", tiles, MAX_TILES)),
+                    },
+                    None => 8,
+                };
+                let clip_limit = match arguments.get(1) {
+                    Some(clip) => match clip.parse() {
+                        Ok(clip) if clip >= 1.0 => clip,
+                        _ => return Err(format!("invalid clip limit '{}', expected at least 1", clip)),
+                    },
+                    None => 2.0,
+                };
+                Ok(Equalization::Clahe { tiles, clip_limit })
+            }
+            _ => Err(format!("unknown equalization '{}'", s)),
+        }
+    }
+}
+
+impl Equalization {
+    /// Equalizes the luma of `source`, the chroma and alpha are kept.
+    pub fn apply(&self, processor: &Processor, source: Arc<StorageImage<Format>>) -> Arc<StorageImage<Format>> {
+        let (tiles, clip_limit) = match *self {
+            Equalization::Global => (1, None),
+            Equalization::Clahe { tiles, clip_limit } => (tiles, Some(clip_limit)),
+        };
+
+        // a tile needs at least a pixel
+        let (width, height) = processor::size(&source);
+        let tiles = (tiles.min(width), tiles.min(height));
+
+        let tables = Histogram::compute_tiles(processor, source.clone(), tiles)
+            .iter()
+            .flat_map(|histogram| histogram.equalization_table(clip_limit))
+            .collect::<Vec<_>>();
+
+        let device = processor.device().clone();
+        let shader = shaders::equalize::Shader::load(device.clone()).expect("failed to load shader module");
+        let pipeline = Arc::new(
+            ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+                .expect("failed to create compute pipeline"),
+        );
+
+        let luts = CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::all(), tables.into_iter())
+            .expect("failed to create the buffer");
+
+        let destination = processor.create_image(width, height);
+        let set = Arc::new(
+            PersistentDescriptorSet::start(pipeline.clone(), 0)
+                .add_image(source)
+                .unwrap()
+                .add_image(destination.clone())
+                .unwrap()
+                .add_buffer(luts)
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        let push_constants = shaders::equalize::ty::PushConstantData {
+            tiles: [tiles.0 as i32, tiles.1 as i32],
+        };
+        processor.dispatch(pipeline, set, (width, height), push_constants);
+        destination
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    // A histogram with `count` pixels of each luma given, the colour channels
+    // are counted the same.
+    fn histogram(counts: &[(usize, u32)]) -> Histogram {
+        let mut histogram = Histogram::new();
+        for &(value, count) in counts {
+            histogram.bins[value] = [count; 4];
+        }
+        histogram
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn equalization_spreads_the_pixels() {
+        let table = histogram(&[(10, 2), (20, 2)]).equalization_table(None);
+        assert_eq!(table.len(), BINS);
+        assert_close(table[0], 0.0);
+        assert_close(table[10], 0.0);
+        assert_close(table[19], 0.0);
+        assert_close(table[20], 1.0);
+        assert_close(table[255], 1.0);
+    }
+
+    #[test]
+    fn single_values_keep_the_identity() {
+        for histogram in &[Histogram::new(), histogram(&[(100, 7)])] {
+            let table = histogram.equalization_table(None);
+            for (value, &mapped) in table.iter().enumerate() {
+                assert_close(mapped, value as f32 / 255.0);
+            }
+        }
+    }
+
+    #[test]
+    fn clipped_counts_are_spread_over_every_bin() {
+        // 1024 pixels, 4 per bin on average: the two full bins are clipped to
+        // 8 and their excess of 2 x 504 gives 3.9375 to every bin
+        let histogram = histogram(&[(0, 512), (255, 512)]);
+
+        let unclipped = histogram.equalization_table(None);
+        assert_close(unclipped[127], 0.0);
+        assert_close(unclipped[254], 0.0);
+        assert_close(unclipped[255], 1.0);
+
+        let table = histogram.equalization_table(Some(2.0));
+        let range = 1024.0 - (8.0 + 3.9375);
+        assert_close(table[0], 0.0);
+        for (value, &mapped) in table.iter().enumerate().take(255).skip(1) {
+            assert_close(mapped, value as f32 * 3.9375 / range);
+        }
+        assert_close(table[255], 1.0);
+    }
+
+    #[test]
+    fn clip_limits_are_at_least_a_pixel() {
+        // 4 pixels are less than one per bin, the limit stays at one
+        let table = histogram(&[(0, 2), (255, 2)]).equalization_table(Some(1.0));
+        let range = 4.0 - (1.0 + 2.0 / 256.0);
+        assert_close(table[0], 0.0);
+        assert_close(table[128], 128.0 * 2.0 / 256.0 / range);
+        assert_close(table[255], 1.0);
+    }
+
+    #[test]
+    fn equalizations_are_parsed() {
+        assert_eq!("global".parse(), Ok(Equalization::Global));
+        assert_eq!("clahe".parse(), Ok(Equalization::Clahe { tiles: 8, clip_limit: 2.0 }));
+        assert_eq!("clahe:4".parse(), Ok(Equalization::Clahe { tiles: 4, clip_limit: 2.0 }));
+        assert_eq!("clahe:64:3.5".parse(), Ok(Equalization::Clahe { tiles: 64, clip_limit: 3.5 }));
+    }
+
+    #[test]
+    fn invalid_equalizations_are_rejected() {
+        let cases = [
+            ("", "unknown equalization ''"),
+            ("local", "unknown equalization 'local'"),
+            ("global:2", "unknown equalization 'global:2'"),
+            ("clahe:4:2:1", "unknown equalization 'clahe:4:2:1'"),
+            ("clahe:0", "invalid tiles '0', expected 1 to 64"),
+            ("clahe:65", "invalid tiles '65', expected 1 to 64"),
+            ("clahe:four", "invalid tiles 'four', expected 1 to 64"),
+            ("clahe:4:0.5", "invalid clip limit '0.5', expected at least 1"),
+            ("clahe:4:", "invalid clip limit '', expected at least 1"),
+        ];
+        for &(text, error) in &cases {
+            assert_eq!(text.parse::<Equalization>(), Err(error.to_owned()));
+        }
+    }
+
+    fn counted() -> Histogram {
+        let mut histogram = Histogram::new();
+        histogram.bins[0] = [1, 2, 3, 4];
+        histogram.bins[255] = [5, 6, 7, 8];
+        histogram
+    }
+
+    #[test]
+    fn csv_has_a_row_per_value() {
+        let mut csv = Vec::new();
+        counted().write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1 + BINS);
+        assert_eq!(lines[0], "value,red,green,blue,luma");
+        assert_eq!(lines[1], "0,1,2,3,4");
+        assert_eq!(lines[2], "1,0,0,0,0");
+        assert_eq!(lines[256], "255,5,6,7,8");
+    }
+
+    #[test]
+    fn json_has_an_array_per_channel() {
+        let mut json = Vec::new();
+        counted().write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+
+        let lines = json.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2 + CHANNELS.len());
+        assert_eq!(lines[0], "{");
+        assert_eq!(lines[5], "}");
+        for (channel, name) in CHANNELS.iter().enumerate() {
+            let line = lines[1 + channel];
+            let prefix = format!("  \"{}\": [", name);
+            let suffix = if channel + 1 < CHANNELS.len() { "]," } else { "]" };
+            assert!(line.starts_with(&prefix) && line.ends_with(suffix), "{}", line);
+
+            let counts = line[prefix.len() .. line.len() - suffix.len()]
+                .split(", ")
+                .map(|count| count.parse::<u32>().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(counts.len(), BINS);
+            assert_eq!(counts[0], 1 + channel as u32);
+            assert_eq!(counts[255], 5 + channel as u32);
+            assert_eq!(counts[1 .. 255].iter().sum::<u32>(), 0);
+        }
+    }
+
+    #[test]
+    fn saved_files_follow_the_extension() {
+        for &(extension, start) in &[("json", "{"), ("csv", "value,"), ("txt", "value,")] {
+            let path = env::temp_dir().join(format!("imagevk-histogram.{}", extension));
+            counted().save(&path).unwrap();
+            let saved = fs::read_to_string(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert!(saved.starts_with(start), "{}", extension);
+        }
+    }
+}
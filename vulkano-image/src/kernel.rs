@@ -0,0 +1,210 @@
+use std::fs;
+
+/// Largest kernel that can be given on the command line, on both axes.
+pub const MAX_KERNEL_SIZE: u32 = 15;
+
+/// Weights of a convolution, stored row by row.
+///
+/// The kernel is centered on each pixel and applied without flipping it, the
+/// way image editors do, so the weights are laid out like the neighbours
+/// they are multiplied with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kernel {
+    pub width: u32,
+    pub height: u32,
+    pub weights: Vec<f32>,
+}
+
+impl Kernel {
+    /// Creates a kernel with an odd number of rows and columns, each at most
+    /// `MAX_KERNEL_SIZE`.
+    pub fn new(width: u32, height: u32, weights: Vec<f32>) -> Result<Kernel, String> {
+        if width % 2 == 0 || height % 2 == 0 {
+            return Err(format!("a {}x{} kernel has no center, both sizes must be odd", width, height));
+        }
+        if width > MAX_KERNEL_SIZE || height > MAX_KERNEL_SIZE {
+            return Err(format!(
+                "a {}x{} kernel is too big, at most {}x{} are supported",
+                width, height, MAX_KERNEL_SIZE, MAX_KERNEL_SIZE
+            ));
+        }
+        assert_eq!(weights.len(), width as usize * height as usize);
+
+        Ok(Kernel { width, height, weights })
+    }
+
+    /// Reads a kernel from a text file with one row per line, the weights
+    /// being separated by spaces or commas.
+    pub fn load(path: &str) -> Result<Kernel, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("failed to read {}: {}", path, err))?;
+
+        let rows = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                line.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|weight| !weight.is_empty())
+                    .map(|weight| weight.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| format!("invalid weight in '{}' of {}", line, path))
+            }).collect::<Result<Vec<_>, _>>()?;
+
+        let width = rows.first().map(|row| row.len()).unwrap_or(0);
+        if width == 0 || rows.iter().any(|row| row.len() != width) {
+            return Err(format!("the rows of the kernel in {} don't all have the same size", path));
+        }
+
+        Kernel::new(width as u32, rows.len() as u32, rows.concat())
+    }
+
+    /// A horizontal Gaussian kernel of `2 * radius + 1` weights, its standard
+    /// deviation is derived from the radius like OpenCV does.
+    pub fn gaussian(radius: u32) -> Kernel {
+        let sigma = 0.3 * (radius as f32 - 1.0) + 0.8;
+        let weights = (0 .. 2 * radius + 1)
+            .map(|i| {
+                let x = i as f32 - radius as f32;
+                (-x * x / (2.0 * sigma * sigma)).exp()
+            }).collect();
+
+        Kernel::normalized(2 * radius + 1, 1, weights)
+    }
+
+    /// A horizontal kernel averaging `2 * radius + 1` pixels.
+    pub fn average(radius: u32) -> Kernel {
+        Kernel::normalized(2 * radius + 1, 1, vec![1.0; 2 * radius as usize + 1])
+    }
+
+    pub fn sharpen() -> Kernel {
+        Kernel {
+            width: 3,
+            height: 3,
+            weights: vec![0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0],
+        }
+    }
+
+    /// Lights the image from the top left, it is meant to be applied with a
+    /// bias of 0.5 so that flat areas end up grey.
+    pub fn emboss() -> Kernel {
+        Kernel {
+            width: 3,
+            height: 3,
+            weights: vec![-1.0, -1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 1.0, 1.0],
+        }
+    }
+
+    /// The same kernel along the other axis.
+    pub fn transpose(&self) -> Kernel {
+        let weights = (0 .. self.width)
+            .flat_map(|x| (0 .. self.height).map(move |y| (x, y)))
+            .map(|(x, y)| self.weights[(y * self.width + x) as usize])
+            .collect();
+
+        Kernel {
+            width: self.height,
+            height: self.width,
+            weights,
+        }
+    }
+
+    fn normalized(width: u32, height: u32, weights: Vec<f32>) -> Kernel {
+        let sum: f32 = weights.iter().sum();
+        Kernel {
+            width,
+            height,
+            weights: weights.iter().map(|weight| weight / sum).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    // Writes `text` to a file of the temporary directory and loads it.
+    fn load_text(name: &str, text: &str) -> Result<Kernel, String> {
+        let path = env::temp_dir().join(format!("imagevk-kernel-{}.txt", name));
+        fs::write(&path, text).unwrap();
+        let kernel = Kernel::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        kernel
+    }
+
+    #[test]
+    fn sizes_must_be_odd() {
+        assert!(Kernel::new(3, 2, vec![0.0; 6]).is_err());
+        assert!(Kernel::new(2, 3, vec![0.0; 6]).is_err());
+        assert!(Kernel::new(1, 1, vec![1.0]).is_ok());
+        assert!(Kernel::new(3, 5, vec![0.0; 15]).is_ok());
+    }
+
+    #[test]
+    fn sizes_are_limited() {
+        let size = MAX_KERNEL_SIZE;
+        assert!(Kernel::new(size, size, vec![0.0; (size * size) as usize]).is_ok());
+        assert!(Kernel::new(size + 2, 1, vec![0.0; size as usize + 2]).is_err());
+        assert!(Kernel::new(1, size + 2, vec![0.0; size as usize + 2]).is_err());
+    }
+
+    #[test]
+    fn transpose_swaps_rows_and_columns() {
+        let kernel = Kernel::new(3, 1, vec![1.0, 2.0, 3.0]).unwrap();
+        assert_eq!(kernel.transpose(), Kernel::new(1, 3, vec![1.0, 2.0, 3.0]).unwrap());
+
+        let kernel = Kernel::new(3, 5, (0 .. 15).map(|i| i as f32).collect()).unwrap();
+        let transposed = kernel.transpose();
+        assert_eq!((transposed.width, transposed.height), (5, 3));
+        for y in 0 .. 5 {
+            for x in 0 .. 3 {
+                assert_eq!(transposed.weights[(x * 5 + y) as usize], kernel.weights[(y * 3 + x) as usize]);
+            }
+        }
+        assert_eq!(transposed.transpose(), kernel);
+    }
+
+    #[test]
+    fn gaussian_is_normalized_and_symmetric() {
+        for radius in 1 .. 8 {
+            let kernel = Kernel::gaussian(radius);
+            assert_eq!((kernel.width, kernel.height), (2 * radius + 1, 1));
+
+            let sum: f32 = kernel.weights.iter().sum();
+            assert!((sum - 1.0).abs() < 1e-6);
+
+            let weights = &kernel.weights;
+            for i in 0 .. radius as usize {
+                assert!((weights[i] - weights[weights.len() - 1 - i]).abs() < 1e-7);
+                // the weights grow toward the center
+                assert!(weights[i] < weights[i + 1]);
+            }
+        }
+    }
+
+    #[test]
+    fn average_is_uniform() {
+        let kernel = Kernel::average(2);
+        assert_eq!((kernel.width, kernel.height), (5, 1));
+        assert!(kernel.weights.iter().all(|&weight| weight == 0.2));
+    }
+
+    #[test]
+    fn load_reads_rows() {
+        let kernel = load_text("rows", "1, 2, 3\n\n 4 5 6 \n7,8,9\n").unwrap();
+        assert_eq!(kernel, Kernel::new(3, 3, (1 .. 10).map(|i| i as f32).collect()).unwrap());
+
+        let kernel = load_text("row", "-0.5 1e-1 0.25").unwrap();
+        assert_eq!(kernel, Kernel::new(3, 1, vec![-0.5, 0.1, 0.25]).unwrap());
+    }
+
+    #[test]
+    fn load_rejects_invalid_kernels() {
+        assert!(load_text("ragged", "1 2 3\n4 5\n6 7 8\n").is_err());
+        assert!(load_text("even", "1 2\n3 4\n5 6\n").is_err());
+        assert!(load_text("weight", "1 x 3\n").is_err());
+        assert!(load_text("empty", "\n\n").is_err());
+        assert!(Kernel::load("/nonexistent/kernel.txt").is_err());
+    }
+}
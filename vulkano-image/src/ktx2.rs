@@ -0,0 +1,324 @@
+//! KTX 2.0 files, the texture container of Khronos.
+
+use std::io;
+use std::io::Write;
+
+use binary::{push_u16, push_u32, push_u64, read_u32, read_u64};
+use compress::{BlockFormat, Compressed};
+use formats::PixelFormat;
+use texture::{Texture, TextureFormat};
+
+const IDENTIFIER: [u8; 12] = [0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n'];
+
+// identifier, header and index
+const HEADER_SIZE: usize = 12 + 9 * 4 + 4 * 4 + 2 * 8;
+const LEVEL_INDEX_SIZE: usize = 3 * 8;
+
+// values of the basic data format descriptor
+const KHR_DF_VERSION: u16 = 2;
+const KHR_DF_MODEL_BC1A: u8 = 128;
+const KHR_DF_MODEL_BC3: u8 = 130;
+const KHR_DF_PRIMARIES_BT709: u8 = 1;
+const KHR_DF_TRANSFER_SRGB: u8 = 2;
+const KHR_DF_CHANNEL_BC1A_ALPHAPRESENT: u8 = 1;
+const KHR_DF_CHANNEL_BC3_COLOR: u8 = 0;
+const KHR_DF_CHANNEL_BC3_ALPHA: u8 = 15;
+// qualifier of a channel that isn't affected by the transfer function
+const KHR_DF_SAMPLE_DATATYPE_LINEAR: u8 = 0x10;
+
+/// The `VkFormat` value of a block format.
+fn vk_format(format: BlockFormat) -> u32 {
+    match format {
+        // VK_FORMAT_BC1_RGBA_SRGB_BLOCK
+        BlockFormat::Bc1 => 134,
+        // VK_FORMAT_BC3_SRGB_BLOCK
+        BlockFormat::Bc3 => 138,
+    }
+}
+
+/// The texel format of a `VkFormat` value, among the ones that can be
+/// loaded.
+fn texture_format(vk_format: u32) -> Option<TextureFormat> {
+    Some(match vk_format {
+        9 => TextureFormat::Pixels(PixelFormat::R8Unorm),
+        37 => TextureFormat::Pixels(PixelFormat::R8G8B8A8Unorm),
+        43 => TextureFormat::Pixels(PixelFormat::R8G8B8A8Srgb),
+        44 => TextureFormat::Pixels(PixelFormat::B8G8R8A8Unorm),
+        97 => TextureFormat::Pixels(PixelFormat::R16G16B16A16Sfloat),
+        109 => TextureFormat::Pixels(PixelFormat::R32G32B32A32Sfloat),
+        131 => TextureFormat::Blocks {
+            format: BlockFormat::Bc1,
+            srgb: false,
+            alpha: false,
+        },
+        132 => TextureFormat::Blocks {
+            format: BlockFormat::Bc1,
+            srgb: true,
+            alpha: false,
+        },
+        133 => TextureFormat::Blocks {
+            format: BlockFormat::Bc1,
+            srgb: false,
+            alpha: true,
+        },
+        134 => TextureFormat::Blocks {
+            format: BlockFormat::Bc1,
+            srgb: true,
+            alpha: true,
+        },
+        137 => TextureFormat::Blocks {
+            format: BlockFormat::Bc3,
+            srgb: false,
+            alpha: true,
+        },
+        138 => TextureFormat::Blocks {
+            format: BlockFormat::Bc3,
+            srgb: true,
+            alpha: true,
+        },
+        _ => return None,
+    })
+}
+
+/// Whether `bytes` start like a KTX2 file.
+pub fn is_ktx2(bytes: &[u8]) -> bool {
+    bytes.starts_with(&IDENTIFIER)
+}
+
+/// Reads a KTX2 file without supercompression.
+pub fn read(bytes: &[u8]) -> Result<Texture, String> {
+    if !is_ktx2(bytes) || bytes.len() < HEADER_SIZE {
+        return Err("not a KTX2 file".to_owned());
+    }
+
+    let vk_format = read_u32(bytes, 12)?;
+    let format = texture_format(vk_format).ok_or_else(|| format!("unsupported VkFormat {}", vk_format))?;
+    let (width, height, depth) = (read_u32(bytes, 20)?, read_u32(bytes, 24)?, read_u32(bytes, 28)?);
+    let (layers, faces, level_count) = (read_u32(bytes, 32)?, read_u32(bytes, 36)?, read_u32(bytes, 40)?);
+    if read_u32(bytes, 44)? != 0 {
+        return Err("supercompressed files are not supported".to_owned());
+    }
+
+    if width == 0 {
+        return Err("the texture has no width".to_owned());
+    }
+    if faces != 1 && faces != 6 {
+        return Err(format!("invalid number of faces {}", faces));
+    }
+    if faces == 6 && (width != height || depth != 0) {
+        return Err("the faces of a cube map must be square".to_owned());
+    }
+    if depth > 0 && layers > 0 {
+        return Err("3D textures can't be arrays".to_owned());
+    }
+
+    // the counts left to 0 have a meaning of their own: no height for 1D
+    // textures, no array and a chain of mipmaps left to be generated
+    let mut texture = Texture {
+        format,
+        width,
+        height: height.max(1),
+        depth: depth.max(1),
+        layers: layers.max(1),
+        faces,
+        array: layers > 0,
+        one_dimensional: height == 0,
+        levels: Vec::new(),
+    };
+
+    texture.check_counts(level_count)?;
+
+    for level in 0 .. level_count.max(1) {
+        let entry = HEADER_SIZE + level as usize * LEVEL_INDEX_SIZE;
+        let offset = read_u64(bytes, entry)? as usize;
+        let length = read_u64(bytes, entry + 8)? as usize;
+
+        let expected = texture
+            .level_bytes(level)
+            .ok_or_else(|| format!("level {} is too big", level))?;
+        if length != expected {
+            return Err(format!("level {} has {} bytes instead of {}", level, length, expected));
+        }
+        let data = offset
+            .checked_add(length)
+            .and_then(|end| bytes.get(offset .. end))
+            .ok_or_else(|| format!("level {} is past the end of the file", level))?;
+        texture.levels.push(data.to_vec());
+    }
+
+    Ok(texture)
+}
+
+/// Writes `image` as a KTX2 file with a single level and no
+/// supercompression.
+pub fn write<W: Write>(writer: &mut W, image: &Compressed) -> io::Result<()> {
+    let dfd = data_format_descriptor(image.format);
+
+    // the level starts on a multiple of the block size
+    let dfd_offset = HEADER_SIZE + LEVEL_INDEX_SIZE;
+    let block_bytes = image.format.block_bytes();
+    let level_offset = (dfd_offset + dfd.len() + block_bytes - 1) / block_bytes * block_bytes;
+
+    let mut bytes = Vec::with_capacity(level_offset);
+    bytes.extend_from_slice(&IDENTIFIER);
+    push_u32(&mut bytes, vk_format(image.format));
+    // type size, 1 for block compressed formats
+    push_u32(&mut bytes, 1);
+    push_u32(&mut bytes, image.width);
+    push_u32(&mut bytes, image.height);
+    // depth, layers, faces, levels and supercompression scheme
+    push_u32(&mut bytes, 0);
+    push_u32(&mut bytes, 0);
+    push_u32(&mut bytes, 1);
+    push_u32(&mut bytes, 1);
+    push_u32(&mut bytes, 0);
+
+    push_u32(&mut bytes, dfd_offset as u32);
+    push_u32(&mut bytes, dfd.len() as u32);
+    // no key/value data nor supercompression global data
+    push_u32(&mut bytes, 0);
+    push_u32(&mut bytes, 0);
+    push_u64(&mut bytes, 0);
+    push_u64(&mut bytes, 0);
+
+    push_u64(&mut bytes, level_offset as u64);
+    push_u64(&mut bytes, image.data.len() as u64);
+    push_u64(&mut bytes, image.data.len() as u64);
+
+    bytes.extend_from_slice(&dfd);
+    bytes.resize(level_offset, 0);
+
+    writer.write_all(&bytes)?;
+    writer.write_all(&image.data)
+}
+
+/// The basic data format descriptor of a block format, with its total size
+/// first. The colours are sRGB, the alpha of BC3 stays linear.
+fn data_format_descriptor(format: BlockFormat) -> Vec<u8> {
+    // bit offset, bit length and channel of each sample
+    let (model, samples): (u8, &[(u16, u8, u8)]) = match format {
+        BlockFormat::Bc1 => (KHR_DF_MODEL_BC1A, &[(0, 64, KHR_DF_CHANNEL_BC1A_ALPHAPRESENT)]),
+        BlockFormat::Bc3 => (
+            KHR_DF_MODEL_BC3,
+            &[
+                (0, 64, KHR_DF_CHANNEL_BC3_ALPHA | KHR_DF_SAMPLE_DATATYPE_LINEAR),
+                (64, 64, KHR_DF_CHANNEL_BC3_COLOR),
+            ],
+        ),
+    };
+
+    let block_size = 24 + 16 * samples.len();
+    let mut dfd = Vec::with_capacity(4 + block_size);
+    push_u32(&mut dfd, 4 + block_size as u32);
+    // Khronos vendor and basic descriptor type
+    push_u32(&mut dfd, 0);
+    push_u16(&mut dfd, KHR_DF_VERSION);
+    push_u16(&mut dfd, block_size as u16);
+    dfd.extend_from_slice(&[model, KHR_DF_PRIMARIES_BT709, KHR_DF_TRANSFER_SRGB, 0]);
+    // blocks of 4 x 4 texels, stored minus one
+    dfd.extend_from_slice(&[3, 3, 0, 0]);
+    dfd.extend_from_slice(&[format.block_bytes() as u8, 0, 0, 0, 0, 0, 0, 0]);
+
+    for &(offset, length, channel) in samples {
+        push_u16(&mut dfd, offset);
+        dfd.push(length - 1);
+        dfd.push(channel);
+        // sample position, then the lower and upper values
+        dfd.extend_from_slice(&[0; 4]);
+        push_u32(&mut dfd, 0);
+        push_u32(&mut dfd, 0xFFFF_FFFF);
+    }
+
+    dfd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use binary::write_u32;
+
+    fn written(image: &Compressed) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(&mut bytes, image).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn written_blocks_read_back() {
+        for &format in &[BlockFormat::Bc1, BlockFormat::Bc3] {
+            let image = Compressed::pattern(format, 5, 3);
+            let texture = read(&written(&image)).unwrap();
+
+            assert_eq!(
+                texture.format,
+                TextureFormat::Blocks {
+                    format,
+                    srgb: true,
+                    alpha: true,
+                }
+            );
+            assert_eq!((texture.width, texture.height, texture.depth), (5, 3, 1));
+            assert_eq!((texture.layers, texture.faces, texture.array), (1, 1, false));
+            assert_eq!(texture.levels, vec![image.data.clone()]);
+        }
+    }
+
+    #[test]
+    fn levels_past_the_full_chain_are_rejected() {
+        let mut bytes = written(&Compressed::pattern(BlockFormat::Bc1, 5, 3));
+        write_u32(&mut bytes, 40, 4);
+        assert!(read(&bytes).err().unwrap().contains("full mipmap chain"));
+        write_u32(&mut bytes, 40, 0xFFFF_FFFF);
+        assert!(read(&bytes).err().unwrap().contains("full mipmap chain"));
+    }
+
+    #[test]
+    fn overflowing_layers_are_rejected() {
+        let mut bytes = written(&Compressed::pattern(BlockFormat::Bc1, 4, 4));
+        write_u32(&mut bytes, 32, 0xFFFF_FFFF);
+        write_u32(&mut bytes, 36, 6);
+        assert!(read(&bytes).err().unwrap().contains("too many"));
+    }
+
+    #[test]
+    fn overflowing_sizes_are_rejected() {
+        let mut bytes = written(&Compressed::pattern(BlockFormat::Bc3, 4, 4));
+        write_u32(&mut bytes, 20, 0xFFFF_FFFF);
+        write_u32(&mut bytes, 24, 0xFFFF_FFFF);
+        assert!(read(&bytes).err().unwrap().contains("too big"));
+
+        // VK_FORMAT_R32G32B32A32_SFLOAT
+        write_u32(&mut bytes, 12, 109);
+        assert!(read(&bytes).err().unwrap().contains("too big"));
+    }
+
+    #[test]
+    fn bc1_without_alpha_is_opaque() {
+        // two equal colours select the 3 colour mode, and every index is the
+        // last one, the transparent black
+        let image = Compressed {
+            format: BlockFormat::Bc1,
+            width: 4,
+            height: 4,
+            data: vec![0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF],
+        };
+        let mut bytes = written(&image);
+        write_u32(&mut bytes, 12, 131);
+
+        let texture = read(&bytes).unwrap();
+        assert_eq!(
+            texture.format,
+            TextureFormat::Blocks {
+                format: BlockFormat::Bc1,
+                srgb: false,
+                alpha: false,
+            }
+        );
+        assert_eq!(texture.image(0, 0).to_rgba().get_pixel(1, 2).data, [0, 0, 0, 255]);
+
+        // the same blocks with alpha are transparent
+        write_u32(&mut bytes, 12, 133);
+        assert_eq!(read(&bytes).unwrap().image(0, 0).to_rgba().get_pixel(1, 2).data, [0, 0, 0, 0]);
+    }
+}
@@ -1,114 +1,205 @@
+extern crate args;
+extern crate golden;
 extern crate image;
+
+#[macro_use]
 extern crate vulkano;
 
-use image::{ImageBuffer, Rgba};
+#[macro_use]
+extern crate vulkano_shader_derive;
+
+mod binary;
+mod compress;
+mod core;
+mod dds;
+mod filters;
+mod formats;
+mod histogram;
+mod kernel;
+mod ktx2;
+mod mipmaps;
+mod options;
+mod processor;
+mod readback;
+mod reference;
+mod resize;
+mod shaders;
+mod texture;
+mod verify;
+
+use vulkano::device::Device;
+use vulkano::device::Queue;
 
 use std::sync::Arc;
 
-use vulkano::buffer::BufferUsage;
-use vulkano::buffer::CpuAccessibleBuffer;
+use compress::Compressed;
+use histogram::Histogram;
+use mipmaps::MipChain;
+use options::Options;
+use processor::Processor;
+use texture::Texture;
 
-use vulkano::command_buffer::AutoCommandBufferBuilder;
-use vulkano::command_buffer::CommandBuffer;
+fn main() {
+    let options = Options::from_args();
 
-use vulkano::device::Device;
-use vulkano::device::DeviceExtensions;
-use vulkano::device::QueuesIter;
+    let (device, mut queues) = core::init();
 
-use vulkano::format::ClearValue;
-use vulkano::format::Format;
+    // select the first queue found
+    let queue = queues.next().unwrap();
 
-use vulkano::image::Dimensions;
-use vulkano::image::StorageImage;
+    if options.verify {
+        let filters = if options.filters.is_empty() {
+            verify::default_filters()
+        } else {
+            options.filters.clone()
+        };
+        let processor = Processor::new(device, queue);
+        let filters_passed = verify::run(&processor, &filters);
+        let resize_passed = verify::run_resize(&processor);
+        let histogram_passed = verify::run_histogram(&processor);
+        let compress_passed = verify::run_compress(&processor);
+        if !(filters_passed && resize_passed && histogram_passed && compress_passed) {
+            ::std::process::exit(1);
+        }
+        return;
+    }
 
-use vulkano::instance::Features;
-use vulkano::instance::Instance;
-use vulkano::instance::InstanceExtensions;
-use vulkano::instance::PhysicalDevice;
+    if let Some(ref path) = options.dump_texture {
+        dump_texture(&Processor::new(device, queue), path, &options);
+        return;
+    }
 
-use vulkano::sync::GpuFuture;
+    match options.input {
+        Some(ref input) => process(Processor::new(device, queue), input, &options),
+        None => clear(device, queue, &options),
+    }
+}
 
-fn main() {
-    let (device, mut queues) = init();
+/// Loads `input`, runs it through the filters and saves the result.
+fn process(processor: Processor, input: &str, options: &Options) {
+    let input_image = image::open(input)
+        .unwrap_or_else(|err| {
+            eprintln!("failed to load {}: {}", input, err);
+            ::std::process::exit(1);
+        }).to_rgba();
+
+    println!("Uploading a {}x{} image", input_image.width(), input_image.height());
+    let mut image = processor.upload(&input_image);
+    for filter in &options.filters {
+        println!("Applying {:?}", filter);
+        image = filter.apply(&processor, image);
+    }
 
-    // select the first queue found
-    let queue = queues.next().unwrap();
+    if let Some(equalization) = options.equalize {
+        println!("Equalizing with {:?}", equalization);
+        image = equalization.apply(&processor, image);
+    }
 
-    println!("Creating image");
-    let image = StorageImage::new(
-        device.clone(),
-        Dimensions::Dim2d {
-            width: 1024,
-            height: 1024,
-        },
-        Format::R8G8B8A8Unorm,
-        Some(queue.family()),
-    ).unwrap();
-
-    // Here we put the image so we can read values
-    let buffer = CpuAccessibleBuffer::from_iter(
-        device.clone(),
-        BufferUsage::all(),
-        (0..1024 * 1024 * 4).map(|_| 0u8),
-    ).expect("failed to create the buffer");
-
-    let command_buffer = AutoCommandBufferBuilder::new(device.clone(), queue.family())
-        .unwrap()
-        .clear_color_image(image.clone(), ClearValue::Float([0.0, 0.0, 1.0, 1.0]))
-        .unwrap()
-        .copy_image_to_buffer(image.clone(), buffer.clone())
-        .unwrap()
-        .build()
-        .unwrap();
-
-    let finished = command_buffer.execute(queue.clone()).unwrap();
-    finished
-        .then_signal_fence_and_flush()
-        .unwrap()
-        .wait(None)
-        .unwrap();
-
-    // save buffer to an image file
-    let buffer_content = buffer.read().unwrap();
-    let image = ImageBuffer::<Rgba<u8>, _>::from_raw(1024, 1024, &buffer_content[..]).unwrap();
-    image.save("image.png").unwrap();
-}
+    if let Some((width, height)) = options.resize {
+        image = resize::resize(&processor, image, width, height, options.resize_filter, options.fit);
+        let (width, height) = processor::size(&image);
+        println!("Resized to {}x{}", width, height);
+    }
 
-fn init() -> (Arc<Device>, QueuesIter) {
-    // Create an instance of the vulkan API
-    let instance =
-        Instance::new(None, &InstanceExtensions::none(), None).expect("failed to create instance");
+    if let Some(downsample) = options.mipmaps {
+        let chain = MipChain::generate(&processor, image.clone(), downsample);
+        println!("Generated {} mipmap levels with {:?}", chain.level_count(), downsample);
+
+        if options.dump_mips {
+            for level in 0 .. chain.level_count() {
+                chain.download(&processor, level).save(options.level_output(level)).unwrap();
+            }
+        }
+    }
 
-    // List all the physical devices that support vulkan
-    for physical_device in PhysicalDevice::enumerate(&instance) {
-        println!("Available device: {}", physical_device.name());
+    if let Some(ref path) = options.histogram {
+        let histogram = Histogram::compute(&processor, image.clone());
+        histogram.save(path).expect("failed to write the histogram");
+        println!("Wrote the histogram of {} pixels to {}", histogram.pixels(), path);
     }
 
-    // now we just get the first
-    let physical = PhysicalDevice::from_index(&instance, 0).expect("no device available");
+    let output = processor.download(image.clone());
+
+    if let Some(format) = options.compress {
+        let compressed = Compressed::encode(&processor, image, format);
+        let path = options.compressed_output();
+        compressed.save(&path).expect("failed to write the compressed image");
+
+        let comparison = golden::compare(&compressed.decode(), &output).unwrap();
+        println!("Compressed to {:?} in {}: {}", format, path, comparison);
+    }
+
+    output.save(&options.output).unwrap();
+}
+
+/// Loads the texture at `path` into an image, reads it back and saves every
+/// 2D image of it.
+fn dump_texture(processor: &Processor, path: &str, options: &Options) {
+    let texture = Texture::load(path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        ::std::process::exit(1);
+    });
+
+    println!(
+        "Loading a {:?} texture of {:?} with {} levels",
+        texture.dimensions(),
+        texture.format,
+        texture.levels.len()
+    );
+    let image = texture.upload(processor);
+    let texture = texture.read_back(processor, image);
+
+    for level in 0 .. texture.levels.len() as u32 {
+        let depth = texture.level_size(level).2;
+        for index in 0 .. texture.image_count(level) {
+            // the images are ordered by layer, then face, then slice
+            let slice = index % depth;
+            let face = index / depth % texture.faces;
+            let layer = index / depth / texture.faces;
+
+            let output = options.texture_output(
+                level,
+                if texture.array { Some(layer) } else { None },
+                if texture.faces == 6 { Some(face) } else { None },
+                if texture.depth > 1 { Some(slice) } else { None },
+            );
+            texture.image(level, index).save(&output).unwrap();
+        }
+    }
+}
+
+/// Clears an image of every format asked and saves them.
+fn clear(device: Arc<Device>, queue: Arc<Queue>, options: &Options) {
+    for &format in &options.formats {
+        println!("Clearing a {:?} image", format.format());
+        let data = match readback::clear_and_read(
+            device.clone(),
+            queue.clone(),
+            format,
+            options.width,
+            options.height,
+            options.color,
+            options.depth,
+        ) {
+            Ok(data) => data,
+            // not every device supports every format, go on with the others
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
 
-    // list all the queue families available for the device
-    for family in physical.queue_families() {
         println!(
-            "Found a queue family with {:?} queue(s)",
-            family.queues_count()
+            "Read back {} bytes, first texel {:?}",
+            data.len(),
+            format.texel(&data, 0)
         );
-    }
 
-    // select a queue that supports graphical operations
-    let queue_family = physical
-        .queue_families()
-        .find(|&q| q.supports_graphics())
-        .expect("couldn't find a graphical queue family");
-
-    let (device, queues) = {
-        Device::new(
-            physical,
-            &Features::none(),
-            &DeviceExtensions::none(),
-            [(queue_family, 0.5)].iter().cloned(),
-        ).expect("failed to create device")
-    };
-
-    (device, queues)
+        // save buffer to an image file
+        let output = options.output_for(format);
+        format
+            .to_image(options.width, options.height, &data)
+            .save(&output)
+            .unwrap();
+    }
 }
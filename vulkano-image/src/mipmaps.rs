@@ -0,0 +1,185 @@
+use image::RgbaImage;
+
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+
+use vulkano::format::Format;
+
+use vulkano::image::Dimensions;
+use vulkano::image::ImageLayout;
+use vulkano::image::ImageUsage;
+use vulkano::image::ImmutableImage;
+use vulkano::image::MipmapsCount;
+use vulkano::image::StorageImage;
+
+use vulkano::pipeline::ComputePipeline;
+
+use vulkano::sampler;
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use processor;
+use processor::Processor;
+use shaders;
+
+/// How each level of a mipmap chain is computed from the previous one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Downsample {
+    /// Blit with linear filtering.
+    Blit,
+    /// Box filter in a compute shader.
+    Compute,
+}
+
+impl FromStr for Downsample {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Downsample, ()> {
+        match s {
+            "blit" => Ok(Downsample::Blit),
+            "compute" => Ok(Downsample::Compute),
+            _ => Err(()),
+        }
+    }
+}
+
+/// An image with its full mipmap chain, down to a single pixel.
+pub struct MipChain {
+    pub image: Arc<ImmutableImage<Format>>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl MipChain {
+    /// Creates the chain of `source`, each level being half the size of the
+    /// previous one, rounded down.
+    ///
+    /// Vulkano tracks the accesses to whole images, so blitting from a level
+    /// to the next one of the same image is seen as a conflict. The levels
+    /// are downsampled in images of their own instead, then all copied to
+    /// the chain at once.
+    pub fn generate(processor: &Processor, source: Arc<StorageImage<Format>>, downsample: Downsample) -> MipChain {
+        let (width, height) = processor::size(&source);
+
+        let mut levels = vec![source];
+        for level in 1 .. level_count(width, height) {
+            let (level_width, level_height) = level_size(width, height, level);
+            let destination = processor.create_image(level_width, level_height);
+            let previous = levels.last().unwrap().clone();
+
+            match downsample {
+                Downsample::Blit => blit(processor, previous, destination.clone()),
+                Downsample::Compute => downsample_compute(processor, previous, destination.clone()),
+            }
+            levels.push(destination);
+        }
+
+        let usage = ImageUsage {
+            transfer_source: true,
+            transfer_destination: true,
+            sampled: true,
+            ..ImageUsage::none()
+        };
+        let (image, initialization) = ImmutableImage::uninitialized(
+            processor.device().clone(),
+            Dimensions::Dim2d { width, height },
+            Format::R8G8B8A8Unorm,
+            MipmapsCount::Log2,
+            usage,
+            ImageLayout::ShaderReadOnlyOptimal,
+            Some(processor.queue().family()),
+        ).expect("failed to create the image");
+
+        // the initialization can only be used by a single command buffer
+        let initialization = Arc::new(initialization);
+        let mut builder = AutoCommandBufferBuilder::new(processor.device().clone(), processor.queue().family()).unwrap();
+        for (level, level_image) in levels.into_iter().enumerate() {
+            let (level_width, level_height) = processor::size(&level_image);
+            builder = builder
+                .copy_image(
+                    level_image,
+                    [0, 0, 0],
+                    0,
+                    0,
+                    initialization.clone(),
+                    [0, 0, 0],
+                    0,
+                    level as u32,
+                    [level_width, level_height, 1],
+                    1,
+                ).unwrap();
+        }
+        processor.submit(builder.build().unwrap());
+
+        MipChain { image, width, height }
+    }
+
+    pub fn level_count(&self) -> u32 {
+        level_count(self.width, self.height)
+    }
+
+    /// Reads the pixels of `level` back.
+    pub fn download(&self, processor: &Processor, level: u32) -> RgbaImage {
+        let (width, height) = level_size(self.width, self.height, level);
+        processor.download_level(self.image.clone(), level, width, height)
+    }
+}
+
+/// Number of levels of a full chain, the last one being 1 x 1.
+pub fn level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).leading_zeros()
+}
+
+/// Size of `level`, no side gets smaller than a pixel.
+pub fn level_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+fn blit(processor: &Processor, source: Arc<StorageImage<Format>>, destination: Arc<StorageImage<Format>>) {
+    let (source_width, source_height) = processor::size(&source);
+    let (width, height) = processor::size(&destination);
+
+    let command_buffer = AutoCommandBufferBuilder::new(processor.device().clone(), processor.queue().family())
+        .unwrap()
+        .blit_image(
+            source,
+            [0, 0, 0],
+            [source_width as i32, source_height as i32, 1],
+            0,
+            0,
+            destination,
+            [0, 0, 0],
+            [width as i32, height as i32, 1],
+            0,
+            0,
+            1,
+            sampler::Filter::Linear,
+        ).unwrap()
+        .build()
+        .unwrap();
+    processor.submit(command_buffer);
+}
+
+fn downsample_compute(processor: &Processor, source: Arc<StorageImage<Format>>, destination: Arc<StorageImage<Format>>) {
+    let device = processor.device().clone();
+    let shader = shaders::downsample::Shader::load(device.clone()).expect("failed to load shader module");
+    let pipeline = Arc::new(
+        ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+            .expect("failed to create compute pipeline"),
+    );
+
+    let size = processor::size(&destination);
+    let set = Arc::new(
+        PersistentDescriptorSet::start(pipeline.clone(), 0)
+            .add_image(source)
+            .unwrap()
+            .add_image(destination)
+            .unwrap()
+            .build()
+            .unwrap(),
+    );
+
+    processor.dispatch(pipeline, set, size, ());
+}
@@ -0,0 +1,315 @@
+use args::{parse_floats, parse_size, parse_value};
+
+use std::env;
+use std::path::Path;
+
+use compress::BlockFormat;
+use filters::Filter;
+use formats::{PixelFormat, ALL_FORMATS};
+use histogram::Equalization;
+use mipmaps::Downsample;
+use resize::{Fit, ResizeFilter};
+
+const USAGE: &str = "usage: imagevk [OPTIONS] [OUTPUT]
+
+Clears an image on the GPU, reads it back and saves it to OUTPUT (default
+image.png). With --input the image is loaded from a file and run through
+compute filters instead.
+
+options:
+    --format F     format of the image: r8, rgba8, rgba8-srgb, bgra8, rgba16f,
+                   rgba32f, d16 or d32f, or all to go through every one of
+                   them and write OUTPUT-F.png files (default rgba8)
+    --size WxH     size of the image (default 1024x1024)
+    --color R,G,B,A
+                   colour the image is cleared with (default 0,0,1,1), linear
+                   for the sRGB and float formats, which are encoded to sRGB
+                   in the PNG file
+    --depth D      depth the depth images are cleared with (default 0.5)
+
+processing:
+    --input PATH   PNG or JPEG image to load instead of clearing one
+    --filter F,..  comma separated filters applied in order to the input
+                   (default none, the image is only copied to the device and
+                   back), among:
+                     invert, grayscale
+                     blur:R         Gaussian blur of radius R
+                     box:R          box blur of radius R
+                     unsharp:R:A    unsharp mask with a blur of radius R,
+                                    adding A times the details (default 1)
+                     sharpen, emboss
+                     sobel, scharr  magnitude of the edges
+                     kernel:PATH    convolution with the kernel in PATH, one
+                                    row per line, up to 15x15
+    --verify       run the filters (or a set of them all when there is no
+                   --filter) on small random images on both the GPU and the
+                   CPU and compare the results, along with the resizes
+                   and the ones of the image crate, and the histograms
+    --equalize E   equalize the luma of the filtered image: global, or clahe
+                   for a contrast limited adaptive equalization, optionally
+                   followed by :TILES (tiles per side, default 8) and :CLIP
+                   (largest bin relative to the mean, default 2)
+    --histogram PATH
+                   write the counts of the red, green, blue and luma values
+                   of the output image to PATH, as .csv or .json
+    --resize WxH   resize the filtered image
+    --resize-filter F
+                   how the resized image is interpolated: nearest or linear
+                   (blits), bicubic or lanczos (compute) (default linear)
+    --fit F        how the image fits in --resize: stretch to exactly WxH,
+                   contain to keep its aspect ratio inside WxH, or cover to
+                   keep its aspect ratio and crop what goes past WxH
+                   (default stretch)
+    --compress F   encode the output image to blocks of a compressed format,
+                   bc1 or bc3 (with interpolated alpha), in a compute shader
+                   and print the PSNR of the decoded blocks
+    --compressed PATH
+                   file the blocks are written to, as .dds or .ktx2 (default
+                   OUTPUT with a .dds extension)
+    --mipmaps M    generate the mipmap chain of the filtered image, each level
+                   downsampled from the previous one with a linear blit or a
+                   compute shader: blit or compute
+    --dump-mips    write every level of the chain next to OUTPUT as
+                   OUTPUT-mipN.png
+
+textures:
+    --dump-texture PATH
+                   load the KTX2 or DDS texture in PATH, with its mipmaps,
+                   array layers, cube faces or 3D slices, into an immutable
+                   image, read it back and write every 2D image of it next to
+                   OUTPUT as OUTPUT-mipN[-layerN][-faceN][-sliceN].png";
+
+/// Command line options of the image example.
+pub struct Options {
+    pub formats: Vec<PixelFormat>,
+    pub output: String,
+    pub width: u32,
+    pub height: u32,
+    pub color: [f32; 4],
+    pub depth: f32,
+    pub input: Option<String>,
+    pub filters: Vec<Filter>,
+    pub verify: bool,
+    pub equalize: Option<Equalization>,
+    pub histogram: Option<String>,
+    pub compress: Option<BlockFormat>,
+    pub compressed: Option<String>,
+    pub resize: Option<(u32, u32)>,
+    pub resize_filter: ResizeFilter,
+    pub fit: Fit,
+    pub mipmaps: Option<Downsample>,
+    pub dump_mips: bool,
+    pub dump_texture: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            formats: vec![PixelFormat::R8G8B8A8Unorm],
+            output: "image.png".to_owned(),
+            width: 1024,
+            height: 1024,
+            color: [0.0, 0.0, 1.0, 1.0],
+            depth: 0.5,
+            input: None,
+            filters: Vec::new(),
+            verify: false,
+            equalize: None,
+            histogram: None,
+            compress: None,
+            compressed: None,
+            resize: None,
+            resize_filter: ResizeFilter::Linear,
+            fit: Fit::Stretch,
+            mipmaps: None,
+            dump_mips: false,
+            dump_texture: None,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the options from the process arguments, exiting with the usage
+    /// message when they are not valid.
+    pub fn from_args() -> Options {
+        match Options::parse(env::args().skip(1)) {
+            Ok(options) => options,
+            Err(err) => {
+                eprintln!("{}\n\n{}", err, USAGE);
+                ::std::process::exit(1);
+            }
+        }
+    }
+
+    pub fn parse<I>(args: I) -> Result<Options, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        let mut output = None;
+        let mut format_given = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" => {
+                    format_given = true;
+                    let value = args.next();
+                    if value.as_ref().map(|v| &v[..]) == Some("all") {
+                        options.formats = ALL_FORMATS.to_vec();
+                    } else {
+                        options.formats = vec![parse_value(&arg, value)?];
+                    }
+                }
+                "--size" => {
+                    let size = args.next();
+                    let (width, height) = parse_size(&arg, size)?;
+                    options.width = width;
+                    options.height = height;
+                }
+                "--color" => {
+                    let value = parse_floats(&arg, args.next(), 4)?;
+                    options.color = [value[0], value[1], value[2], value[3]];
+                }
+                "--depth" => {
+                    options.depth = parse_value(&arg, args.next())?;
+                    if options.depth < 0.0 || options.depth > 1.0 {
+                        return Err("--depth must be between 0 and 1".to_owned());
+                    }
+                }
+                "--input" => options.input = Some(parse_value(&arg, args.next())?),
+                "--filter" => {
+                    let value = args.next().ok_or_else(|| format!("missing value for {}", arg))?;
+                    options.filters = value
+                        .split(',')
+                        .map(|filter| filter.trim().parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(|err| format!("{} for {}", err, arg))?;
+                }
+                "--verify" => options.verify = true,
+                "--equalize" => {
+                    let value = args.next().ok_or_else(|| format!("missing value for {}", arg))?;
+                    options.equalize = Some(value.parse().map_err(|err| format!("{} for {}", err, arg))?);
+                }
+                "--histogram" => options.histogram = Some(parse_value(&arg, args.next())?),
+                "--compress" => options.compress = Some(parse_value(&arg, args.next())?),
+                "--compressed" => options.compressed = Some(parse_value(&arg, args.next())?),
+                "--resize" => options.resize = Some(parse_size(&arg, args.next())?),
+                "--resize-filter" => options.resize_filter = parse_value(&arg, args.next())?,
+                "--fit" => options.fit = parse_value(&arg, args.next())?,
+                "--mipmaps" => options.mipmaps = Some(parse_value(&arg, args.next())?),
+                "--dump-mips" => options.dump_mips = true,
+                "--dump-texture" => options.dump_texture = Some(parse_value(&arg, args.next())?),
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    ::std::process::exit(0);
+                }
+                _ if !arg.starts_with('-') && output.is_none() => output = Some(arg.clone()),
+                _ => return Err(format!("unknown argument '{}'", arg)),
+            }
+        }
+
+        if let Some(output) = output {
+            options.output = output;
+        }
+
+        // loaded images are always processed as 8 bit RGBA
+        if options.input.is_some() && format_given {
+            return Err("--format only applies to cleared images".to_owned());
+        }
+        if options.input.is_none() && !options.verify && !options.filters.is_empty() {
+            return Err("--filter needs an --input image".to_owned());
+        }
+        if options.equalize.is_some() && options.input.is_none() {
+            return Err("--equalize needs an --input image".to_owned());
+        }
+        if let Some(ref histogram) = options.histogram {
+            if options.input.is_none() {
+                return Err("--histogram needs an --input image".to_owned());
+            }
+            let extension = Path::new(histogram).extension().and_then(|e| e.to_str());
+            if extension != Some("csv") && extension != Some("json") {
+                return Err(format!("can't tell the format of '{}', use .csv or .json", histogram));
+            }
+        }
+        if options.compress.is_some() && options.input.is_none() {
+            return Err("--compress needs an --input image".to_owned());
+        }
+        if let Some(ref compressed) = options.compressed {
+            if options.compress.is_none() {
+                return Err("--compressed needs --compress".to_owned());
+            }
+            let extension = Path::new(compressed).extension().and_then(|e| e.to_str());
+            if extension != Some("dds") && extension != Some("ktx2") {
+                return Err(format!("can't tell the format of '{}', use .dds or .ktx2", compressed));
+            }
+        }
+        if options.resize.is_some() && options.input.is_none() {
+            return Err("--resize needs an --input image".to_owned());
+        }
+        if options.mipmaps.is_some() && options.input.is_none() {
+            return Err("--mipmaps needs an --input image".to_owned());
+        }
+        if options.dump_mips && options.mipmaps.is_none() {
+            return Err("--dump-mips needs --mipmaps".to_owned());
+        }
+        if options.verify && options.input.is_some() {
+            return Err("--verify makes up its own images, it can't be used with --input".to_owned());
+        }
+        if options.dump_texture.is_some() && (options.verify || options.input.is_some()) {
+            return Err("--dump-texture can't be used with --input or --verify".to_owned());
+        }
+
+        Ok(options)
+    }
+
+    /// Path the image of `format` is saved to, when several formats are
+    /// written `image.png` becomes `image-rgba8.png` and so on.
+    pub fn output_for(&self, format: PixelFormat) -> String {
+        if self.formats.len() == 1 {
+            return self.output.clone();
+        }
+
+        self.output_with_suffix(format.name())
+    }
+
+    /// Path the compressed image is written to.
+    pub fn compressed_output(&self) -> String {
+        match self.compressed {
+            Some(ref compressed) => compressed.clone(),
+            None => Path::new(&self.output)
+                .with_extension("dds")
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+
+    /// Path the mipmap `level` is dumped to, like `image-mip3.png`.
+    pub fn level_output(&self, level: u32) -> String {
+        self.output_with_suffix(&format!("mip{}", level))
+    }
+
+    /// Path an image of a texture is dumped to, like `image-mip0-face2.png`,
+    /// the parts that don't apply to the texture are left out.
+    pub fn texture_output(&self, level: u32, layer: Option<u32>, face: Option<u32>, slice: Option<u32>) -> String {
+        let mut suffix = format!("mip{}", level);
+        for &(name, index) in &[("layer", layer), ("face", face), ("slice", slice)] {
+            if let Some(index) = index {
+                suffix.push_str(&format!("-{}{}", name, index));
+            }
+        }
+        self.output_with_suffix(&suffix)
+    }
+
+    fn output_with_suffix(&self, suffix: &str) -> String {
+        let path = Path::new(&self.output);
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "image".to_owned());
+
+        path.with_file_name(format!("{}-{}.png", stem, suffix))
+            .to_string_lossy()
+            .into_owned()
+    }
+}
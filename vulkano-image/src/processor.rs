@@ -0,0 +1,155 @@
+use image::RgbaImage;
+
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::CpuAccessibleBuffer;
+
+use vulkano::command_buffer::AutoCommandBuffer;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::command_buffer::CommandBuffer;
+
+use vulkano::descriptor::descriptor_set::DescriptorSetsCollection;
+
+use vulkano::device::Device;
+use vulkano::device::Queue;
+
+use vulkano::format::Format;
+
+use vulkano::image::Dimensions;
+use vulkano::image::ImageAccess;
+use vulkano::image::StorageImage;
+
+use vulkano::pipeline::ComputePipelineAbstract;
+
+use vulkano::sync::GpuFuture;
+
+use std::sync::Arc;
+
+/// Moves RGBA images between the host and the device and runs compute
+/// filters on them.
+///
+/// The images live on the device as `R8G8B8A8Unorm` storage images, so that
+/// the filters can both read and write them and be chained one after the
+/// other.
+pub struct Processor {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+}
+
+impl Processor {
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>) -> Processor {
+        Processor { device, queue }
+    }
+
+    pub fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &Arc<Queue> {
+        &self.queue
+    }
+
+    /// Creates an uninitialized image of `width` x `height` pixels.
+    pub fn create_image(&self, width: u32, height: u32) -> Arc<StorageImage<Format>> {
+        StorageImage::new(
+            self.device.clone(),
+            Dimensions::Dim2d { width, height },
+            Format::R8G8B8A8Unorm,
+            Some(self.queue.family()),
+        ).unwrap()
+    }
+
+    /// Copies `image` to the device through a staging buffer.
+    pub fn upload(&self, image: &RgbaImage) -> Arc<StorageImage<Format>> {
+        let (width, height) = image.dimensions();
+        let staging = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::transfer_source(),
+            image.iter().cloned(),
+        ).expect("failed to create the buffer");
+
+        let device_image = self.create_image(width, height);
+        let command_buffer = AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
+            .unwrap()
+            .copy_buffer_to_image(staging, device_image.clone())
+            .unwrap()
+            .build()
+            .unwrap();
+        self.submit(command_buffer);
+
+        device_image
+    }
+
+    /// Copies `image` back from the device.
+    pub fn download(&self, image: Arc<StorageImage<Format>>) -> RgbaImage {
+        let (width, height) = size(&image);
+        self.download_level(image, 0, width, height)
+    }
+
+    /// Copies the mipmap `level` of `image`, of `width` x `height` pixels,
+    /// back from the device.
+    pub fn download_level<I>(&self, image: I, level: u32, width: u32, height: u32) -> RgbaImage
+    where
+        I: ImageAccess + Send + Sync + 'static,
+    {
+        // Here we put the image so we can read values
+        let buffer = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::all(),
+            (0 .. width as usize * height as usize * 4).map(|_| 0u8),
+        ).expect("failed to create the buffer");
+
+        let command_buffer = AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
+            .unwrap()
+            .copy_image_to_buffer_dimensions(image, buffer.clone(), [0, 0, 0], [width, height, 1], 0, 1, level)
+            .unwrap()
+            .build()
+            .unwrap();
+        self.submit(command_buffer);
+
+        let buffer_content = buffer.read().unwrap();
+        RgbaImage::from_raw(width, height, buffer_content.to_vec()).unwrap()
+    }
+
+    /// Runs `pipeline` once per pixel of an image of `size`, in groups of
+    /// 16 x 16 pixels.
+    pub fn dispatch<Cp, S, Pc>(&self, pipeline: Cp, set: S, size: (u32, u32), push_constants: Pc)
+    where
+        Cp: ComputePipelineAbstract + Send + Sync + 'static + Clone,
+        S: DescriptorSetsCollection,
+    {
+        let (width, height) = size;
+        self.dispatch_groups(pipeline, set, [(width + 15) / 16, (height + 15) / 16, 1], push_constants);
+    }
+
+    /// Runs `pipeline` with the given number of work groups, for the shaders
+    /// that don't work on a pixel per invocation.
+    pub fn dispatch_groups<Cp, S, Pc>(&self, pipeline: Cp, set: S, groups: [u32; 3], push_constants: Pc)
+    where
+        Cp: ComputePipelineAbstract + Send + Sync + 'static + Clone,
+        S: DescriptorSetsCollection,
+    {
+        let command_buffer = AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
+            .unwrap()
+            .dispatch(groups, pipeline, set, push_constants)
+            .unwrap()
+            .build()
+            .unwrap();
+        self.submit(command_buffer);
+    }
+
+    /// Executes `command_buffer` and waits for it to be done.
+    pub fn submit(&self, command_buffer: AutoCommandBuffer) {
+        let finished = command_buffer.execute(self.queue.clone()).unwrap();
+        finished
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+    }
+}
+
+/// Width and height of a 2D `image`.
+pub fn size(image: &StorageImage<Format>) -> (u32, u32) {
+    let dimensions = image.dimensions();
+    (dimensions.width(), dimensions.height())
+}
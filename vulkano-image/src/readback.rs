@@ -0,0 +1,154 @@
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::CpuAccessibleBuffer;
+
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::command_buffer::CommandBuffer;
+
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+
+use vulkano::device::Device;
+use vulkano::device::Queue;
+
+use vulkano::framebuffer::Framebuffer;
+
+use vulkano::image::AttachmentImage;
+use vulkano::image::ImageUsage;
+
+use vulkano::pipeline::ComputePipeline;
+
+use vulkano::sampler::Filter;
+use vulkano::sampler::MipmapMode;
+use vulkano::sampler::Sampler;
+use vulkano::sampler::SamplerAddressMode;
+
+use vulkano::sync::GpuFuture;
+
+use std::sync::Arc;
+
+use formats::PixelFormat;
+use shaders;
+
+/// Creates a `width` x `height` image of `format`, clears it with `color` (or
+/// `depth` for the depth formats) and returns its texels, `texel_size()`
+/// bytes each, in the byte order of the host.
+pub fn clear_and_read(
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    format: PixelFormat,
+    width: u32,
+    height: u32,
+    color: [f32; 4],
+    depth: f32,
+) -> Result<Vec<u8>, String> {
+    let usage = ImageUsage {
+        transfer_source: true,
+        transfer_destination: true,
+        sampled: format.is_depth(),
+        ..ImageUsage::none()
+    };
+    let image = AttachmentImage::with_usage(device.clone(), [width, height], format.format(), usage)
+        .map_err(|err| format!("can't create a {:?} image: {}", format.format(), err))?;
+
+    // Here we put the image so we can read values
+    let buffer = CpuAccessibleBuffer::from_iter(
+        device.clone(),
+        BufferUsage::all(),
+        (0 .. width as usize * height as usize * format.texel_size()).map(|_| 0u8),
+    ).expect("failed to create the buffer");
+
+    let clear_value = format.clear_value(color, depth);
+    let command_buffer = if format.is_depth() {
+        // depth images are cleared by a render pass and sampled into the
+        // buffer, copying them isn't supported
+        let render_pass = Arc::new(
+            single_pass_renderpass!(device.clone(),
+                attachments: {
+                    depth: {
+                        load: Clear,
+                        store: Store,
+                        format: format.format(),
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [],
+                    depth_stencil: {depth}
+                }
+            ).unwrap(),
+        );
+
+        let framebuffer = Arc::new(
+            Framebuffer::start(render_pass.clone())
+                .add(image.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        let shader = shaders::depth::Shader::load(device.clone()).expect("failed to load shader module");
+        let pipeline = Arc::new(
+            ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+                .expect("failed to create compute pipeline"),
+        );
+
+        // the texels are fetched, the filters don't matter
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Nearest,
+            Filter::Nearest,
+            MipmapMode::Nearest,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        ).unwrap();
+
+        let set = Arc::new(
+            PersistentDescriptorSet::start(pipeline.clone(), 0)
+                .add_sampled_image(image.clone(), sampler)
+                .unwrap()
+                .add_buffer(buffer.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        let push_constants = shaders::depth::ty::PushConstantData {
+            image_size: [width, height],
+        };
+
+        AutoCommandBufferBuilder::new(device.clone(), queue.family())
+            .unwrap()
+            .begin_render_pass(framebuffer.clone(), false, vec![clear_value])
+            .unwrap()
+            .end_render_pass()
+            .unwrap()
+            .dispatch([(width + 15) / 16, (height + 15) / 16, 1], pipeline.clone(), set, push_constants)
+            .unwrap()
+            .build()
+            .unwrap()
+    } else {
+        AutoCommandBufferBuilder::new(device.clone(), queue.family())
+            .unwrap()
+            .clear_color_image(image.clone(), clear_value)
+            .unwrap()
+            .copy_image_to_buffer(image.clone(), buffer.clone())
+            .unwrap()
+            .build()
+            .unwrap()
+    };
+
+    let finished = command_buffer.execute(queue.clone()).unwrap();
+    finished
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+
+    let buffer_content = buffer.read().unwrap();
+    Ok(buffer_content.to_vec())
+}
+
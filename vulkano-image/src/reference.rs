@@ -0,0 +1,253 @@
+//! CPU implementations of the filters, computed the same way as the compute
+//! shaders to check their results.
+
+use image::{Rgba, RgbaImage};
+
+use histogram;
+use histogram::Histogram;
+use kernel::Kernel;
+
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+pub fn invert(image: &RgbaImage) -> RgbaImage {
+    map(image, |color| [1.0 - color[0], 1.0 - color[1], 1.0 - color[2], color[3]])
+}
+
+pub fn grayscale(image: &RgbaImage) -> RgbaImage {
+    map(image, |color| {
+        let luma = dot(&color, &LUMA);
+        [luma, luma, luma, color[3]]
+    })
+}
+
+/// Applies `kernel` to the colours of `image` and adds `bias`, the alpha
+/// channel is left as is.
+pub fn convolve(image: &RgbaImage, kernel: &Kernel, bias: f32) -> RgbaImage {
+    let (radius_x, radius_y) = (kernel.width as i64 / 2, kernel.height as i64 / 2);
+
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let mut sum = [0.0; 3];
+        for ky in 0 .. kernel.height {
+            for kx in 0 .. kernel.width {
+                let weight = kernel.weights[(ky * kernel.width + kx) as usize];
+                let color = load(image, x as i64 + kx as i64 - radius_x, y as i64 + ky as i64 - radius_y);
+                for channel in 0 .. 3 {
+                    sum[channel] += weight * color[channel];
+                }
+            }
+        }
+
+        let alpha = load(image, x as i64, y as i64)[3];
+        store([sum[0] + bias, sum[1] + bias, sum[2] + bias, alpha])
+    })
+}
+
+/// Applies a kernel made of `row` and its transpose one after the other,
+/// rounding the colours in between like the intermediate image does.
+pub fn convolve_separable(image: &RgbaImage, row: &Kernel) -> RgbaImage {
+    convolve(&convolve(image, row, 0.0), &row.transpose(), 0.0)
+}
+
+/// Magnitude of the gradient of the luma, with the derivatives smoothed by
+/// `(side, center, side)`.
+pub fn gradient(image: &RgbaImage, side: f32, center: f32) -> RgbaImage {
+    let luma = |x: i64, y: i64| dot(&load(image, x, y), &LUMA);
+    let weights = [side, center, side];
+
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let (mut gx, mut gy) = (0.0, 0.0);
+        for i in 0 .. 3 {
+            let offset = i as i64 - 1;
+            gx += weights[i] * (luma(x + 1, y + offset) - luma(x - 1, y + offset));
+            gy += weights[i] * (luma(x + offset, y + 1) - luma(x + offset, y - 1));
+        }
+
+        let magnitude = (gx * gx + gy * gy).sqrt() / (2.0 * side + center);
+        store([magnitude, magnitude, magnitude, load(image, x, y)[3]])
+    })
+}
+
+/// Sharpens `image` by adding `amount` times the difference with `blurred`.
+pub fn unsharp(image: &RgbaImage, blurred: &RgbaImage, amount: f32) -> RgbaImage {
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let color = load(image, x as i64, y as i64);
+        let blur = load(blurred, x as i64, y as i64);
+        let sharpen = |channel: usize| color[channel] + amount * (color[channel] - blur[channel]);
+        store([sharpen(0), sharpen(1), sharpen(2), color[3]])
+    })
+}
+
+/// Counts the values of each of the `tiles` tiles of `image`, split at the
+/// same pixels as the shader does.
+pub fn histograms(image: &RgbaImage, tiles: (u32, u32)) -> Vec<Histogram> {
+    let (width, height) = (image.width() as u64, image.height() as u64);
+    let (tiles_x, tiles_y) = (tiles.0 as u64, tiles.1 as u64);
+    let mut histograms = vec![Histogram::new(); (tiles_x * tiles_y) as usize];
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        // the last tile whose first pixel is at or before this one
+        let tile_x = (0 .. tiles_x).filter(|t| t * width / tiles_x <= x as u64).last().unwrap();
+        let tile_y = (0 .. tiles_y).filter(|t| t * height / tiles_y <= y as u64).last().unwrap();
+
+        let bin = &mut histograms[(tile_y * tiles_x + tile_x) as usize].bins;
+        bin[pixel[0] as usize][0] += 1;
+        bin[pixel[1] as usize][1] += 1;
+        bin[pixel[2] as usize][2] += 1;
+        bin[histogram::luma_bin(pixel[0], pixel[1], pixel[2])][3] += 1;
+    }
+
+    histograms
+}
+
+/// Largest difference between the channels of two images of the same size.
+pub fn max_difference(a: &RgbaImage, b: &RgbaImage) -> u8 {
+    assert_eq!(a.dimensions(), b.dimensions());
+    a.iter()
+        .zip(b.iter())
+        .map(|(&a, &b)| if a > b { a - b } else { b - a })
+        .max()
+        .unwrap_or(0)
+}
+
+fn map<F>(image: &RgbaImage, f: F) -> RgbaImage
+where
+    F: Fn([f32; 4]) -> [f32; 4],
+{
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| store(f(load(image, x as i64, y as i64))))
+}
+
+/// Reads a pixel as normalized floats, the coordinates are clamped to the
+/// image like the shaders do.
+fn load(image: &RgbaImage, x: i64, y: i64) -> [f32; 4] {
+    let x = x.max(0).min(image.width() as i64 - 1) as u32;
+    let y = y.max(0).min(image.height() as i64 - 1) as u32;
+    let pixel = image.get_pixel(x, y);
+    [
+        pixel[0] as f32 / 255.0,
+        pixel[1] as f32 / 255.0,
+        pixel[2] as f32 / 255.0,
+        pixel[3] as f32 / 255.0,
+    ]
+}
+
+/// Rounds the colour to 8 bits like writing to an `rgba8` image.
+fn store(color: [f32; 4]) -> Rgba<u8> {
+    let unorm = |value: f32| (value.max(0.0).min(1.0) * 255.0).round() as u8;
+    Rgba([unorm(color[0]), unorm(color[1]), unorm(color[2]), unorm(color[3])])
+}
+
+fn dot(color: &[f32; 4], weights: &[f32; 3]) -> f32 {
+    color[0] * weights[0] + color[1] * weights[1] + color[2] * weights[2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([(x * 40) as u8, (y * 50) as u8, (x * 13 + y * 29) as u8, 200 - (x * 7) as u8])
+        })
+    }
+
+    #[test]
+    fn invert_keeps_alpha() {
+        let source = image(5, 4);
+        let inverted = invert(&source);
+        for (a, b) in source.pixels().zip(inverted.pixels()) {
+            assert_eq!(b.data, [255 - a[0], 255 - a[1], 255 - a[2], a[3]]);
+        }
+        assert_eq!(max_difference(&invert(&inverted), &source), 0);
+    }
+
+    #[test]
+    fn grayscale_of_primaries() {
+        let source = RgbaImage::from_fn(3, 1, |x, _| {
+            let mut pixel = Rgba([0, 0, 0, 255]);
+            pixel[x as usize] = 255;
+            pixel
+        });
+        let gray = grayscale(&source);
+        for (x, weight) in LUMA.iter().enumerate() {
+            let value = (weight * 255.0).round() as u8;
+            assert_eq!(gray.get_pixel(x as u32, 0).data, [value, value, value, 255]);
+        }
+    }
+
+    #[test]
+    fn identity_kernel_keeps_the_image() {
+        let source = image(6, 5);
+        let mut weights = vec![0.0; 9];
+        weights[4] = 1.0;
+        let identity = Kernel::new(3, 3, weights).unwrap();
+        assert_eq!(max_difference(&convolve(&source, &identity, 0.0), &source), 0);
+    }
+
+    #[test]
+    fn convolve_clamps_at_the_edges() {
+        // a kernel picking the left neighbour shifts the image right, the
+        // first column repeating itself
+        let source = image(4, 3);
+        let left = Kernel::new(3, 1, vec![1.0, 0.0, 0.0]).unwrap();
+        let shifted = convolve(&source, &left, 0.0);
+        for (x, y, pixel) in shifted.enumerate_pixels() {
+            let expected = source.get_pixel(x.max(1) - 1, y);
+            assert_eq!(&pixel.data[.. 3], &expected.data[.. 3]);
+            assert_eq!(pixel[3], source.get_pixel(x, y)[3]);
+        }
+    }
+
+    #[test]
+    fn separable_blur_of_a_flat_image() {
+        let flat = RgbaImage::from_pixel(7, 5, Rgba([10, 120, 250, 90]));
+        assert_eq!(max_difference(&convolve_separable(&flat, &Kernel::gaussian(2)), &flat), 0);
+    }
+
+    #[test]
+    fn flat_images_have_no_gradient() {
+        let flat = RgbaImage::from_pixel(5, 5, Rgba([80, 80, 80, 255]));
+        let edges = gradient(&flat, 1.0, 2.0);
+        assert!(edges.pixels().all(|pixel| pixel.data == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn unsharp_without_blur_changes_nothing() {
+        let source = image(5, 5);
+        assert_eq!(max_difference(&unsharp(&source, &source, 1.5), &source), 0);
+    }
+
+    #[test]
+    fn histograms_count_every_pixel() {
+        let source = image(7, 5);
+        for &tiles in &[(1, 1), (3, 2), (7, 5)] {
+            let histograms = histograms(&source, tiles);
+            assert_eq!(histograms.len(), (tiles.0 * tiles.1) as usize);
+
+            for channel in 0 .. 4 {
+                let total: u32 = histograms
+                    .iter()
+                    .flat_map(|histogram| histogram.bins.iter())
+                    .map(|bin| bin[channel])
+                    .sum();
+                assert_eq!(total, 35);
+            }
+        }
+
+        // a tile per pixel holds the value of its pixel
+        let histograms = histograms(&source, (7, 5));
+        let pixel = source.get_pixel(3, 2);
+        assert_eq!(histograms[2 * 7 + 3].bins[pixel[0] as usize][0], 1);
+    }
+
+    #[test]
+    fn max_difference_of_channels() {
+        let a = image(3, 3);
+        let mut b = a.clone();
+        assert_eq!(max_difference(&a, &b), 0);
+
+        b.get_pixel_mut(1, 2)[3] = a.get_pixel(1, 2)[3] - 9;
+        b.get_pixel_mut(2, 0)[0] = a.get_pixel(2, 0)[0] + 4;
+        assert_eq!(max_difference(&a, &b), 9);
+    }
+}
@@ -0,0 +1,259 @@
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+
+use vulkano::format::Format;
+
+use vulkano::image::StorageImage;
+
+use vulkano::pipeline::ComputePipeline;
+
+use vulkano::sampler;
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use processor;
+use processor::Processor;
+use shaders;
+
+/// How the pixels of a resized image are interpolated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResizeFilter {
+    /// Blit picking the closest texel.
+    Nearest,
+    /// Blit with bilinear filtering.
+    Linear,
+    /// Separable Catmull-Rom cubic in a compute shader.
+    Bicubic,
+    /// Separable Lanczos with 3 lobes in a compute shader.
+    Lanczos,
+}
+
+impl FromStr for ResizeFilter {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ResizeFilter, ()> {
+        match s {
+            "nearest" => Ok(ResizeFilter::Nearest),
+            "linear" => Ok(ResizeFilter::Linear),
+            "bicubic" => Ok(ResizeFilter::Bicubic),
+            "lanczos" => Ok(ResizeFilter::Lanczos),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How an image is fitted in the requested size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fit {
+    /// Exactly the requested size, the aspect ratio may change.
+    Stretch,
+    /// As big as possible inside the requested size, keeping the aspect
+    /// ratio.
+    Contain,
+    /// Keeps the aspect ratio and fills the requested size, cropping the
+    /// sides that don't fit.
+    Cover,
+}
+
+impl FromStr for Fit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Fit, ()> {
+        match s {
+            "stretch" => Ok(Fit::Stretch),
+            "contain" => Ok(Fit::Contain),
+            "cover" => Ok(Fit::Cover),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Resizes `source` to `width` x `height` as described by `fit`.
+pub fn resize(
+    processor: &Processor,
+    source: Arc<StorageImage<Format>>,
+    width: u32,
+    height: u32,
+    filter: ResizeFilter,
+    fit: Fit,
+) -> Arc<StorageImage<Format>> {
+    let (source_width, source_height) = processor::size(&source);
+    let (scaled_width, scaled_height) = scaled_size(source_width, source_height, width, height, fit);
+    let scaled = resize_exact(processor, source, scaled_width, scaled_height, filter);
+
+    if fit != Fit::Cover || (scaled_width, scaled_height) == (width, height) {
+        return scaled;
+    }
+
+    // keep the center of the scaled image
+    let cropped = processor.create_image(width, height);
+    let offset = [
+        ((scaled_width - width) / 2) as i32,
+        ((scaled_height - height) / 2) as i32,
+        0,
+    ];
+    let command_buffer = AutoCommandBufferBuilder::new(processor.device().clone(), processor.queue().family())
+        .unwrap()
+        .copy_image(scaled, offset, 0, 0, cropped.clone(), [0, 0, 0], 0, 0, [width, height, 1], 1)
+        .unwrap()
+        .build()
+        .unwrap();
+    processor.submit(command_buffer);
+
+    cropped
+}
+
+/// Size of an image of `source_width` x `source_height` pixels once scaled
+/// to fit in `width` x `height`, before the cropping of `Fit::Cover`.
+pub fn scaled_size(source_width: u32, source_height: u32, width: u32, height: u32, fit: Fit) -> (u32, u32) {
+    let scale_x = width as f64 / source_width as f64;
+    let scale_y = height as f64 / source_height as f64;
+    let scale = match fit {
+        Fit::Stretch => return (width, height),
+        Fit::Contain => scale_x.min(scale_y),
+        Fit::Cover => scale_x.max(scale_y),
+    };
+
+    // the side that decided the scale keeps its size exactly
+    let scaled = |size: u32, target: u32, target_scale: f64| {
+        if target_scale == scale {
+            target
+        } else {
+            ((size as f64 * scale).round() as u32).max(1)
+        }
+    };
+    (
+        scaled(source_width, width, scale_x),
+        scaled(source_height, height, scale_y),
+    )
+}
+
+/// Resizes `source` to exactly `width` x `height` pixels.
+pub fn resize_exact(
+    processor: &Processor,
+    source: Arc<StorageImage<Format>>,
+    width: u32,
+    height: u32,
+    filter: ResizeFilter,
+) -> Arc<StorageImage<Format>> {
+    match filter {
+        ResizeFilter::Nearest => blit(processor, source, width, height, sampler::Filter::Nearest),
+        ResizeFilter::Linear => blit(processor, source, width, height, sampler::Filter::Linear),
+        ResizeFilter::Bicubic => resample(processor, source, width, height, 0),
+        ResizeFilter::Lanczos => resample(processor, source, width, height, 1),
+    }
+}
+
+fn blit(
+    processor: &Processor,
+    source: Arc<StorageImage<Format>>,
+    width: u32,
+    height: u32,
+    filter: sampler::Filter,
+) -> Arc<StorageImage<Format>> {
+    let (source_width, source_height) = processor::size(&source);
+    let destination = processor.create_image(width, height);
+
+    let command_buffer = AutoCommandBufferBuilder::new(processor.device().clone(), processor.queue().family())
+        .unwrap()
+        .blit_image(
+            source,
+            [0, 0, 0],
+            [source_width as i32, source_height as i32, 1],
+            0,
+            0,
+            destination.clone(),
+            [0, 0, 0],
+            [width as i32, height as i32, 1],
+            0,
+            0,
+            1,
+            filter,
+        ).unwrap()
+        .build()
+        .unwrap();
+    processor.submit(command_buffer);
+
+    destination
+}
+
+/// Resamples the columns then the rows, like the `image` crate does, the
+/// `kernel` selects the filter of the shader.
+fn resample(
+    processor: &Processor,
+    source: Arc<StorageImage<Format>>,
+    width: u32,
+    height: u32,
+    kernel: u32,
+) -> Arc<StorageImage<Format>> {
+    let device = processor.device().clone();
+    let shader = shaders::resample::Shader::load(device.clone()).expect("failed to load shader module");
+    let pipeline = Arc::new(
+        ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+            .expect("failed to create compute pipeline"),
+    );
+
+    let (source_width, _) = processor::size(&source);
+    let passes = [(1, processor.create_image(source_width, height)), (0, processor.create_image(width, height))];
+
+    let mut image = source;
+    for &(axis, ref destination) in passes.iter() {
+        let set = Arc::new(
+            PersistentDescriptorSet::start(pipeline.clone(), 0)
+                .add_image(image)
+                .unwrap()
+                .add_image(destination.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        let push_constants = shaders::resample::ty::PushConstantData { axis, kernel };
+        processor.dispatch(pipeline.clone(), set, processor::size(destination), push_constants);
+        image = destination.clone();
+    }
+
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stretch_keeps_the_requested_size() {
+        assert_eq!(scaled_size(100, 50, 30, 40, Fit::Stretch), (30, 40));
+    }
+
+    #[test]
+    fn contain_fits_inside() {
+        assert_eq!(scaled_size(200, 100, 100, 100, Fit::Contain), (100, 50));
+        assert_eq!(scaled_size(100, 200, 100, 100, Fit::Contain), (50, 100));
+        assert_eq!(scaled_size(50, 25, 100, 50, Fit::Contain), (100, 50));
+    }
+
+    #[test]
+    fn cover_fills_the_size() {
+        assert_eq!(scaled_size(200, 100, 100, 100, Fit::Cover), (200, 100));
+        assert_eq!(scaled_size(100, 200, 100, 100, Fit::Cover), (100, 200));
+        assert_eq!(scaled_size(3, 2, 100, 100, Fit::Cover), (150, 100));
+    }
+
+    #[test]
+    fn the_other_side_is_rounded() {
+        // 2 * 100 / 3 = 66.67
+        assert_eq!(scaled_size(3, 2, 100, 100, Fit::Contain), (100, 67));
+        // 3 * 2 / 5 = 1.2
+        assert_eq!(scaled_size(5, 3, 2, 2, Fit::Contain), (2, 1));
+        // 7 * 10 / 4 = 17.5, rounded away from zero
+        assert_eq!(scaled_size(4, 7, 10, 10, Fit::Cover), (10, 18));
+    }
+
+    #[test]
+    fn sides_keep_at_least_a_pixel() {
+        assert_eq!(scaled_size(1000, 1, 10, 10, Fit::Contain), (10, 1));
+        assert_eq!(scaled_size(1, 1000, 10, 10, Fit::Contain), (1, 10));
+    }
+}
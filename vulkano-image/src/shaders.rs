@@ -0,0 +1,659 @@
+pub mod depth {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout(set = 0, binding = 0) uniform sampler2D depth;
+
+layout(set = 0, binding = 1) buffer Values {
+    float values[];
+};
+
+layout(push_constant) uniform PushConstantData {
+    uvec2 image_size;
+} pc;
+
+// copies the depth of every texel to the buffer, the copy commands can't
+// read depth images
+void main() {
+    uvec2 position = gl_GlobalInvocationID.xy;
+    if (position.x >= pc.image_size.x || position.y >= pc.image_size.y) {
+        return;
+    }
+
+    values[position.y * pc.image_size.x + position.x] = texelFetch(depth, ivec2(position), 0).r;
+}
+"]
+    struct Dummy;
+}
+
+pub mod invert {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba8) uniform readonly image2D source;
+layout(set = 0, binding = 1, rgba8) uniform writeonly image2D destination;
+
+void main() {
+    ivec2 position = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(position, imageSize(source)))) {
+        return;
+    }
+
+    vec4 color = imageLoad(source, position);
+    imageStore(destination, position, vec4(1.0 - color.rgb, color.a));
+}
+"]
+    struct Dummy;
+}
+
+pub mod grayscale {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba8) uniform readonly image2D source;
+layout(set = 0, binding = 1, rgba8) uniform writeonly image2D destination;
+
+void main() {
+    ivec2 position = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(position, imageSize(source)))) {
+        return;
+    }
+
+    // Rec. 709 luma
+    vec4 color = imageLoad(source, position);
+    float luma = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    imageStore(destination, position, vec4(vec3(luma), color.a));
+}
+"]
+    struct Dummy;
+}
+
+pub mod convolution {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba8) uniform readonly image2D source;
+layout(set = 0, binding = 1, rgba8) uniform writeonly image2D destination;
+
+// row by row, applied without flipping it
+layout(set = 0, binding = 2) readonly buffer Kernel {
+    float weights[];
+};
+
+layout(push_constant) uniform PushConstantData {
+    ivec2 kernel_size;
+    float bias;
+} pc;
+
+void main() {
+    ivec2 position = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(source);
+    if (any(greaterThanEqual(position, size))) {
+        return;
+    }
+
+    // the pixels outside of the image repeat the ones on its edges
+    ivec2 radius = pc.kernel_size / 2;
+    vec3 sum = vec3(0.0);
+    for (int y = 0; y < pc.kernel_size.y; y++) {
+        for (int x = 0; x < pc.kernel_size.x; x++) {
+            ivec2 neighbour = clamp(position + ivec2(x, y) - radius, ivec2(0), size - 1);
+            sum += weights[y * pc.kernel_size.x + x] * imageLoad(source, neighbour).rgb;
+        }
+    }
+
+    float alpha = imageLoad(source, position).a;
+    imageStore(destination, position, vec4(sum + pc.bias, alpha));
+}
+"]
+    struct Dummy;
+}
+
+pub mod gradient {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba8) uniform readonly image2D source;
+layout(set = 0, binding = 1, rgba8) uniform writeonly image2D destination;
+
+// the derivatives are smoothed across with the weights (side, center, side),
+// 1 and 2 for Sobel or 3 and 10 for Scharr
+layout(push_constant) uniform PushConstantData {
+    float side;
+    float center;
+} pc;
+
+float luma(ivec2 position) {
+    ivec2 clamped = clamp(position, ivec2(0), imageSize(source) - 1);
+    return dot(imageLoad(source, clamped).rgb, vec3(0.2126, 0.7152, 0.0722));
+}
+
+void main() {
+    ivec2 position = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(position, imageSize(source)))) {
+        return;
+    }
+
+    float weights[3] = float[](pc.side, pc.center, pc.side);
+    vec2 gradient = vec2(0.0);
+    for (int i = 0; i < 3; i++) {
+        gradient.x += weights[i] * (luma(position + ivec2(1, i - 1)) - luma(position + ivec2(-1, i - 1)));
+        gradient.y += weights[i] * (luma(position + ivec2(i - 1, 1)) - luma(position + ivec2(i - 1, -1)));
+    }
+
+    // a sharp edge between black and white has a magnitude of 1
+    float magnitude = length(gradient) / (2.0 * pc.side + pc.center);
+    float alpha = imageLoad(source, position).a;
+    imageStore(destination, position, vec4(vec3(magnitude), alpha));
+}
+"]
+    struct Dummy;
+}
+
+pub mod unsharp {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba8) uniform readonly image2D source;
+layout(set = 0, binding = 1, rgba8) uniform writeonly image2D destination;
+layout(set = 0, binding = 2, rgba8) uniform readonly image2D blurred;
+
+layout(push_constant) uniform PushConstantData {
+    float amount;
+} pc;
+
+void main() {
+    ivec2 position = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(position, imageSize(source)))) {
+        return;
+    }
+
+    // adds back the details removed by the blur
+    vec4 color = imageLoad(source, position);
+    vec3 details = color.rgb - imageLoad(blurred, position).rgb;
+    imageStore(destination, position, vec4(color.rgb + pc.amount * details, color.a));
+}
+"]
+    struct Dummy;
+}
+
+pub mod downsample {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba8) uniform readonly image2D source;
+layout(set = 0, binding = 1, rgba8) uniform writeonly image2D destination;
+
+// averages the source texels covered by each destination pixel, weighted by
+// how much of them is covered when the source size isn't even
+void main() {
+    ivec2 position = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(destination);
+    if (any(greaterThanEqual(position, size))) {
+        return;
+    }
+
+    ivec2 source_size = imageSize(source);
+    vec2 ratio = vec2(source_size) / vec2(size);
+    vec2 start = vec2(position) * ratio;
+    vec2 end = start + ratio;
+
+    ivec2 first = ivec2(floor(start));
+    ivec2 last = min(ivec2(ceil(end)), source_size);
+
+    vec4 sum = vec4(0.0);
+    for (int y = first.y; y < last.y; y++) {
+        float weight_y = min(end.y, float(y + 1)) - max(start.y, float(y));
+        for (int x = first.x; x < last.x; x++) {
+            float weight_x = min(end.x, float(x + 1)) - max(start.x, float(x));
+            sum += weight_x * weight_y * imageLoad(source, ivec2(x, y));
+        }
+    }
+
+    imageStore(destination, position, sum / (ratio.x * ratio.y));
+}
+"]
+    struct Dummy;
+}
+
+pub mod resample {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba8) uniform readonly image2D source;
+layout(set = 0, binding = 1, rgba8) uniform writeonly image2D destination;
+
+// resizes along a single axis, 0 for x and 1 for y, the other one keeps the
+// size of the source; the kernel is 0 for Catmull-Rom and 1 for Lanczos 3
+layout(push_constant) uniform PushConstantData {
+    uint axis;
+    uint kernel;
+} pc;
+
+const float PI = 3.14159265358979;
+
+float catmull_rom(float x) {
+    x = abs(x);
+    if (x < 1.0) {
+        return (1.5 * x - 2.5) * x * x + 1.0;
+    }
+    if (x < 2.0) {
+        return ((-0.5 * x + 2.5) * x - 4.0) * x + 2.0;
+    }
+    return 0.0;
+}
+
+float sinc(float x) {
+    if (x == 0.0) {
+        return 1.0;
+    }
+    x *= PI;
+    return sin(x) / x;
+}
+
+float lanczos3(float x) {
+    return abs(x) < 3.0 ? sinc(x) * sinc(x / 3.0) : 0.0;
+}
+
+void main() {
+    ivec2 position = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(position, imageSize(destination)))) {
+        return;
+    }
+
+    int source_size = imageSize(source)[pc.axis];
+    float ratio = float(source_size) / float(imageSize(destination)[pc.axis]);
+
+    // when shrinking, the kernel is stretched to cover all the source texels
+    float scale = max(ratio, 1.0);
+    float support = (pc.kernel == 0 ? 2.0 : 3.0) * scale;
+
+    float center = (float(position[pc.axis]) + 0.5) * ratio;
+    int first = clamp(int(floor(center - support)), 0, source_size - 1);
+    int last = clamp(int(ceil(center + support)), 0, source_size - 1);
+    center -= 0.5;
+
+    vec4 sum = vec4(0.0);
+    float total = 0.0;
+    for (int i = first; i <= last; i++) {
+        float x = (float(i) - center) / scale;
+        float weight = pc.kernel == 0 ? catmull_rom(x) : lanczos3(x);
+
+        ivec2 texel = position;
+        texel[pc.axis] = i;
+        sum += weight * imageLoad(source, texel);
+        total += weight;
+    }
+
+    imageStore(destination, position, sum / total);
+}
+"]
+    struct Dummy;
+}
+
+pub mod histogram {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba8) uniform readonly image2D source;
+
+// 4 x 256 bins per tile, for the red, green, blue and luma channels
+layout(set = 0, binding = 1) buffer Bins {
+    uint bins[];
+};
+
+layout(push_constant) uniform PushConstantData {
+    ivec2 tiles;
+    // number of blocks each tile is split in
+    ivec2 blocks;
+} pc;
+
+const int BLOCK_SIZE = 64;
+
+shared uint local_bins[4 * 256];
+
+// each group counts the pixels of a block of a tile in shared memory first,
+// then adds its counts to the ones of the tile
+void main() {
+    uint index = gl_LocalInvocationIndex;
+    for (uint channel = 0; channel < 4; channel++) {
+        local_bins[channel * 256 + index] = 0;
+    }
+    memoryBarrierShared();
+    barrier();
+
+    ivec2 size = imageSize(source);
+    ivec2 group = ivec2(gl_WorkGroupID.xy);
+    ivec2 tile = group / pc.blocks;
+    ivec2 tile_end = (tile + 1) * size / pc.tiles;
+    ivec2 block_start = tile * size / pc.tiles + (group % pc.blocks) * BLOCK_SIZE;
+    ivec2 block_end = min(block_start + BLOCK_SIZE, tile_end);
+
+    ivec2 local = ivec2(gl_LocalInvocationID.xy);
+    for (int y = block_start.y + local.y; y < block_end.y; y += 16) {
+        for (int x = block_start.x + local.x; x < block_end.x; x += 16) {
+            uvec3 rgb = uvec3(round(imageLoad(source, ivec2(x, y)).rgb * 255.0));
+            uint luma = (77u * rgb.r + 150u * rgb.g + 29u * rgb.b + 128u) >> 8u;
+
+            atomicAdd(local_bins[rgb.r], 1u);
+            atomicAdd(local_bins[256 + rgb.g], 1u);
+            atomicAdd(local_bins[512 + rgb.b], 1u);
+            atomicAdd(local_bins[768 + luma], 1u);
+        }
+    }
+    memoryBarrierShared();
+    barrier();
+
+    uint offset = uint(tile.y * pc.tiles.x + tile.x) * 4 * 256;
+    for (uint channel = 0; channel < 4; channel++) {
+        uint count = local_bins[channel * 256 + index];
+        if (count != 0) {
+            atomicAdd(bins[offset + channel * 256 + index], count);
+        }
+    }
+}
+"]
+    struct Dummy;
+}
+
+pub mod equalize {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba8) uniform readonly image2D source;
+layout(set = 0, binding = 1, rgba8) uniform writeonly image2D destination;
+
+// 256 new lumas per tile
+layout(set = 0, binding = 2) buffer Luts {
+    float luts[];
+};
+
+layout(push_constant) uniform PushConstantData {
+    ivec2 tiles;
+} pc;
+
+float lut(ivec2 tile, uint luma) {
+    return luts[(tile.y * pc.tiles.x + tile.x) * 256 + luma];
+}
+
+// maps the luma of each pixel with the tables of the 4 closest tiles,
+// weighted by the distance to their centers, and keeps the chroma
+void main() {
+    ivec2 position = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(source);
+    if (any(greaterThanEqual(position, size))) {
+        return;
+    }
+
+    vec4 color = imageLoad(source, position);
+    uvec3 rgb = uvec3(round(color.rgb * 255.0));
+    uint luma = (77u * rgb.r + 150u * rgb.g + 29u * rgb.b + 128u) >> 8u;
+
+    vec2 tile = (vec2(position) + 0.5) * vec2(pc.tiles) / vec2(size) - 0.5;
+    ivec2 tile0 = clamp(ivec2(floor(tile)), ivec2(0), pc.tiles - 1);
+    ivec2 tile1 = min(tile0 + 1, pc.tiles - 1);
+    vec2 weight = clamp(tile - vec2(tile0), 0.0, 1.0);
+
+    float top = mix(lut(tile0, luma), lut(ivec2(tile1.x, tile0.y), luma), weight.x);
+    float bottom = mix(lut(ivec2(tile0.x, tile1.y), luma), lut(tile1, luma), weight.x);
+    float y = mix(top, bottom, weight.y);
+
+    // full range YCbCr, as in JPEG files
+    float cb = dot(color.rgb, vec3(-0.168736, -0.331264, 0.5));
+    float cr = dot(color.rgb, vec3(0.5, -0.418688, -0.081312));
+    vec3 equalized = vec3(y + 1.402 * cr, y - 0.344136 * cb - 0.714136 * cr, y + 1.772 * cb);
+
+    imageStore(destination, position, vec4(clamp(equalized, 0.0, 1.0), color.a));
+}
+"]
+    struct Dummy;
+}
+
+pub mod block_compress {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba8) uniform readonly image2D source;
+
+// 2 words per BC1 block and 4 per BC3 block, row by row
+layout(set = 0, binding = 1) buffer Blocks {
+    uint words[];
+};
+
+layout(push_constant) uniform PushConstantData {
+    uint bc3;
+} pc;
+
+vec4 texels[16];
+
+uint to_565(vec3 color) {
+    uvec3 q = uvec3(round(clamp(color, 0.0, 1.0) * vec3(31.0, 63.0, 31.0)));
+    return (q.r << 11) | (q.g << 5) | q.b;
+}
+
+vec3 from_565(uint color) {
+    return vec3((color >> 11) & 31u, (color >> 5) & 63u, color & 31u) / vec3(31.0, 63.0, 31.0);
+}
+
+// the colours go from one end of their principal axis to the other, the
+// transparent texels of BC1 get index 3 of the 3 colour mode
+uvec2 encode_color(bool punch_through) {
+    vec3 mean = vec3(0.0);
+    float count = 0.0;
+    for (int i = 0; i < 16; i++) {
+        if (!punch_through || texels[i].a >= 0.5) {
+            mean += texels[i].rgb;
+            count += 1.0;
+        }
+    }
+    if (count == 0.0) {
+        // both endpoints black, every texel transparent
+        return uvec2(0u, 0xFFFFFFFFu);
+    }
+    mean /= count;
+
+    mat3 covariance = mat3(0.0);
+    for (int i = 0; i < 16; i++) {
+        if (!punch_through || texels[i].a >= 0.5) {
+            vec3 d = texels[i].rgb - mean;
+            covariance += outerProduct(d, d);
+        }
+    }
+
+    vec3 axis = vec3(0.57735);
+    for (int i = 0; i < 8; i++) {
+        vec3 next = covariance * axis;
+        if (dot(next, next) < 1e-12) {
+            break;
+        }
+        axis = normalize(next);
+    }
+
+    float low = 0.0;
+    float high = 0.0;
+    for (int i = 0; i < 16; i++) {
+        if (!punch_through || texels[i].a >= 0.5) {
+            float t = dot(texels[i].rgb - mean, axis);
+            low = min(low, t);
+            high = max(high, t);
+        }
+    }
+
+    uint color0 = to_565(mean + axis * high);
+    uint color1 = to_565(mean + axis * low);
+
+    // BC1 decoders pick the 4 colour mode when color0 > color1, BC3 always
+    // uses it
+    bool three_colors;
+    if (pc.bc3 != 0) {
+        three_colors = false;
+    } else {
+        bool transparent = false;
+        for (int i = 0; i < 16; i++) {
+            transparent = transparent || texels[i].a < 0.5;
+        }
+        if ((color0 < color1) != transparent) {
+            uint swap = color0;
+            color0 = color1;
+            color1 = swap;
+        }
+        three_colors = color0 <= color1;
+    }
+
+    vec3 palette[4];
+    palette[0] = from_565(color0);
+    palette[1] = from_565(color1);
+    if (three_colors) {
+        palette[2] = (palette[0] + palette[1]) / 2.0;
+        palette[3] = vec3(0.0);
+    } else {
+        palette[2] = (2.0 * palette[0] + palette[1]) / 3.0;
+        palette[3] = (palette[0] + 2.0 * palette[1]) / 3.0;
+    }
+
+    uint indices = 0u;
+    for (int i = 0; i < 16; i++) {
+        uint index = 0u;
+        if (three_colors && punch_through && texels[i].a < 0.5) {
+            index = 3u;
+        } else {
+            float best = 1e30;
+            for (uint candidate = 0u; candidate < (three_colors ? 3u : 4u); candidate++) {
+                vec3 d = texels[i].rgb - palette[candidate];
+                if (dot(d, d) < best) {
+                    best = dot(d, d);
+                    index = candidate;
+                }
+            }
+        }
+        indices |= index << (2 * i);
+    }
+
+    return uvec2(color0 | (color1 << 16), indices);
+}
+
+// 8 alphas between the largest and the smallest one
+uvec2 encode_alpha() {
+    uint high = 0u;
+    uint low = 255u;
+    uint alphas[16];
+    for (int i = 0; i < 16; i++) {
+        alphas[i] = uint(round(texels[i].a * 255.0));
+        high = max(high, alphas[i]);
+        low = min(low, alphas[i]);
+    }
+
+    uvec2 block = uvec2(high | (low << 8), 0u);
+    if (high == low) {
+        return block;
+    }
+
+    uint palette[8];
+    palette[0] = high;
+    palette[1] = low;
+    for (uint i = 2u; i < 8u; i++) {
+        palette[i] = ((8u - i) * high + (i - 1u) * low + 3u) / 7u;
+    }
+
+    for (int i = 0; i < 16; i++) {
+        uint index = 0u;
+        uint best = 256u;
+        for (uint candidate = 0u; candidate < 8u; candidate++) {
+            uint error = uint(abs(int(alphas[i]) - int(palette[candidate])));
+            if (error < best) {
+                best = error;
+                index = candidate;
+            }
+        }
+
+        // the 48 bits of indices start at bit 16 of the block
+        uint bit = 16u + 3u * uint(i);
+        if (bit < 32u) {
+            block.x |= index << bit;
+            if (bit > 29u) {
+                block.y |= index >> (32u - bit);
+            }
+        } else {
+            block.y |= index << (bit - 32u);
+        }
+    }
+
+    return block;
+}
+
+// each invocation encodes a block of 4 x 4 texels, the ones past the edges
+// of the image repeat the last row and column
+void main() {
+    ivec2 size = imageSize(source);
+    ivec2 blocks = (size + 3) / 4;
+    ivec2 block = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(block, blocks))) {
+        return;
+    }
+
+    for (int i = 0; i < 16; i++) {
+        ivec2 position = min(block * 4 + ivec2(i % 4, i / 4), size - 1);
+        texels[i] = imageLoad(source, position);
+    }
+
+    uint index = uint(block.y * blocks.x + block.x);
+    if (pc.bc3 != 0) {
+        uvec2 alpha = encode_alpha();
+        uvec2 color = encode_color(false);
+        words[index * 4] = alpha.x;
+        words[index * 4 + 1] = alpha.y;
+        words[index * 4 + 2] = color.x;
+        words[index * 4 + 3] = color.y;
+    } else {
+        uvec2 color = encode_color(true);
+        words[index * 2] = color.x;
+        words[index * 2 + 1] = color.y;
+    }
+}
+"]
+    struct Dummy;
+}
@@ -0,0 +1,282 @@
+use image::DynamicImage;
+
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::CpuAccessibleBuffer;
+
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+
+use vulkano::format::Format;
+
+use vulkano::image::Dimensions;
+use vulkano::image::ImageLayout;
+use vulkano::image::ImageUsage;
+use vulkano::image::ImmutableImage;
+use vulkano::image::MipmapsCount;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use compress;
+use compress::{BlockFormat, Compressed};
+use dds;
+use formats::PixelFormat;
+use ktx2;
+use processor::Processor;
+
+/// The formats of the texels of a texture file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureFormat {
+    Pixels(PixelFormat),
+    /// Compressed blocks, whose colours are encoded to sRGB when `srgb` is
+    /// set, the alpha being linear either way. BC1 without `alpha` reads its
+    /// transparent texels as opaque black, BC3 always has it.
+    Blocks { format: BlockFormat, srgb: bool, alpha: bool },
+}
+
+impl TextureFormat {
+    pub fn format(&self) -> Format {
+        match *self {
+            TextureFormat::Pixels(format) => format.format(),
+            TextureFormat::Blocks { format, srgb, alpha } => match (format, srgb, alpha) {
+                (BlockFormat::Bc1, false, false) => Format::BC1_RGBUnormBlock,
+                (BlockFormat::Bc1, true, false) => Format::BC1_RGBSrgbBlock,
+                (BlockFormat::Bc1, false, true) => Format::BC1_RGBAUnormBlock,
+                (BlockFormat::Bc1, true, true) => Format::BC1_RGBASrgbBlock,
+                (BlockFormat::Bc3, false, _) => Format::BC3UnormBlock,
+                (BlockFormat::Bc3, true, _) => Format::BC3SrgbBlock,
+            },
+        }
+    }
+
+    /// Bytes of a single image of `width` x `height` texels, `None` when it
+    /// is too big to be addressed.
+    pub fn image_bytes(&self, width: u32, height: u32) -> Option<usize> {
+        match *self {
+            TextureFormat::Pixels(format) => (width as usize)
+                .checked_mul(height as usize)?
+                .checked_mul(format.texel_size()),
+            TextureFormat::Blocks { format, .. } => {
+                let (blocks_x, blocks_y) = compress::block_count(width, height);
+                (blocks_x as usize)
+                    .checked_mul(blocks_y as usize)?
+                    .checked_mul(format.block_bytes())
+            }
+        }
+    }
+
+    /// Converts a single image to the pixels of the `image` crate, decoding
+    /// the blocks on the CPU.
+    pub fn to_image(&self, width: u32, height: u32, data: &[u8]) -> DynamicImage {
+        match *self {
+            TextureFormat::Pixels(format) => format.to_image(width, height, data),
+            TextureFormat::Blocks { format, alpha, .. } => {
+                let compressed = Compressed {
+                    format,
+                    width,
+                    height,
+                    data: data.to_vec(),
+                };
+                let mut image = compressed.decode();
+                if !alpha {
+                    for pixel in image.pixels_mut() {
+                        pixel[3] = 255;
+                    }
+                }
+                DynamicImage::ImageRgba8(image)
+            }
+        }
+    }
+}
+
+/// A texture read from a KTX2 or DDS file, with all its mipmap levels.
+///
+/// Each level holds the images of every array layer one after the other,
+/// each layer holding the 6 faces of a cube map or the slices of a volume,
+/// which is how a whole level is copied to an image.
+pub struct Texture {
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Slices of a 3D texture, 1 otherwise.
+    pub depth: u32,
+    /// Array layers, 1 when the texture isn't an array.
+    pub layers: u32,
+    /// 6 for cube maps, 1 otherwise.
+    pub faces: u32,
+    /// Whether the texture is an array, possibly of a single layer.
+    pub array: bool,
+    /// Whether the texture has no height, as opposed to a height of 1.
+    pub one_dimensional: bool,
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl Texture {
+    /// Reads the file at `path`, telling KTX2 and DDS files apart from their
+    /// first bytes.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Texture, String> {
+        let path = path.as_ref();
+        let mut bytes = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+
+        let texture = if ktx2::is_ktx2(&bytes) {
+            ktx2::read(&bytes)
+        } else if dds::is_dds(&bytes) {
+            dds::read(&bytes)
+        } else {
+            Err("not a KTX2 nor a DDS file".to_owned())
+        };
+        texture.map_err(|err| format!("failed to load {}: {}", path.display(), err))
+    }
+
+    /// The kind of image the texture is loaded as.
+    pub fn dimensions(&self) -> Dimensions {
+        let (width, height, depth) = (self.width, self.height, self.depth);
+        let array_layers = self.layers;
+
+        match (self.faces, self.depth, self.array, self.one_dimensional) {
+            (6, _, false, _) => Dimensions::Cubemap { size: width },
+            (6, _, true, _) => Dimensions::CubemapArray { size: width, array_layers },
+            (_, 1, false, true) => Dimensions::Dim1d { width },
+            (_, 1, true, true) => Dimensions::Dim1dArray { width, array_layers },
+            (_, 1, false, false) => Dimensions::Dim2d { width, height },
+            (_, 1, true, false) => Dimensions::Dim2dArray { width, height, array_layers },
+            _ => Dimensions::Dim3d { width, height, depth },
+        }
+    }
+
+    /// Width, height and depth of `level`.
+    pub fn level_size(&self, level: u32) -> (u32, u32, u32) {
+        (
+            (self.width >> level).max(1),
+            (self.height >> level).max(1),
+            (self.depth >> level).max(1),
+        )
+    }
+
+    /// Checks the counts read from a file: `level_count` can't be more than
+    /// the levels of a full mipmap chain, down to a single texel, and the
+    /// images of a level must be countable.
+    pub fn check_counts(&self, level_count: u32) -> Result<(), String> {
+        let full_chain = 32 - self.width.max(self.height).max(self.depth).leading_zeros();
+        if level_count > full_chain {
+            return Err(format!(
+                "{} levels are more than the {} of a full mipmap chain",
+                level_count, full_chain
+            ));
+        }
+
+        // the first level has the most images
+        self.depth
+            .checked_mul(self.faces)
+            .and_then(|images| images.checked_mul(self.layers))
+            .map(|_| ())
+            .ok_or_else(|| format!("{} layers of {} faces are too many", self.layers, self.faces))
+    }
+
+    /// Number of 2D images of `level`: one per slice, face and layer.
+    pub fn image_count(&self, level: u32) -> u32 {
+        self.level_size(level).2 * self.faces * self.layers
+    }
+
+    /// Bytes of a level, `None` when it is too big to be addressed.
+    pub fn level_bytes(&self, level: u32) -> Option<usize> {
+        let (width, height, _) = self.level_size(level);
+        self.format
+            .image_bytes(width, height)?
+            .checked_mul(self.image_count(level) as usize)
+    }
+
+    /// The `index`-th 2D image of `level`.
+    pub fn image(&self, level: u32, index: u32) -> DynamicImage {
+        let (width, height, _) = self.level_size(level);
+        // the level was read, so its images can be addressed
+        let size = self.levels[level as usize].len() / self.image_count(level) as usize;
+        let start = index as usize * size;
+        self.format
+            .to_image(width, height, &self.levels[level as usize][start .. start + size])
+    }
+
+    /// Copies every level to a new immutable image.
+    pub fn upload(&self, processor: &Processor) -> Arc<ImmutableImage<Format>> {
+        let usage = ImageUsage {
+            transfer_source: true,
+            transfer_destination: true,
+            sampled: true,
+            ..ImageUsage::none()
+        };
+        let (image, initialization) = ImmutableImage::uninitialized(
+            processor.device().clone(),
+            self.dimensions(),
+            self.format.format(),
+            MipmapsCount::Specific(self.levels.len() as u32),
+            usage,
+            ImageLayout::ShaderReadOnlyOptimal,
+            Some(processor.queue().family()),
+        ).expect("failed to create the image");
+
+        // the initialization can only be used by a single command buffer
+        let initialization = Arc::new(initialization);
+        let mut builder = AutoCommandBufferBuilder::new(processor.device().clone(), processor.queue().family()).unwrap();
+        for (level, data) in self.levels.iter().enumerate() {
+            let staging = CpuAccessibleBuffer::from_iter(
+                processor.device().clone(),
+                BufferUsage::transfer_source(),
+                data.iter().cloned(),
+            ).expect("failed to create the buffer");
+
+            let (width, height, depth) = self.level_size(level as u32);
+            builder = builder
+                .copy_buffer_to_image_dimensions(
+                    staging,
+                    initialization.clone(),
+                    [0, 0, 0],
+                    [width, height, depth],
+                    0,
+                    self.layers * self.faces,
+                    level as u32,
+                ).unwrap();
+        }
+        processor.submit(builder.build().unwrap());
+
+        image
+    }
+
+    /// Copies every level of `image`, loaded from this texture, back from
+    /// the device.
+    pub fn read_back(&self, processor: &Processor, image: Arc<ImmutableImage<Format>>) -> Texture {
+        let levels = (0 .. self.levels.len() as u32)
+            .map(|level| {
+                let buffer = CpuAccessibleBuffer::from_iter(
+                    processor.device().clone(),
+                    BufferUsage::all(),
+                    (0 .. self.levels[level as usize].len()).map(|_| 0u8),
+                ).expect("failed to create the buffer");
+
+                let (width, height, depth) = self.level_size(level);
+                let command_buffer =
+                    AutoCommandBufferBuilder::new(processor.device().clone(), processor.queue().family())
+                        .unwrap()
+                        .copy_image_to_buffer_dimensions(
+                            image.clone(),
+                            buffer.clone(),
+                            [0, 0, 0],
+                            [width, height, depth],
+                            0,
+                            self.layers * self.faces,
+                            level,
+                        ).unwrap()
+                        .build()
+                        .unwrap();
+                processor.submit(command_buffer);
+
+                let content = buffer.read().unwrap();
+                content.to_vec()
+            }).collect();
+
+        Texture { levels, ..*self }
+    }
+}
@@ -0,0 +1,278 @@
+use image::imageops;
+use image::{FilterType, Rgba, RgbaImage};
+
+use golden;
+
+use compress::{BlockFormat, Compressed};
+use filters::Filter;
+use histogram::Histogram;
+use kernel::{Kernel, MAX_KERNEL_SIZE};
+use processor::Processor;
+use reference;
+use resize;
+use resize::ResizeFilter;
+
+// Sizes of the test images, small and odd so that the edges and the groups
+// of 16 x 16 pixels not fully inside the image are covered.
+const SIZES: [(u32, u32); 2] = [(17, 13), (40, 33)];
+
+// The GPU and the CPU may round the last bit differently, and the unsharp
+// mask amplifies the differences of its blurred image.
+const TOLERANCE: u8 = 2;
+
+// Smallest PSNR of the compressed gradients, in decibels, and largest error
+// of their alpha with BC3.
+const MIN_COMPRESSED_PSNR: f64 = 30.0;
+const COMPRESSED_ALPHA_TOLERANCE: u8 = 8;
+
+// The `image` crate truncates the colours instead of rounding them, after
+// each of its two passes.
+const RESIZE_TOLERANCE: u8 = 3;
+
+/// The filters checked when none are given on the command line.
+pub fn default_filters() -> Vec<Filter> {
+    let mut random = Random(7);
+    let weights = (0 .. MAX_KERNEL_SIZE * MAX_KERNEL_SIZE)
+        .map(|_| random.next() as f32 / 255.0)
+        .collect::<Vec<_>>();
+    let sum: f32 = weights.iter().sum();
+    let kernel = Kernel::new(
+        MAX_KERNEL_SIZE,
+        MAX_KERNEL_SIZE,
+        weights.iter().map(|weight| weight / sum).collect(),
+    ).unwrap();
+
+    vec![
+        Filter::Invert,
+        Filter::Grayscale,
+        Filter::Blur(1),
+        Filter::Blur(5),
+        Filter::BoxBlur(2),
+        Filter::Unsharp(2, 1.5),
+        Filter::Sharpen,
+        Filter::Emboss,
+        Filter::Sobel,
+        Filter::Scharr,
+        Filter::Kernel(Kernel::new(5, 3, (0 .. 15).map(|i| i as f32 / 60.0 - 0.05).collect()).unwrap()),
+        Filter::Kernel(kernel),
+    ]
+}
+
+/// Runs `filters` on small random images on both the GPU and the CPU and
+/// prints the largest difference for each one. Returns whether they all
+/// matched.
+pub fn run(processor: &Processor, filters: &[Filter]) -> bool {
+    let images = test_images();
+
+    let mut passed = true;
+    for filter in filters {
+        let difference = images
+            .iter()
+            .map(|image| {
+                let gpu = processor.download(filter.apply(processor, processor.upload(image)));
+                let cpu = filter.apply_cpu(image);
+                reference::max_difference(&gpu, &cpu)
+            }).max()
+            .unwrap_or(0);
+
+        let ok = difference <= TOLERANCE;
+        passed &= ok;
+        println!(
+            "{:<6} {:?}: max difference {}",
+            if ok { "ok" } else { "FAILED" },
+            filter,
+            difference
+        );
+    }
+
+    passed
+}
+
+/// Resizes small random images on the GPU and with the `image` crate and
+/// prints the largest difference for each filter. Returns whether they all
+/// matched.
+///
+/// The linear blit is only compared when enlarging, since it doesn't widen
+/// its filter to cover all the texels when shrinking like the `image` crate.
+pub fn run_resize(processor: &Processor) -> bool {
+    let images = test_images();
+    let cases = [
+        (ResizeFilter::Nearest, FilterType::Nearest, true),
+        (ResizeFilter::Linear, FilterType::Triangle, false),
+        (ResizeFilter::Bicubic, FilterType::CatmullRom, true),
+        (ResizeFilter::Lanczos, FilterType::Lanczos3, true),
+    ];
+
+    let mut passed = true;
+    for &(filter, image_filter, shrink) in cases.iter() {
+        for image in &images {
+            let (width, height) = image.dimensions();
+            let mut sizes = vec![(width * 2 + 3, height * 2 + 5)];
+            if shrink {
+                sizes.push((width * 2 / 3, height / 2));
+            }
+
+            for &(new_width, new_height) in &sizes {
+                let gpu = processor.download(resize::resize_exact(
+                    processor,
+                    processor.upload(image),
+                    new_width,
+                    new_height,
+                    filter,
+                ));
+                let cpu = imageops::resize(image, new_width, new_height, image_filter);
+                let difference = reference::max_difference(&gpu, &cpu);
+
+                let ok = difference <= RESIZE_TOLERANCE;
+                passed &= ok;
+                println!(
+                    "{:<6} {:?} {}x{} to {}x{}: max difference {}",
+                    if ok { "ok" } else { "FAILED" },
+                    filter,
+                    width,
+                    height,
+                    new_width,
+                    new_height,
+                    difference
+                );
+            }
+        }
+    }
+
+    passed
+}
+
+/// Counts the values of random images, whole and split in tiles, on the GPU
+/// and the CPU. Returns whether all the counts are the same.
+pub fn run_histogram(processor: &Processor) -> bool {
+    // one image spans several of the blocks counted by each work group
+    let mut images = test_images();
+    let mut random = Random(3);
+    images.push(RgbaImage::from_fn(150, 70, |_, _| random.pixel()));
+
+    let mut passed = true;
+    for image in &images {
+        for &tiles in &[(1, 1), (3, 2)] {
+            let gpu = Histogram::compute_tiles(processor, processor.upload(image), tiles);
+            let cpu = reference::histograms(image, tiles);
+
+            let ok = gpu == cpu;
+            passed &= ok;
+            println!(
+                "{:<6} histogram of {}x{} in {}x{} tiles",
+                if ok { "ok" } else { "FAILED" },
+                image.width(),
+                image.height(),
+                tiles.0,
+                tiles.1
+            );
+        }
+    }
+
+    passed
+}
+
+/// Compresses smooth gradients, of a size that isn't a multiple of the
+/// blocks, decodes them on the CPU and checks that they are close enough to
+/// the source. Random images can't be compressed well, but the gradients
+/// catch the endpoints or indices being misplaced.
+pub fn run_compress(processor: &Processor) -> bool {
+    let gradient = RgbaImage::from_fn(37, 21, |x, y| {
+        Rgba([(x * 7) as u8, (y * 12) as u8, (255 - x * 3 - y * 4) as u8, (x * 5 + y * 3) as u8])
+    });
+
+    let mut passed = true;
+    for &format in &[BlockFormat::Bc1, BlockFormat::Bc3] {
+        // BC1 only has punch-through alpha
+        let source = match format {
+            BlockFormat::Bc1 => RgbaImage::from_fn(37, 21, |x, y| {
+                let pixel = gradient.get_pixel(x, y);
+                Rgba([pixel[0], pixel[1], pixel[2], 255])
+            }),
+            BlockFormat::Bc3 => gradient.clone(),
+        };
+
+        let decoded = Compressed::encode(processor, processor.upload(&source), format).decode();
+        let comparison = golden::compare(&decoded, &source).unwrap();
+        let alpha_error = decoded
+            .pixels()
+            .zip(source.pixels())
+            .map(|(a, b)| if a[3] > b[3] { a[3] - b[3] } else { b[3] - a[3] })
+            .max()
+            .unwrap_or(0);
+
+        let ok = comparison.psnr >= MIN_COMPRESSED_PSNR && alpha_error <= COMPRESSED_ALPHA_TOLERANCE;
+        passed &= ok;
+        println!(
+            "{:<6} {:?}: {}, alpha max difference {}",
+            if ok { "ok" } else { "FAILED" },
+            format,
+            comparison,
+            alpha_error
+        );
+    }
+
+    passed
+}
+
+fn test_images() -> Vec<RgbaImage> {
+    let mut random = Random(1);
+    SIZES
+        .iter()
+        .map(|&(width, height)| RgbaImage::from_fn(width, height, |_, _| random.pixel()))
+        .collect()
+}
+
+/// Deterministic xorshift generator, so that every run checks the same images.
+struct Random(u32);
+
+impl Random {
+    fn next(&mut self) -> u8 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 24) as u8
+    }
+
+    fn pixel(&mut self) -> Rgba<u8> {
+        Rgba([self.next(), self.next(), self.next(), self.next()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core;
+
+    // The comparisons need a Vulkan device, they only run with
+    // `cargo test -- --ignored`.
+    fn processor() -> Processor {
+        let (device, mut queues) = core::init();
+        Processor::new(device, queues.next().unwrap())
+    }
+
+    #[test]
+    #[ignore]
+    fn filters_match_the_cpu() {
+        assert!(run(&processor(), &default_filters()));
+    }
+
+    #[test]
+    #[ignore]
+    fn resize_matches_the_image_crate() {
+        assert!(run_resize(&processor()));
+    }
+
+    #[test]
+    #[ignore]
+    fn histogram_matches_the_cpu() {
+        assert!(run_histogram(&processor()));
+    }
+
+    #[test]
+    #[ignore]
+    fn compression_is_close_to_the_source() {
+        assert!(run_compress(&processor()));
+    }
+}
@@ -4,6 +4,7 @@ extern crate vulkano_shader_derive;
 #[macro_use]
 extern crate vulkano;
 
+extern crate vulkano_graphical_pipeline;
 extern crate vulkano_win;
 extern crate winit;
 
@@ -23,6 +24,8 @@ use vulkano::swapchain::{
 use vulkano::sync::now;
 use vulkano::sync::GpuFuture;
 
+use vulkano_graphical_pipeline::color::{choose_format, is_srgb};
+
 use vulkano_win::VkSurfaceBuild;
 
 use winit::EventsLoop;
@@ -32,6 +35,9 @@ use std::sync::Arc;
 
 mod shaders;
 
+// The background colour, as picked in sRGB.
+const CLEAR_COLOR: [f32; 3] = [0.36, 0.23, 0.26];
+
 fn main() {
     let instance = create_instance();
     let physical = create_physical_device(&instance);
@@ -46,6 +52,22 @@ fn main() {
     let (mut swapchain, mut images) =
         create_swapchain(surface.clone(), physical, device.clone(), queue.clone());
 
+    // The shaders work on linear colours. An sRGB swapchain encodes them when they are written,
+    // otherwise the fragment shader has to do it, and the clear colour is given already encoded.
+    let encode_srgb = !is_srgb(swapchain.format());
+    let clear_color = if encode_srgb {
+        CLEAR_COLOR
+    } else {
+        [
+            srgb_to_linear(CLEAR_COLOR[0]),
+            srgb_to_linear(CLEAR_COLOR[1]),
+            srgb_to_linear(CLEAR_COLOR[2]),
+        ]
+    };
+    let push_constants = shaders::fs::ty::PushConstantData {
+        encode_srgb: encode_srgb as u32,
+    };
+
     // Get the new dimensions for the viewport/framebuffers.
     let mut dimensions = surface
         .capabilities(physical)
@@ -261,18 +283,18 @@ fn main() {
                 .begin_render_pass(
                     framebuffers.as_ref().unwrap()[image_num].clone(),
                     false,
-                    vec![[0.36, 0.23, 0.26, 1.0].into()],
+                    vec![[clear_color[0], clear_color[1], clear_color[2], 1.0].into()],
                 ).unwrap()
                 // We are now inside the first subpass of the render pass. We add a draw command.
                 //
                 // The last two parameters contain the list of resources to pass to the shaders.
-                // Since we used an `EmptyPipeline` object, the objects have to be `()`.
+                // There are no descriptor sets, only the push constants of the fragment shader.
                 .draw(
                     pipeline.clone(),
                     &dynamic_state,
                     vertex_buffer.clone(),
                     (),
-                    (),
+                    push_constants,
                 ).unwrap()
                 // We leave the render pass by calling `draw_end`. Note that if we had multiple
                 // subpasses we could have called `next_inline` (or `next_secondary`) to jump to the
@@ -398,7 +420,7 @@ fn create_swapchain<W>(
 
     let dimensions = caps.current_extent.unwrap_or([1280, 1024]);
     let alpha = caps.supported_composite_alpha.iter().next().unwrap();
-    let format = caps.supported_formats[0].0;
+    let format = choose_format(&caps.supported_formats);
 
     // setup swapchain
     let (swapchain, images) = Swapchain::new(
@@ -419,3 +441,11 @@ fn create_swapchain<W>(
 
     (swapchain, images)
 }
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
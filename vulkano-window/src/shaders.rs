@@ -21,8 +21,30 @@ pub mod fs {
 
 layout(location = 0) out vec4 f_color;
 
+// set when the swapchain images don't encode the colours to sRGB themselves
+layout(push_constant) uniform PushConstantData {
+    uint encode_srgb;
+} pc;
+
+// the colour of the triangle, as picked in sRGB
+const vec3 COLOR = vec3(0.99, 0.82, 0.63);
+
+vec3 srgb_to_linear(vec3 c) {
+    return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+}
+
+vec3 linear_to_srgb(vec3 c) {
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+}
+
 void main() {
-    f_color = vec4(0.99, 0.82, 0.63, 1.0);
+    // the shading happens on linear values
+    vec3 color = srgb_to_linear(COLOR);
+
+    if (pc.encode_srgb != 0) {
+        color = linear_to_srgb(color);
+    }
+    f_color = vec4(color, 1.0);
 }
 "]
     struct Dummy;